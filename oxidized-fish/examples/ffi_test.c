@@ -0,0 +1,81 @@
+/*
+ * Exercises the C API against the static library:
+ *
+ *   cargo build --release --lib
+ *   cc -Iinclude examples/ffi_test.c target/release/liboxidized_fish.a \
+ *      -lpthread -ldl -lm -o ffi_test && ./ffi_test
+ */
+
+#include "oxidized_fish.h"
+
+#include <stdio.h>
+#include <string.h>
+
+static int failures = 0;
+
+#define CHECK(cond)                                                   \
+    do {                                                              \
+        if (!(cond)) {                                                \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,    \
+                    __LINE__, #cond);                                 \
+            failures++;                                               \
+        }                                                             \
+    } while (0)
+
+static void on_info(const OxInfo *info, void *user_data) {
+    int *count = user_data;
+    (*count)++;
+    printf("info depth %u multipv %u score cp %d mate %d nodes %llu time %llu pv %s\n",
+           info->depth, info->multipv, info->score_cp, info->mate,
+           (unsigned long long)info->nodes, (unsigned long long)info->time_ms,
+           info->pv);
+}
+
+int main(void) {
+    char best[8];
+    int info_count = 0;
+
+    CHECK(ox_api_version() == 1);
+    printf("%s\n", ox_version());
+
+    OxEngine *engine = ox_engine_new(16);
+    CHECK(engine != NULL);
+
+    /* Errors leave the position alone */
+    CHECK(ox_engine_set_position(engine, "not a fen", NULL, 0) == OX_STATUS_INVALID_FEN);
+    const char *illegal[] = {"e2e5"};
+    CHECK(ox_engine_set_position(engine, NULL, illegal, 1) == OX_STATUS_ILLEGAL_MOVE);
+    CHECK(ox_engine_best_move(engine, best, sizeof best) == OX_STATUS_NO_MOVE);
+
+    /* Fool's mate in one for black */
+    const char *moves[] = {"f2f3", "e7e5", "g2g4"};
+    CHECK(ox_engine_set_position(engine, NULL, moves, 3) == OX_STATUS_OK);
+    OxLimits limits = {.depth = 4};
+    CHECK(ox_engine_start_search(engine, &limits, on_info, &info_count) == OX_STATUS_OK);
+    CHECK(ox_engine_set_position(engine, NULL, NULL, 0) == OX_STATUS_BUSY);
+    CHECK(ox_engine_best_move(engine, best, sizeof best) == OX_STATUS_OK);
+    CHECK(strcmp(best, "d8h4") == 0);
+    CHECK(info_count > 0);
+    CHECK(!ox_engine_is_searching(engine));
+
+    /* A search without limits runs until stopped */
+    CHECK(ox_engine_set_position(engine,
+                                 "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+                                 NULL, 0) == OX_STATUS_OK);
+    CHECK(ox_engine_start_search(engine, NULL, NULL, NULL) == OX_STATUS_OK);
+    CHECK(ox_engine_is_searching(engine));
+    ox_engine_stop(engine);
+    CHECK(!ox_engine_is_searching(engine));
+    CHECK(ox_engine_best_move(engine, best, sizeof best) == OX_STATUS_OK);
+    CHECK(strlen(best) >= 4);
+    CHECK(ox_engine_best_move(engine, best, 4) == OX_STATUS_INVALID_ARGUMENT);
+
+    ox_engine_free(engine);
+
+    if (failures) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return 1;
+    }
+    printf("ok\n");
+    return 0;
+}
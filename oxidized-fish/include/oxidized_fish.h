@@ -0,0 +1,116 @@
+#ifndef OXIDIZED_FISH_H
+#define OXIDIZED_FISH_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+typedef enum OxStatus {
+  OX_STATUS_OK = 0,
+  OX_STATUS_INVALID_ARGUMENT = 1,
+  OX_STATUS_INVALID_FEN = 2,
+  OX_STATUS_ILLEGAL_MOVE = 3,
+  OX_STATUS_BUSY = 4,
+  OX_STATUS_NO_MOVE = 5,
+} OxStatus;
+
+// An engine with its own hash table and position.
+typedef struct OxEngine OxEngine;
+
+// Search limits, where 0 means no limit. A search without any runs until
+// `ox_engine_stop`.
+typedef struct OxLimits {
+  uint32_t depth;
+  uint64_t movetime_ms;
+  uint64_t nodes;
+  uint32_t multipv;
+} OxLimits;
+
+// Progress of a search, only valid during the callback.
+typedef struct OxInfo {
+  uint32_t depth;
+  uint32_t multipv;
+  int32_t score_cp;
+  int32_t mate;
+  uint64_t nodes;
+  uint64_t time_ms;
+  const char *pv;
+} OxInfo;
+
+typedef void (*OxInfoCallback)(const struct OxInfo *info, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The version of this API, for checking against the header the caller was
+// built with.
+uint32_t ox_api_version(void);
+
+// The engine version as a NUL-terminated string.
+const char *ox_version(void);
+
+// A new engine at the start position with a `hash_mb` hash table, or the
+// default size for 0. Free it with `ox_engine_free`.
+struct OxEngine *ox_engine_new(size_t hash_mb);
+
+// Stops any search and frees the engine.
+//
+// # Safety
+// `engine` must come from `ox_engine_new` and not be used afterwards.
+void ox_engine_free(struct OxEngine *engine);
+
+// Sets the position from a FEN, or the start position when `fen` is NULL,
+// followed by `move_count` moves in UCI notation. The position is left
+// unchanged on error.
+//
+// # Safety
+// `engine` must be live, `fen` NULL or NUL-terminated, and `moves` must
+// point to `move_count` NUL-terminated strings.
+enum OxStatus ox_engine_set_position(struct OxEngine *engine,
+                                     const char *fen,
+                                     const char *const *moves,
+                                     size_t move_count);
+
+// Starts searching the current position and returns at once. `callback`
+// may be NULL; it is called on the search thread with `user_data`.
+//
+// # Safety
+// `engine` must be live and `limits` NULL (no limits) or valid.
+enum OxStatus ox_engine_start_search(struct OxEngine *engine,
+                                     const struct OxLimits *limits,
+                                     OxInfoCallback callback,
+                                     void *user_data);
+
+// Stops the search and returns once it has ended.
+//
+// # Safety
+// `engine` must be live.
+void ox_engine_stop(struct OxEngine *engine);
+
+// Returns once the search has ended by its limits.
+//
+// # Safety
+// `engine` must be live.
+void ox_engine_wait(struct OxEngine *engine);
+
+// Whether a search is still running.
+//
+// # Safety
+// `engine` must be live.
+bool ox_engine_is_searching(const struct OxEngine *engine);
+
+// Waits for the search and writes its best move in UCI notation into
+// `buf`, which needs room for 6 bytes.
+//
+// # Safety
+// `engine` must be live and `buf` point to `len` writable bytes.
+enum OxStatus ox_engine_best_move(struct OxEngine *engine, char *buf, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* OXIDIZED_FISH_H */
@@ -0,0 +1,269 @@
+//! `annotate <games.pgn>`: analyses every position of every game and writes
+//! the games back with the evaluation after each move. Moves are judged by
+//! how much of the mover's expected score they give away by the WDL model:
+//! inaccuracies, mistakes and blunders get their NAG, a comment and the
+//! better line. Accuracy per player is printed at the end.
+
+use crate::board::Board;
+use crate::game::{GameResult, GameState};
+use crate::pgn::{self, Annotation, PgnGame};
+use crate::search::{Search, MAX_PLY};
+use crate::types::{Color, Move, MATE_VALUE};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Duration;
+
+const DEFAULT_DEPTH: u8 = 12;
+const DEFAULT_HASH: usize = 64;
+const VARIATION_LENGTH: usize = 6;
+
+/// Expected score lost, out of 1, for each NAG from the worst down.
+const JUDGEMENTS: [(f64, u8, &str); 3] = [
+    (0.15, 4, "Blunder"),
+    (0.10, 2, "Mistake"),
+    (0.05, 6, "Inaccuracy"),
+];
+
+/// Centipawn losses are capped here so a missed mate doesn't swamp the average.
+const MAX_CP_LOSS: i32 = 1000;
+
+struct Config {
+    input: String,
+    output: Option<String>,
+    depth: u8,
+    movetime: Option<Duration>,
+    hash: usize,
+}
+
+/// One player's moves in one or more games.
+#[derive(Default)]
+struct Stats {
+    moves: usize,
+    accuracy: f64, // Summed over moves
+    cp_loss: i64,
+    counts: [usize; 3], // As in `JUDGEMENTS`
+}
+
+impl Stats {
+    fn add(&mut self, other: &Stats) {
+        self.moves += other.moves;
+        self.accuracy += other.accuracy;
+        self.cp_loss += other.cp_loss;
+        for (c, o) in self.counts.iter_mut().zip(other.counts) {
+            *c += o;
+        }
+    }
+
+    fn summary(&self) -> String {
+        let moves = self.moves.max(1);
+        format!(
+            "accuracy {:.1}%, average centipawn loss {}, {} inaccuracies, {} mistakes, {} blunders",
+            self.accuracy / moves as f64,
+            self.cp_loss / moves as i64,
+            self.counts[2],
+            self.counts[1],
+            self.counts[0]
+        )
+    }
+}
+
+pub fn run(args: &[String]) {
+    let Some(config) = parse_args(args) else {
+        eprintln!(
+            "Usage: annotate <games.pgn> [--output <file.pgn>] [--depth <plies>] \
+             [--movetime <ms>] [--hash <MB>]"
+        );
+        return;
+    };
+    let text = match std::fs::read_to_string(&config.input) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Error: Failed to read {}: {}", config.input, e);
+            return;
+        }
+    };
+    let mut out: Box<dyn Write> = match &config.output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(e) => {
+                eprintln!("Error: Failed to create {}: {}", path, e);
+                return;
+            }
+        },
+        None => Box::new(io::stdout()),
+    };
+
+    let mut searcher = Search::with_hash(config.hash);
+
+    let mut players: BTreeMap<String, Stats> = BTreeMap::new();
+    let games = pgn::read_games(&text);
+    for (i, game) in games.iter().enumerate() {
+        let game = match game {
+            Ok(game) => game,
+            Err(e) => {
+                eprintln!("Error: Game {}: {}", i + 1, e);
+                continue;
+            }
+        };
+        searcher.clear_tt();
+        let (annotations, stats) = annotate_game(&mut searcher, &config, game);
+        if let Err(e) = writeln!(out, "{}", pgn::write_game(game, &annotations)) {
+            eprintln!("Error: Failed to write games: {}", e);
+            return;
+        }
+
+        eprintln!("Game {}/{}:", i + 1, games.len());
+        for (color, tag) in [(Color::White, "White"), (Color::Black, "Black")] {
+            let name = game.tag(tag).unwrap_or("?");
+            let stats = &stats[color as usize];
+            eprintln!("  {} ({}): {}", name, tag, stats.summary());
+            players.entry(name.to_string()).or_default().add(stats);
+        }
+    }
+    if let Err(e) = out.flush() {
+        eprintln!("Error: Failed to write games: {}", e);
+    }
+
+    if games.len() > 1 {
+        eprintln!("All games:");
+        for (name, stats) in &players {
+            eprintln!("  {}: {}", name, stats.summary());
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Option<Config> {
+    let mut config = Config {
+        input: String::new(),
+        output: None,
+        depth: DEFAULT_DEPTH,
+        movetime: None,
+        hash: DEFAULT_HASH,
+    };
+    let mut i = 0;
+    while i < args.len() {
+        if !args[i].starts_with("--") {
+            config.input = args[i].clone();
+            i += 1;
+            continue;
+        }
+        let Some(value) = args.get(i + 1) else {
+            eprintln!("Error: Missing value for {}", args[i]);
+            return None;
+        };
+        let number = value.parse::<usize>().ok().filter(|&n| n > 0);
+        match (args[i].as_str(), number) {
+            ("--output", _) => config.output = Some(value.clone()),
+            ("--depth", Some(n)) if n < MAX_PLY => config.depth = n as u8,
+            ("--movetime", Some(n)) => config.movetime = Some(Duration::from_millis(n as u64)),
+            ("--hash", Some(n)) => config.hash = n,
+            _ => {
+                eprintln!("Error: Bad option {} {}", args[i], value);
+                return None;
+            }
+        }
+        i += 2;
+    }
+    (!config.input.is_empty()).then_some(config)
+}
+
+/// A position's best move, if the game goes on, and its score for the side
+/// to move.
+fn evaluate(searcher: &mut Search, config: &Config, game: &GameState) -> (Option<Move>, i32) {
+    match game.result() {
+        Some(GameResult::Checkmate(_)) => return (None, -MATE_VALUE),
+        Some(GameResult::Draw(_)) => return (None, 0),
+        None => {}
+    }
+    let best = searcher.go(game, config.depth, config.movetime);
+    let score = searcher.root_lines.first().map_or(0, |&(_, score)| score);
+    (best, score)
+}
+
+/// The side to move's expected score out of 1.
+fn expected_score(score: i32, board: &Board) -> f64 {
+    let (w, d, _) = crate::wdl::wdl(score, board);
+    (w as f64 + d as f64 / 2.0) / 1000.0
+}
+
+/// Lichess's accuracy of a move from the win percentage it lost.
+fn move_accuracy(loss: f64) -> f64 {
+    (103.1668 * (-0.04354 * loss * 100.0).exp() - 3.1669).clamp(0.0, 100.0)
+}
+
+/// A score for white as `[%eval]` writes it: pawns, or `#n` for a mate.
+fn format_eval(score: i32, side_to_move: Color) -> String {
+    let score = if side_to_move == Color::White {
+        score
+    } else {
+        -score
+    };
+    if score.abs() >= MATE_VALUE - MAX_PLY as i32 {
+        let moves = (MATE_VALUE - score.abs() + 1) / 2;
+        format!("[%eval #{}]", if score > 0 { moves } else { -moves })
+    } else {
+        format!("[%eval {:.2}]", score as f64 / 100.0)
+    }
+}
+
+fn annotate_game(
+    searcher: &mut Search,
+    config: &Config,
+    pgn_game: &PgnGame,
+) -> (Vec<Annotation>, [Stats; 2]) {
+    let mut stats: [Stats; 2] = Default::default();
+    let mut annotations = Vec::new();
+    let mut game = GameState::new(pgn_game.start);
+    let (mut best, mut score) = evaluate(searcher, config, &game);
+    for &played in &pgn_game.moves {
+        let before = game.board;
+        // The TT still holds this search, so the better line comes from it now
+        let line = best.map(|m| searcher.tt_line(&before, m, VARIATION_LENGTH));
+        game.make_move(played);
+        let (next_best, next_score) = evaluate(searcher, config, &game);
+
+        // The mover's expected score with the best move and with the one played
+        let (loss, cp_loss) = if best == Some(played) {
+            (0.0, 0)
+        } else {
+            let with_best = expected_score(score, &before);
+            let with_played = 1.0 - expected_score(next_score, &game.board);
+            let cp_loss = score.clamp(-MAX_CP_LOSS, MAX_CP_LOSS)
+                + next_score.clamp(-MAX_CP_LOSS, MAX_CP_LOSS);
+            ((with_best - with_played).max(0.0), cp_loss.max(0))
+        };
+
+        let mover = &mut stats[before.side_to_move as usize];
+        mover.moves += 1;
+        mover.accuracy += move_accuracy(loss);
+        mover.cp_loss += cp_loss as i64;
+
+        let mut annotation = Annotation::default();
+        let mut comment = Vec::new();
+        if game.result().is_none() {
+            comment.push(format_eval(next_score, game.board.side_to_move));
+        }
+        if let Some(j) = JUDGEMENTS.iter().position(|&(min, _, _)| loss >= min) {
+            let (_, nag, name) = JUDGEMENTS[j];
+            mover.counts[j] += 1;
+            annotation.nag = Some(nag);
+            if let Some(line) = line {
+                comment.push(format!(
+                    "{}. {} was best.",
+                    name,
+                    pgn::to_san(&before, line[0])
+                ));
+                annotation.variation = line;
+            }
+        }
+        if !comment.is_empty() {
+            annotation.comment = Some(comment.join(" "));
+        }
+        annotations.push(annotation);
+
+        best = next_best;
+        score = next_score;
+    }
+    (annotations, stats)
+}
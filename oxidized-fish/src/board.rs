@@ -1,3 +1,4 @@
+use crate::movegen::MoveGen;
 use crate::types::{Color, Move, PieceType};
 use crate::zobrist::ZOBRIST;
 
@@ -8,18 +9,50 @@ pub struct Board {
     pub side_to_move: Color,
     pub castling_rights: u8, // 1: WK, 2: WQ, 4: BK, 8: BQ
     pub ep_square: Option<u8>,
-    #[allow(dead_code)]
     pub halfmove_clock: u8,
     pub fullmove_number: u16,
     pub hash: u64,
+    pub pawn_hash: u64,
     pub pins: u64, // Pins for the side to move
 }
 
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Board {
     pub fn new() -> Self {
         Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
     }
 
+    /// `from_fen` trusts its input, so check what the search relies on before
+    /// taking a FEN from a user: eight full ranks and one king a side.
+    pub fn is_valid_fen(fen: &str) -> bool {
+        let mut parts = fen.split_whitespace();
+        let Some(placement) = parts.next() else {
+            return false;
+        };
+        if !matches!(parts.next(), None | Some("w") | Some("b")) {
+            return false;
+        }
+        let ranks: Vec<&str> = placement.split('/').collect();
+        let full_ranks = ranks.len() == 8
+            && ranks.iter().all(|rank| {
+                let mut files = 0;
+                for c in rank.chars() {
+                    match c {
+                        '1'..='8' => files += c as u32 - '0' as u32,
+                        c if "pnbrqkPNBRQK".contains(c) => files += 1,
+                        _ => return false,
+                    }
+                }
+                files == 8
+            });
+        full_ranks && placement.matches('K').count() == 1 && placement.matches('k').count() == 1
+    }
+
     pub fn from_fen(fen: &str) -> Self {
         let parts: Vec<&str> = fen.split_whitespace().collect();
         let mut board = Board {
@@ -31,11 +64,13 @@ impl Board {
             halfmove_clock: 0,
             fullmove_number: 1,
             hash: 0,
+            pawn_hash: 0,
             pins: 0,
         };
 
         if parts.is_empty() {
             board.hash = board.calculate_hash();
+            board.pawn_hash = board.calculate_pawn_hash();
             board.pins = board.calculate_pins();
             return board;
         }
@@ -109,10 +144,68 @@ impl Board {
         }
 
         board.hash = board.calculate_hash();
+        board.pawn_hash = board.calculate_pawn_hash();
         board.pins = board.calculate_pins();
         board
     }
 
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.get_piece_at(rank * 8 + file) {
+                    Some((piece, color)) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        let c = b"pnbrqk"[piece as usize] as char;
+                        placement.push(if color == Color::White {
+                            c.to_ascii_uppercase()
+                        } else {
+                            c
+                        });
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                placement.push('/');
+            }
+        }
+
+        let mut castling: String = ['K', 'Q', 'k', 'q']
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| self.castling_rights & (1 << i) != 0)
+            .map(|(_, &c)| c)
+            .collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        let ep = self.ep_square.map_or("-".to_string(), |sq| {
+            format!("{}{}", (b'a' + sq % 8) as char, sq / 8 + 1)
+        });
+
+        format!(
+            "{} {} {} {} {} {}",
+            placement,
+            if self.side_to_move == Color::White {
+                "w"
+            } else {
+                "b"
+            },
+            castling,
+            ep,
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+
     pub fn calculate_hash(&self) -> u64 {
         let mut h = 0u64;
         for c in 0..2 {
@@ -135,6 +228,19 @@ impl Board {
         h
     }
 
+    pub fn calculate_pawn_hash(&self) -> u64 {
+        let mut h = 0u64;
+        for c in 0..2 {
+            let mut bb = self.by_type[PieceType::Pawn as usize] & self.by_color[c];
+            while bb != 0 {
+                let sq = bb.trailing_zeros() as usize;
+                h ^= ZOBRIST.pieces[c][PieceType::Pawn as usize][sq];
+                bb &= bb - 1;
+            }
+        }
+        h
+    }
+
     pub fn calculate_pins(&self) -> u64 {
         let mut pins = 0u64;
         let us = self.side_to_move;
@@ -236,6 +342,9 @@ impl Board {
         let (piece, _) = self.get_piece_at(from).expect("No piece at from");
 
         next.hash ^= ZOBRIST.pieces[us as usize][piece as usize][from as usize];
+        if piece == PieceType::Pawn {
+            next.pawn_hash ^= ZOBRIST.pieces[us as usize][PieceType::Pawn as usize][from as usize];
+        }
         if let Some(sq) = self.ep_square {
             next.hash ^= ZOBRIST.ep[sq as usize];
         }
@@ -251,11 +360,17 @@ impl Board {
                 next.by_type[PieceType::Pawn as usize] &= !(1u64 << cap_sq);
                 next.hash ^=
                     ZOBRIST.pieces[them as usize][PieceType::Pawn as usize][cap_sq as usize];
+                next.pawn_hash ^=
+                    ZOBRIST.pieces[them as usize][PieceType::Pawn as usize][cap_sq as usize];
             } else {
                 let (cap_piece, _) = self.get_piece_at(to).expect("Capture but no piece");
                 next.by_color[them as usize] &= !(1u64 << to);
                 next.by_type[cap_piece as usize] &= !(1u64 << to);
                 next.hash ^= ZOBRIST.pieces[them as usize][cap_piece as usize][to as usize];
+                if cap_piece == PieceType::Pawn {
+                    next.pawn_hash ^=
+                        ZOBRIST.pieces[them as usize][PieceType::Pawn as usize][to as usize];
+                }
             }
             next.halfmove_clock = 0;
         } else if piece == PieceType::Pawn {
@@ -278,6 +393,9 @@ impl Board {
         next.by_color[us as usize] |= 1u64 << to;
         next.by_type[placed_piece as usize] |= 1u64 << to;
         next.hash ^= ZOBRIST.pieces[us as usize][placed_piece as usize][to as usize];
+        if placed_piece == PieceType::Pawn {
+            next.pawn_hash ^= ZOBRIST.pieces[us as usize][PieceType::Pawn as usize][to as usize];
+        }
 
         if piece == PieceType::King {
             if flags == Move::K_CASTLE {
@@ -414,11 +532,12 @@ impl Board {
         let from = m.from();
         let to = m.to();
         let (mut piece, _) = self.get_piece_at(from).unwrap();
-        let victim = self
-            .get_piece_at(to)
-            .map(|(p, _)| p)
-            .unwrap_or(PieceType::Pawn);
-        let mut score = values[victim as usize];
+        // En passant takes a pawn from another square, and quiet moves take nothing
+        let mut score = match self.get_piece_at(to) {
+            Some((victim, _)) => values[victim as usize],
+            None if m.is_capture() => values[PieceType::Pawn as usize],
+            None => 0,
+        };
         if m.is_promotion() {
             let promo = m.promoted_piece().unwrap();
             score += values[promo as usize] - values[PieceType::Pawn as usize];
@@ -484,6 +603,35 @@ impl Board {
         )
     }
 
+    pub fn has_legal_moves(&self) -> bool {
+        MoveGen::generate(self)
+            .into_iter()
+            .any(|m| self.is_legal(m))
+    }
+
+    /// Bare kings, a single minor piece, or bishops that all share one square color.
+    pub fn is_insufficient_material(&self) -> bool {
+        const DARK_SQUARES: u64 = 0xAA55AA55AA55AA55;
+        if (self.by_type[PieceType::Pawn as usize]
+            | self.by_type[PieceType::Rook as usize]
+            | self.by_type[PieceType::Queen as usize])
+            != 0
+        {
+            return false;
+        }
+        let knights = self.by_type[PieceType::Knight as usize];
+        let bishops = self.by_type[PieceType::Bishop as usize];
+        if (knights | bishops).count_ones() <= 1 {
+            return true;
+        }
+        knights == 0 && ((bishops & DARK_SQUARES) == 0 || (bishops & !DARK_SQUARES) == 0)
+    }
+
+    /// Fifty-move rule; a checkmate delivered on the hundredth ply still counts.
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.halfmove_clock >= 100 && (!self.is_in_check() || self.has_legal_moves())
+    }
+
     pub fn is_legal(&self, m: Move) -> bool {
         let next_board = self.make_move(m);
         let us = self.side_to_move;
@@ -537,3 +685,46 @@ fn bishop_line(sq1: u8, sq2: u8) -> u64 {
         0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insufficient(fen: &str) -> bool {
+        Board::from_fen(fen).is_insufficient_material()
+    }
+
+    #[test]
+    fn insufficient_material() {
+        assert!(insufficient("8/8/8/4k3/8/8/8/4K3 w - - 0 1"));
+        assert!(insufficient("8/8/8/4k3/8/8/8/4KN2 w - - 0 1"));
+        assert!(insufficient("8/8/8/4k3/8/8/8/2B1K3 w - - 0 1"));
+        // Bishops on c1 and f8 are both on dark squares
+        assert!(insufficient("5b2/8/8/4k3/8/8/8/2B1K3 w - - 0 1"));
+        assert!(insufficient("5b2/8/8/4k3/8/8/8/B1B1K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn sufficient_material() {
+        // Bishops on c1 and c8 are on opposite colors
+        assert!(!insufficient("2b5/8/8/4k3/8/8/8/2B1K3 w - - 0 1"));
+        assert!(!insufficient("8/8/8/4k3/8/8/8/2N1KN2 w - - 0 1"));
+        assert!(!insufficient("8/8/8/4k3/8/8/8/2N1KB2 w - - 0 1"));
+        assert!(!insufficient("8/8/8/4k3/8/8/4P3/4K3 w - - 0 1"));
+        assert!(!insufficient("8/8/8/4k3/8/8/8/4K2R w - - 0 1"));
+    }
+
+    #[test]
+    fn fifty_move_draw() {
+        let fifty = |clock: u8| {
+            Board::from_fen(&format!("8/8/8/4k3/8/8/8/4K2R w - - {} 80", clock))
+                .is_fifty_move_draw()
+        };
+        assert!(!fifty(99));
+        assert!(fifty(100));
+        // In check with a way out is still a draw
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/4KR2 b - - 100 80").is_fifty_move_draw());
+        // Mated on the hundredth ply is not
+        assert!(!Board::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 100 90").is_fifty_move_draw());
+    }
+}
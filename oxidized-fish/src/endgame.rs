@@ -0,0 +1,598 @@
+use crate::board::Board;
+use crate::pawns::PawnEntry;
+use crate::tables::{ATTACKS, EG_VALUE, MG_VALUE};
+use crate::types::{Color, PieceType};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+
+pub const KNOWN_WIN: i32 = 10000;
+pub const SCALE_NORMAL: i32 = 64;
+const SCALE_DRAW: i32 = 0;
+
+type ValueFn = fn(&Board, Color) -> i32;
+type ScaleFn = fn(&Board, Color, &PawnEntry) -> i32;
+
+#[derive(Clone, Copy)]
+enum Evaluator {
+    Value(ValueFn), // Replaces the evaluation, score is from the strong side's point of view
+    Scale(ScaleFn), // Scales the endgame part of the evaluation, 0 (draw) to SCALE_NORMAL
+}
+
+#[derive(Clone, Copy)]
+struct Endgame {
+    name: &'static str,
+    strong_side: Color,
+    evaluator: Evaluator,
+}
+
+lazy_static! {
+    static ref ENDGAMES: HashMap<u64, Endgame> = {
+        let mut map = HashMap::new();
+        add(&mut map, "KK", Evaluator::Value(draw));
+        add(&mut map, "KNK", Evaluator::Value(draw));
+        add(&mut map, "KBK", Evaluator::Value(draw));
+        add(&mut map, "KNNK", Evaluator::Value(draw));
+        add(&mut map, "KPK", Evaluator::Value(kpk));
+        add(&mut map, "KBNK", Evaluator::Value(kbnk));
+        add(&mut map, "KRKP", Evaluator::Value(krkp));
+        add(&mut map, "KRKB", Evaluator::Value(krkb));
+        add(&mut map, "KRKN", Evaluator::Value(krkn));
+        add(&mut map, "KPKP", Evaluator::Scale(kpkp));
+        add(&mut map, "KNPK", Evaluator::Scale(knpk));
+        map
+    };
+    static ref KPK_BITBASE: Vec<bool> = generate_kpk_bitbase();
+}
+
+fn add(map: &mut HashMap<u64, Endgame>, code: &'static str, evaluator: Evaluator) {
+    for strong_side in [Color::White, Color::Black] {
+        map.insert(
+            key_from_code(code, strong_side),
+            Endgame {
+                name: code,
+                strong_side,
+                evaluator,
+            },
+        );
+    }
+}
+
+/// Material signature: four bits per piece type and color, kings excluded.
+pub fn material_key(board: &Board) -> u64 {
+    let mut key = 0u64;
+    for c in 0..2 {
+        for pt in 0..5 {
+            let count = (board.by_type[pt] & board.by_color[c]).count_ones() as u64;
+            key |= count << (4 * (c * 5 + pt));
+        }
+    }
+    key
+}
+
+fn mirror_key(key: u64) -> u64 {
+    ((key & 0xFFFFF) << 20) | (key >> 20)
+}
+
+/// Builds the material key for a code such as "KBNK", strong side's pieces first.
+fn key_from_code(code: &str, strong_side: Color) -> u64 {
+    let weak_start = code[1..].find('K').map(|i| i + 1).unwrap_or(code.len());
+    let sides = [&code[..weak_start], &code[weak_start..]];
+    let mut key = 0u64;
+    for (i, side) in sides.iter().enumerate() {
+        let c = if i == 0 {
+            strong_side as usize
+        } else {
+            strong_side.opponent() as usize
+        };
+        for ch in side.chars() {
+            let pt = match ch {
+                'P' => 0,
+                'N' => 1,
+                'B' => 2,
+                'R' => 3,
+                'Q' => 4,
+                _ => continue,
+            };
+            key += 1u64 << (4 * (c * 5 + pt));
+        }
+    }
+    key
+}
+
+/// The position's material and the specialized endgame for it, if any,
+/// looked up once for `probe_value` and `scale_factor`.
+#[derive(Clone, Copy)]
+pub struct EndgameProbe {
+    key: u64,
+    endgame: Option<Endgame>,
+}
+
+pub fn probe(board: &Board) -> EndgameProbe {
+    let key = material_key(board);
+    EndgameProbe {
+        key,
+        endgame: ENDGAMES.get(&key).copied(),
+    }
+}
+
+/// Looks up a specialized evaluation function for the position's material.
+/// Returns the name of the endgame and its score from White's point of view.
+pub fn probe_value(board: &Board, probe: &EndgameProbe) -> Option<(&'static str, i32)> {
+    let mut endgame = probe.endgame;
+    if endgame.is_none() {
+        for strong_side in [Color::White, Color::Black] {
+            let weak = board.by_color[strong_side.opponent() as usize];
+            if (weak & !board.by_type[PieceType::King as usize]) == 0
+                && non_pawn_material(board, strong_side) >= MG_VALUE[PieceType::Rook as usize]
+            {
+                endgame = Some(Endgame {
+                    name: "KXK",
+                    strong_side,
+                    evaluator: Evaluator::Value(kxk),
+                });
+            }
+        }
+    }
+    let endgame = endgame?;
+    if let Evaluator::Value(f) = endgame.evaluator {
+        let score = f(board, endgame.strong_side);
+        return Some((
+            endgame.name,
+            if endgame.strong_side == Color::White {
+                score
+            } else {
+                -score
+            },
+        ));
+    }
+    None
+}
+
+/// Scale factor for the endgame part of the evaluation when `strong_side` is
+/// ahead, along with the name of the rule that produced it.
+pub fn scale_factor(
+    board: &Board,
+    strong_side: Color,
+    pawns: &PawnEntry,
+    probe: &EndgameProbe,
+) -> (i32, Option<&'static str>) {
+    if let Some(e) = probe.endgame {
+        if let Evaluator::Scale(f) = e.evaluator {
+            if e.strong_side == strong_side || probe.key == mirror_key(probe.key) {
+                return (f(board, strong_side, pawns), Some(e.name));
+            }
+        }
+    }
+
+    let weak_side = strong_side.opponent();
+    let s = strong_side as usize;
+    let w = weak_side as usize;
+    let strong_npm = non_pawn_material(board, strong_side);
+    let weak_npm = non_pawn_material(board, weak_side);
+    let strong_pawns = board.by_type[PieceType::Pawn as usize] & board.by_color[s];
+
+    // Pawnless endings with at most a minor piece up are very hard to win
+    if strong_pawns == 0 && strong_npm - weak_npm <= MG_VALUE[PieceType::Bishop as usize] {
+        let sf = if strong_npm < MG_VALUE[PieceType::Rook as usize] {
+            SCALE_DRAW
+        } else if weak_npm <= MG_VALUE[PieceType::Bishop as usize] {
+            4
+        } else {
+            14
+        };
+        return (sf, Some("Pawnless"));
+    }
+
+    let bishops = board.by_type[PieceType::Bishop as usize];
+    let strong_bishops = bishops & board.by_color[s];
+    let weak_bishops = bishops & board.by_color[w];
+
+    // Bishop and rook pawns where the bishop does not control the queening square
+    let weak_pawns = board.by_type[PieceType::Pawn as usize] & board.by_color[w];
+    if strong_npm == MG_VALUE[PieceType::Bishop as usize]
+        && strong_bishops != 0
+        && weak_npm == 0
+        && weak_pawns == 0
+    {
+        for file_mask in [crate::tables::FILE_BB[0], crate::tables::FILE_BB[7]] {
+            if (strong_pawns & !file_mask) != 0 {
+                continue;
+            }
+            let file = (file_mask.trailing_zeros() % 8) as u8;
+            let queening_sq = if strong_side == Color::White {
+                56 + file
+            } else {
+                file
+            };
+            let weak_king = king_square(board, weak_side);
+            let bishop_sq = strong_bishops.trailing_zeros() as u8;
+            if !same_color(bishop_sq, queening_sq) && distance(weak_king, queening_sq) <= 1 {
+                return (SCALE_DRAW, Some("Wrong bishop"));
+            }
+        }
+    }
+
+    // Opposite-colored bishops
+    if strong_bishops.count_ones() == 1
+        && weak_bishops.count_ones() == 1
+        && !same_color(
+            strong_bishops.trailing_zeros() as u8,
+            weak_bishops.trailing_zeros() as u8,
+        )
+    {
+        let bishop_value = MG_VALUE[PieceType::Bishop as usize];
+        let sf = if strong_npm == bishop_value && weak_npm == bishop_value {
+            18 + 4 * pawns.passed[s].count_ones() as i32
+        } else {
+            22 + 3 * board.by_color[s].count_ones() as i32
+        };
+        return (sf.min(SCALE_NORMAL), Some("Opposite bishops"));
+    }
+
+    (SCALE_NORMAL, None)
+}
+
+fn non_pawn_material(board: &Board, c: Color) -> i32 {
+    (1..5)
+        .map(|pt| {
+            (board.by_type[pt] & board.by_color[c as usize]).count_ones() as i32 * MG_VALUE[pt]
+        })
+        .sum()
+}
+
+fn king_square(board: &Board, c: Color) -> u8 {
+    (board.by_type[PieceType::King as usize] & board.by_color[c as usize]).trailing_zeros() as u8
+}
+
+fn piece_square(board: &Board, pt: PieceType, c: Color) -> u8 {
+    (board.by_type[pt as usize] & board.by_color[c as usize]).trailing_zeros() as u8
+}
+
+fn distance(a: u8, b: u8) -> i32 {
+    let df = (a % 8) as i32 - (b % 8) as i32;
+    let dr = (a / 8) as i32 - (b / 8) as i32;
+    df.abs().max(dr.abs())
+}
+
+fn same_color(a: u8, b: u8) -> bool {
+    ((a / 8 + a % 8) & 1) == ((b / 8 + b % 8) & 1)
+}
+
+fn push_to_edge(sq: u8) -> i32 {
+    let fd = ((sq % 8) as i32).min(7 - (sq % 8) as i32);
+    let rd = ((sq / 8) as i32).min(7 - (sq / 8) as i32);
+    90 - (7 * fd * fd / 2 + 7 * rd * rd / 2)
+}
+
+fn push_to_corner(sq: u8) -> i32 {
+    (7 - (sq / 8) as i32 - (sq % 8) as i32).abs()
+}
+
+fn push_close(a: u8, b: u8) -> i32 {
+    140 - 20 * distance(a, b)
+}
+
+fn push_away(a: u8, b: u8) -> i32 {
+    120 - push_close(a, b)
+}
+
+/// Maps a square to the frame where the strong side plays up the board.
+fn relative(sq: u8, strong_side: Color) -> u8 {
+    if strong_side == Color::White {
+        sq
+    } else {
+        sq ^ 56
+    }
+}
+
+fn draw(_board: &Board, _strong_side: Color) -> i32 {
+    0
+}
+
+/// Lone king against enough material to mate: drive it to the edge.
+fn kxk(board: &Board, strong_side: Color) -> i32 {
+    let s = strong_side as usize;
+    let strong_king = king_square(board, strong_side);
+    let weak_king = king_square(board, strong_side.opponent());
+
+    let mut result = push_to_edge(weak_king) + push_close(strong_king, weak_king);
+    for (pieces, value) in board.by_type.iter().zip(EG_VALUE).take(5) {
+        result += (pieces & board.by_color[s]).count_ones() as i32 * value;
+    }
+
+    let ours = |pt: PieceType| board.by_type[pt as usize] & board.by_color[s];
+    let bishops = ours(PieceType::Bishop);
+    let light_squares = 0x55AA55AA55AA55AAu64;
+    if ours(PieceType::Queen) != 0
+        || ours(PieceType::Rook) != 0
+        || (bishops != 0 && ours(PieceType::Knight) != 0)
+        || ((bishops & light_squares) != 0 && (bishops & !light_squares) != 0)
+    {
+        result = (result + KNOWN_WIN).min(2 * KNOWN_WIN - 1);
+    }
+    result
+}
+
+/// Bishop and knight mate: the weak king has to be driven to a corner of the
+/// bishop's color.
+fn kbnk(board: &Board, strong_side: Color) -> i32 {
+    let strong_king = king_square(board, strong_side);
+    let mut weak_king = king_square(board, strong_side.opponent());
+    let bishop_sq = piece_square(board, PieceType::Bishop, strong_side);
+
+    // push_to_corner favours a1/h8, which are dark squares
+    if !same_color(bishop_sq, 0) {
+        weak_king ^= 7;
+    }
+    KNOWN_WIN + push_close(strong_king, weak_king) + 42 * push_to_corner(weak_king)
+}
+
+fn kpk(board: &Board, strong_side: Color) -> i32 {
+    let (strong_king, pawn, weak_king, strong_to_move) = normalize_kpk(board, strong_side);
+    if !probe_kpk(strong_king, pawn, weak_king, strong_to_move) {
+        return 0;
+    }
+    KNOWN_WIN + EG_VALUE[PieceType::Pawn as usize] + (pawn / 8) as i32
+}
+
+/// Rook against pawn: a win unless the pawn is far advanced and supported.
+fn krkp(board: &Board, strong_side: Color) -> i32 {
+    let weak_side = strong_side.opponent();
+    let strong_king = relative(king_square(board, strong_side), strong_side);
+    let weak_king = relative(king_square(board, weak_side), strong_side);
+    let rook = relative(
+        piece_square(board, PieceType::Rook, strong_side),
+        strong_side,
+    );
+    let pawn = relative(piece_square(board, PieceType::Pawn, weak_side), strong_side);
+    let queening_sq = pawn % 8;
+    let rook_value = EG_VALUE[PieceType::Rook as usize];
+    let strong_to_move = board.side_to_move == strong_side;
+
+    // Strong king in front of the pawn, or the weak king too far away
+    if strong_king % 8 == pawn % 8 && strong_king < pawn {
+        return rook_value - distance(strong_king, pawn);
+    }
+    if distance(weak_king, pawn) >= 3 + !strong_to_move as i32 && distance(weak_king, rook) >= 3 {
+        return rook_value - distance(strong_king, pawn);
+    }
+    // Pawn is far advanced and supported by its king
+    if weak_king / 8 <= 2
+        && distance(weak_king, pawn) == 1
+        && strong_king / 8 >= 3
+        && distance(strong_king, pawn) > 2 + strong_to_move as i32
+    {
+        return 40 - 4 * distance(strong_king, pawn);
+    }
+    // A pawn on the first rank only comes from a bad FEN; it has arrived
+    let stop_sq = pawn.checked_sub(8).unwrap_or(queening_sq);
+    100 - 4
+        * (distance(strong_king, stop_sq)
+            - distance(weak_king, stop_sq)
+            - distance(pawn, queening_sq))
+}
+
+fn krkb(board: &Board, strong_side: Color) -> i32 {
+    push_to_edge(king_square(board, strong_side.opponent()))
+}
+
+fn krkn(board: &Board, strong_side: Color) -> i32 {
+    let weak_side = strong_side.opponent();
+    let weak_king = king_square(board, weak_side);
+    let knight = piece_square(board, PieceType::Knight, weak_side);
+    push_to_edge(weak_king) + push_away(weak_king, knight)
+}
+
+/// With a pawn each, a position where the strong side's pawn alone does not win
+/// the KPK ending is most likely a draw.
+fn kpkp(board: &Board, strong_side: Color, _pawns: &PawnEntry) -> i32 {
+    let (strong_king, pawn, weak_king, strong_to_move) = normalize_kpk(board, strong_side);
+    if pawn / 8 >= 4 && pawn % 8 != 0 {
+        return SCALE_NORMAL;
+    }
+    if probe_kpk(strong_king, pawn, weak_king, strong_to_move) {
+        SCALE_NORMAL
+    } else {
+        SCALE_DRAW
+    }
+}
+
+/// Knight and rook pawn on the seventh with the defending king in the corner.
+fn knpk(board: &Board, strong_side: Color, _pawns: &PawnEntry) -> i32 {
+    let pawn = relative(
+        piece_square(board, PieceType::Pawn, strong_side),
+        strong_side,
+    );
+    let weak_king = relative(king_square(board, strong_side.opponent()), strong_side);
+    let file = pawn % 8;
+    if (file == 0 || file == 7) && pawn / 8 == 6 && distance(weak_king, 56 + file) <= 1 {
+        SCALE_DRAW
+    } else {
+        SCALE_NORMAL
+    }
+}
+
+/// Returns (strong king, pawn, weak king, strong side to move) with the strong
+/// side playing up the board and the pawn on files a-d.
+fn normalize_kpk(board: &Board, strong_side: Color) -> (u8, u8, u8, bool) {
+    let mut strong_king = relative(king_square(board, strong_side), strong_side);
+    let mut pawn = relative(
+        piece_square(board, PieceType::Pawn, strong_side),
+        strong_side,
+    );
+    let mut weak_king = relative(king_square(board, strong_side.opponent()), strong_side);
+    if pawn % 8 > 3 {
+        strong_king ^= 7;
+        pawn ^= 7;
+        weak_king ^= 7;
+    }
+    (
+        strong_king,
+        pawn,
+        weak_king,
+        board.side_to_move == strong_side,
+    )
+}
+
+fn probe_kpk(strong_king: u8, pawn: u8, weak_king: u8, strong_to_move: bool) -> bool {
+    KPK_BITBASE[kpk_index(!strong_to_move as usize, weak_king, strong_king, pawn)]
+}
+
+// KPK bitbase, White has the pawn on files a-d and ranks 2-7.
+const KPK_SIZE: usize = 2 * 24 * 64 * 64;
+const KPK_INVALID: u8 = 0;
+const KPK_UNKNOWN: u8 = 1;
+const KPK_DRAW: u8 = 2;
+const KPK_WIN: u8 = 4;
+
+fn kpk_index(stm: usize, black_king: u8, white_king: u8, pawn: u8) -> usize {
+    white_king as usize
+        | (black_king as usize) << 6
+        | stm << 12
+        | ((pawn % 8) as usize) << 13
+        | ((6 - pawn / 8) as usize) << 15
+}
+
+fn kpk_decode(idx: usize) -> (usize, u8, u8, u8) {
+    let white_king = (idx & 0x3F) as u8;
+    let black_king = ((idx >> 6) & 0x3F) as u8;
+    let stm = (idx >> 12) & 1;
+    let pawn = (((6 - ((idx >> 15) & 7)) * 8) + ((idx >> 13) & 3)) as u8;
+    (stm, white_king, black_king, pawn)
+}
+
+fn kpk_initial(idx: usize) -> u8 {
+    let (stm, white_king, black_king, pawn) = kpk_decode(idx);
+    let pawn_attacks = ATTACKS.pawn[0][pawn as usize];
+
+    if distance(white_king, black_king) <= 1
+        || white_king == pawn
+        || black_king == pawn
+        || (stm == 0 && (pawn_attacks & (1u64 << black_king)) != 0)
+    {
+        return KPK_INVALID;
+    }
+
+    // Immediate promotion that can't be stopped
+    if stm == 0
+        && pawn / 8 == 6
+        && white_king != pawn + 8
+        && (distance(black_king, pawn + 8) > 1 || distance(white_king, pawn + 8) == 1)
+    {
+        return KPK_WIN;
+    }
+
+    // Stalemate, or the black king captures an undefended pawn
+    let black_moves = ATTACKS.king[black_king as usize];
+    let white_king_attacks = ATTACKS.king[white_king as usize];
+    if stm == 1
+        && ((black_moves & !(white_king_attacks | pawn_attacks)) == 0
+            || (black_moves & !white_king_attacks & (1u64 << pawn)) != 0)
+    {
+        return KPK_DRAW;
+    }
+
+    KPK_UNKNOWN
+}
+
+fn kpk_classify(db: &[u8], idx: usize) -> u8 {
+    let (stm, white_king, black_king, pawn) = kpk_decode(idx);
+    let (good, bad) = if stm == 0 {
+        (KPK_WIN, KPK_DRAW)
+    } else {
+        (KPK_DRAW, KPK_WIN)
+    };
+
+    let mut r = KPK_INVALID;
+    if stm == 0 {
+        let mut moves = ATTACKS.king[white_king as usize];
+        while moves != 0 {
+            let to = moves.trailing_zeros() as u8;
+            r |= db[kpk_index(1, black_king, to, pawn)];
+            moves &= moves - 1;
+        }
+        if pawn / 8 < 6 {
+            r |= db[kpk_index(1, black_king, white_king, pawn + 8)];
+        }
+        if pawn / 8 == 1 && pawn + 8 != white_king && pawn + 8 != black_king {
+            r |= db[kpk_index(1, black_king, white_king, pawn + 16)];
+        }
+    } else {
+        let mut moves = ATTACKS.king[black_king as usize];
+        while moves != 0 {
+            let to = moves.trailing_zeros() as u8;
+            r |= db[kpk_index(0, to, white_king, pawn)];
+            moves &= moves - 1;
+        }
+    }
+
+    if (r & good) != 0 {
+        good
+    } else if (r & KPK_UNKNOWN) != 0 {
+        KPK_UNKNOWN
+    } else {
+        bad
+    }
+}
+
+fn generate_kpk_bitbase() -> Vec<bool> {
+    let mut db: Vec<u8> = (0..KPK_SIZE).map(kpk_initial).collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in 0..KPK_SIZE {
+            if db[idx] == KPK_UNKNOWN {
+                let result = kpk_classify(&db, idx);
+                if result != KPK_UNKNOWN {
+                    db[idx] = result;
+                    changed = true;
+                }
+            }
+        }
+    }
+    db.into_iter().map(|r| r == KPK_WIN).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kpk_value(fen: &str, strong_side: Color) -> i32 {
+        kpk(&Board::from_fen(fen), strong_side)
+    }
+
+    #[test]
+    fn kpk_wins() {
+        // King in front of its pawn on the sixth rank, either side to move
+        assert!(kpk_value("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Color::White) > KNOWN_WIN);
+        assert!(kpk_value("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", Color::White) > KNOWN_WIN);
+        // Kd6 and Kd7 before the defending king gets back
+        assert!(kpk_value("4k3/4P3/4K3/8/8/8/8/8 w - - 0 1", Color::White) > KNOWN_WIN);
+        // The defending king is outside the square of the pawn
+        assert!(kpk_value("8/8/8/8/P7/8/8/k6K w - - 0 1", Color::White) > KNOWN_WIN);
+        // The same as Black, on files e-h
+        assert!(kpk_value("8/8/8/8/4p3/4k3/8/4K3 w - - 0 1", Color::Black) > KNOWN_WIN);
+        assert!(kpk_value("K6k/8/8/7p/8/8/8/8 b - - 0 1", Color::Black) > KNOWN_WIN);
+    }
+
+    #[test]
+    fn kpk_draws() {
+        // Stalemate
+        assert_eq!(
+            kpk_value("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1", Color::White),
+            0
+        );
+        // A rook pawn with the defending king in the corner
+        assert_eq!(kpk_value("k7/8/8/8/8/8/P7/K7 w - - 0 1", Color::White), 0);
+        // The defending king catches the pawn
+        assert_eq!(kpk_value("8/8/8/2k5/P7/8/8/7K w - - 0 1", Color::White), 0);
+        assert_eq!(kpk_value("8/8/8/8/4p3/8/8/4K2k w - - 0 1", Color::Black), 0);
+    }
+
+    #[test]
+    fn krkp_with_a_pawn_on_its_last_rank() {
+        // Not a legal position, but a FEN can say so
+        let board = Board::from_fen("8/8/8/8/8/8/4k1K1/R3p3 w - - 0 1");
+        krkp(&board, Color::White);
+    }
+}
@@ -1,19 +1,14 @@
 use crate::board::Board;
-use crate::tables::{EG_PST, EG_VALUE, MG_PST, MG_VALUE};
-use crate::types::{Color, PieceType, CONTEMPT};
+use crate::endgame;
+use crate::pawns::PawnTable;
+use crate::tables::{EG_PST, EG_VALUE, FILE_BB, MG_PST, MG_VALUE, RANK_BB};
+use crate::types::{Color, PieceType};
 
 const MG_ROOK_OPEN: i32 = 55;
 const MG_ROOK_SEMI: i32 = 28;
 const EG_ROOK_OPEN: i32 = 25;
 const EG_ROOK_SEMI: i32 = 14;
 
-const MG_PAWN_ISO: i32 = -20;
-const EG_PAWN_ISO: i32 = -32;
-const MG_PAWN_DOUBLED: i32 = -28;
-const EG_PAWN_DOUBLED: i32 = -50;
-const MG_PAWN_BACKWARD: i32 = -18;
-const EG_PAWN_BACKWARD: i32 = -24;
-
 const MG_BISHOP_PAIR: i32 = 45;
 const EG_BISHOP_PAIR: i32 = 75;
 
@@ -26,34 +21,116 @@ const EG_KNIGHT_OUTPOST: i32 = 22;
 const MG_TEMPO: i32 = 18;
 const MG_KING_SHIELD: i32 = 18;
 
-const FILE_BB: [u64; 8] = [
-    0x0101010101010101,
-    0x0202020202020202,
-    0x0404040404040404,
-    0x0808080808080808,
-    0x1010101010101010,
-    0x2020202020202020,
-    0x4040404040404040,
-    0x8080808080808080,
-];
+const CENTER_BB: u64 = 0x0000001818000000;
+const EXTENDED_CENTER_BB: u64 = 0x00003C3C3C3C0000;
 
-const RANK_BB: [u64; 8] = [
-    0xFF,
-    0xFF00,
-    0xFF0000,
-    0xFF000000,
-    0xFF00000000,
-    0xFF0000000000,
-    0xFF000000000000,
-    0xFF00000000000000,
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    Material = 0,
+    Pst = 1,
+    Pawns = 2,
+    Pieces = 3,
+    Mobility = 4,
+    KingSafety = 5,
+    Threats = 6,
+    Center = 7,
+}
+
+pub const TERM_COUNT: usize = 8;
+
+const TERM_NAMES: [&str; TERM_COUNT] = [
+    "Material",
+    "PST",
+    "Pawns",
+    "Pieces",
+    "Mobility",
+    "King safety",
+    "Threats",
+    "Center",
 ];
 
-const CENTER_BB: u64 = 0x0000001818000000;
-const EXTENDED_CENTER_BB: u64 = 0x00003C3C3C3C0000;
+/// Per-term breakdown of a static evaluation, filled in by `trace`.
+#[derive(Clone, Default)]
+pub struct EvalTrace {
+    pub terms: [[[i32; 2]; 2]; TERM_COUNT], // [term][color][mg, eg]
+    pub phase: i32,
+    pub endgame: Option<&'static str>, // Specialized evaluator or scaling rule that applied
+    pub scale_factor: i32,
+    pub tapered: i32, // White's point of view, before tempo
+    pub tempo: i32,   // White's point of view
+    pub score: i32,   // Side to move's point of view
+}
+
+impl EvalTrace {
+    fn add(&mut self, term: Term, c: usize, mg: i32, eg: i32) {
+        self.terms[term as usize][c][0] += mg;
+        self.terms[term as usize][c][1] += eg;
+    }
+
+    fn total(&self, c: usize, stage: usize) -> i32 {
+        self.terms.iter().map(|t| t[c][stage]).sum()
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut out = String::new();
+        out.push_str("         Term |    White    |    Black    |    Total\n");
+        out.push_str("              |   MG    EG  |   MG    EG  |   MG    EG\n");
+        out.push_str(" -------------+-------------+-------------+------------\n");
+        for (i, name) in TERM_NAMES.iter().enumerate() {
+            let [w, b] = self.terms[i];
+            out.push_str(&format!(
+                " {:>12} | {:5} {:5} | {:5} {:5} | {:5} {:5}\n",
+                name,
+                w[0],
+                w[1],
+                b[0],
+                b[1],
+                w[0] - b[0],
+                w[1] - b[1]
+            ));
+        }
+        out.push_str(" -------------+-------------+-------------+------------\n");
+        let (w_mg, w_eg, b_mg, b_eg) = (
+            self.total(0, 0),
+            self.total(0, 1),
+            self.total(1, 0),
+            self.total(1, 1),
+        );
+        out.push_str(&format!(
+            " {:>12} | {:5} {:5} | {:5} {:5} | {:5} {:5}\n\n",
+            "Total",
+            w_mg,
+            w_eg,
+            b_mg,
+            b_eg,
+            w_mg - b_mg,
+            w_eg - b_eg
+        ));
+        out.push_str(&format!(
+            "Phase:    {} / 24 (middlegame weight)\n",
+            self.phase
+        ));
+        if let Some(name) = self.endgame {
+            out.push_str(&format!("Endgame:  {}\n", name));
+        }
+        out.push_str(&format!(
+            "Scale:    {} / {} (endgame weight)\n",
+            self.scale_factor,
+            endgame::SCALE_NORMAL
+        ));
+        out.push_str(&format!("Tapered:  {} (white side)\n", self.tapered));
+        out.push_str(&format!("Tempo:    {} (white side)\n", self.tempo));
+        out.push_str(&format!("Final evaluation: {} (side to move)", self.score));
+        out
+    }
+}
 
-pub fn evaluate(board: &Board) -> i32 {
-    let mut mg = [0; 2];
-    let mut eg = [0; 2];
+pub fn evaluate(board: &Board, pawn_table: &mut PawnTable) -> i32 {
+    trace(board, pawn_table).score
+}
+
+pub fn trace(board: &Board, pawn_table: &mut PawnTable) -> EvalTrace {
+    let mut t = EvalTrace::default();
     let mut game_phase = 0;
     let mut mobility = [0; 2];
     let mut king_safety_bonus = [0; 2];
@@ -87,6 +164,7 @@ pub fn evaluate(board: &Board) -> i32 {
         board.by_type[PieceType::Pawn as usize] & board.by_color[0],
         board.by_type[PieceType::Pawn as usize] & board.by_color[1],
     ];
+    let pawn_entry = pawn_table.probe(board);
 
     for c in 0..2 {
         let us = if c == 0 { Color::White } else { Color::Black };
@@ -96,9 +174,10 @@ pub fn evaluate(board: &Board) -> i32 {
         let my_pawns = pawns[c];
         let enemy_pawns = pawns[them as usize];
 
+        t.add(Term::Pawns, c, pawn_entry.mg[c], pawn_entry.eg[c]);
+
         if (board.by_type[PieceType::Bishop as usize] & friends).count_ones() >= 2 {
-            mg[c] += MG_BISHOP_PAIR;
-            eg[c] += EG_BISHOP_PAIR;
+            t.add(Term::Pieces, c, MG_BISHOP_PAIR, EG_BISHOP_PAIR);
         }
 
         let mut minor_count = 0;
@@ -111,15 +190,19 @@ pub fn evaluate(board: &Board) -> i32 {
                 let rank = sq / 8;
                 let p_idx = if c == 0 { sq ^ 56 } else { sq };
 
-                mg[c] += MG_VALUE[pt] + MG_PST[pt][p_idx as usize];
-                eg[c] += EG_VALUE[pt] + EG_PST[pt][p_idx as usize];
+                t.add(Term::Material, c, MG_VALUE[pt], EG_VALUE[pt]);
+                t.add(
+                    Term::Pst,
+                    c,
+                    MG_PST[pt][p_idx as usize],
+                    EG_PST[pt][p_idx as usize],
+                );
                 game_phase += PHASE_VALUES[pt];
 
                 // Dev Penalty
                 if (pt == 1 || pt == 2) && (if c == 0 { rank == 0 } else { rank == 7 }) {
-                    mg[c] -= 35;
+                    t.add(Term::Pieces, c, -35, 0);
                 }
-
                 // Center
                 let bit = 1u64 << sq;
                 if (bit & CENTER_BB) != 0 {
@@ -157,31 +240,6 @@ pub fn evaluate(board: &Board) -> i32 {
                 }
 
                 match pt {
-                    0 => {
-                        if (my_pawns & FILE_BB[file] & !(1u64 << sq)) != 0 {
-                            mg[c] += MG_PAWN_DOUBLED;
-                            eg[c] += EG_PAWN_DOUBLED;
-                        }
-                        let left_file = if file > 0 { FILE_BB[file - 1] } else { 0 };
-                        let right_file = if file < 7 { FILE_BB[file + 1] } else { 0 };
-                        if (my_pawns & (left_file | right_file)) == 0 {
-                            mg[c] += MG_PAWN_ISO;
-                            eg[c] += EG_PAWN_ISO;
-                        }
-                        if is_passed_pawn(sq, us, enemy_pawns) {
-                            let r = if c == 0 { rank } else { 7 - rank };
-                            mg[c] += (r as i32 * r as i32) * 10;
-                            eg[c] += (r as i32 * r as i32) * 10;
-                        }
-                        if is_backward_pawn(sq, us, my_pawns, enemy_pawns) {
-                            mg[c] += MG_PAWN_BACKWARD;
-                            eg[c] += EG_PAWN_BACKWARD;
-                        }
-                        if is_connected_pawn(sq, us, my_pawns) {
-                            let r = if c == 0 { rank } else { 7 - rank };
-                            mg[c] += (r as i32) * 6;
-                        }
-                    }
                     1 => {
                         minor_count += 1;
                         mobility[c] +=
@@ -193,11 +251,9 @@ pub fn evaluate(board: &Board) -> i32 {
                         } else {
                             rank >= 2 && rank <= 4
                         }) {
-                            if (crate::tables::ATTACKS.pawn[them as usize][sq as usize] & my_pawns)
-                                != 0
-                            {
-                                mg[c] += MG_KNIGHT_OUTPOST;
-                                eg[c] += EG_KNIGHT_OUTPOST;
+                            let bit = 1u64 << sq;
+                            if (pawn_entry.attacks[c] & bit) != 0 {
+                                t.add(Term::Pieces, c, MG_KNIGHT_OUTPOST, EG_KNIGHT_OUTPOST);
                             }
                         }
                     }
@@ -214,19 +270,16 @@ pub fn evaluate(board: &Board) -> i32 {
                         king_danger[c] +=
                             (piece_attacks & king_ring[them as usize]).count_ones() as i32 * 25; // Increased from 18
                         let file_mask = FILE_BB[file];
-                        if (my_pawns & file_mask) == 0 {
-                            if (enemy_pawns & file_mask) == 0 {
-                                mg[c] += MG_ROOK_OPEN;
-                                eg[c] += EG_ROOK_OPEN;
+                        if pawn_entry.is_semi_open(c, file) {
+                            if pawn_entry.is_semi_open(them as usize, file) {
+                                t.add(Term::Pieces, c, MG_ROOK_OPEN, EG_ROOK_OPEN);
                             } else {
-                                mg[c] += MG_ROOK_SEMI;
-                                eg[c] += EG_ROOK_SEMI;
+                                t.add(Term::Pieces, c, MG_ROOK_SEMI, EG_ROOK_SEMI);
                             }
                         }
                         let r = if c == 0 { rank } else { 7 - rank };
                         if r == 6 {
-                            mg[c] += MG_ROOK_7TH;
-                            eg[c] += EG_ROOK_7TH;
+                            t.add(Term::Pieces, c, MG_ROOK_7TH, EG_ROOK_7TH);
                         }
                         if (FILE_BB[king_sq[them as usize] as usize % 8] & file_mask) != 0 {
                             king_danger[c] += 20; // Increased from 15
@@ -248,7 +301,7 @@ pub fn evaluate(board: &Board) -> i32 {
                                     | (if file < 7 { FILE_BB[file + 1] } else { 0 }))
                                 & my_pawns;
                             king_safety_bonus[c] += (shield.count_ones() as i32) * MG_KING_SHIELD;
-                            if (FILE_BB[file] & my_pawns) == 0 {
+                            if pawn_entry.is_semi_open(c, file) {
                                 king_danger[them as usize] += 25;
                             }
                         }
@@ -260,46 +313,63 @@ pub fn evaluate(board: &Board) -> i32 {
         }
 
         if minor_count > 1 && game_phase < 15 {
-            mg[c] += 20;
+            t.add(Term::Pieces, c, 20, 0);
         }
     }
 
-    let total_mg = [
-        mg[0] + mobility[0] + king_safety_bonus[0] + piece_threats[0] + center_control[0]
-            - (king_danger[1] * king_danger[1] / 50),
-        mg[1] + mobility[1] + king_safety_bonus[1] + piece_threats[1] + center_control[1]
-            - (king_danger[0] * king_danger[0] / 50),
-    ];
+    for c in 0..2 {
+        let them = c ^ 1;
+        t.add(Term::Mobility, c, mobility[c], mobility[c]);
+        t.add(
+            Term::KingSafety,
+            c,
+            king_safety_bonus[c] - (king_danger[them] * king_danger[them] / 50),
+            0,
+        );
+        t.add(Term::Threats, c, piece_threats[c], piece_threats[c] / 2);
+        t.add(Term::Center, c, center_control[c], center_control[c] / 3);
+    }
 
-    let total_eg = [
-        eg[0] + mobility[0] + piece_threats[0] / 2 + center_control[0] / 3,
-        eg[1] + mobility[1] + piece_threats[1] / 2 + center_control[1] / 3,
-    ];
+    let total_mg = [t.total(0, 0), t.total(1, 0)];
+    let total_eg = [t.total(0, 1), t.total(1, 1)];
 
     let mg_phase = game_phase.min(24);
     let eg_phase = 24 - mg_phase;
 
-    let mut score =
-        ((total_mg[0] - total_mg[1]) * mg_phase + (total_eg[0] - total_eg[1]) * eg_phase) / 24;
-
-    if board.side_to_move == Color::White {
-        score += MG_TEMPO;
+    let strong_side = if total_eg[0] >= total_eg[1] {
+        Color::White
     } else {
-        score -= MG_TEMPO;
+        Color::Black
+    };
+    let probe = endgame::probe(board);
+    let (scale_factor, scale_rule) = endgame::scale_factor(board, strong_side, &pawn_entry, &probe);
+
+    t.phase = mg_phase;
+    t.scale_factor = scale_factor;
+    t.endgame = scale_rule;
+    t.tapered = ((total_mg[0] - total_mg[1]) * mg_phase
+        + (total_eg[0] - total_eg[1]) * eg_phase * scale_factor / endgame::SCALE_NORMAL)
+        / 24;
+    t.tempo = MG_TEMPO;
+
+    if let Some((name, value)) = endgame::probe_value(board, &probe) {
+        t.endgame = Some(name);
+        t.scale_factor = endgame::SCALE_NORMAL;
+        t.tapered = value;
+        t.tempo = 0;
     }
 
-    let contempt = CONTEMPT.load(std::sync::atomic::Ordering::Relaxed);
-    if board.side_to_move == Color::White {
-        score += contempt;
-    } else {
-        score -= contempt;
+    if board.side_to_move == Color::Black {
+        t.tempo = -t.tempo;
     }
 
-    if board.side_to_move == Color::White {
+    let score = t.tapered + t.tempo;
+    t.score = if board.side_to_move == Color::White {
         score
     } else {
         -score
-    }
+    };
+    t
 }
 
 fn get_king_ring(sq: u8) -> u64 {
@@ -324,75 +394,3 @@ fn get_king_ring(sq: u8) -> u64 {
     }
     ring
 }
-
-pub fn is_passed_pawn(sq: u8, color: Color, enemy_pawns: u64) -> bool {
-    let file = (sq % 8) as i16;
-    let rank = (sq / 8) as i16;
-    let mut mask = 0u64;
-    for f in (file - 1).max(0)..=(file + 1).min(7) {
-        if color == Color::White {
-            for r in (rank + 1)..8 {
-                mask |= 1u64 << (r * 8 + f);
-            }
-        } else {
-            for r in 0..rank {
-                mask |= 1u64 << (r * 8 + f);
-            }
-        }
-    }
-    (mask & enemy_pawns) == 0
-}
-
-fn is_backward_pawn(sq: u8, color: Color, my_pawns: u64, enemy_pawns: u64) -> bool {
-    let file = (sq % 8) as i16;
-    let rank = (sq / 8) as i16;
-    let stop_sq = if color == Color::White {
-        if rank >= 7 {
-            return false;
-        }
-        sq + 8
-    } else {
-        if rank <= 0 {
-            return false;
-        }
-        sq - 8
-    };
-    let mut neighbors = 0u64;
-    for f in (file - 1).max(0)..=(file + 1).min(7) {
-        if f == file {
-            continue;
-        }
-        if color == Color::White {
-            for r in 0..=rank {
-                neighbors |= 1u64 << (r * 8 + f);
-            }
-        } else {
-            for r in rank..8 {
-                neighbors |= 1u64 << (r * 8 + f);
-            }
-        }
-    }
-    if (my_pawns & neighbors) == 0 {
-        let enemy_attacks =
-            crate::tables::ATTACKS.pawn[color.opponent() as usize][stop_sq as usize];
-        if (enemy_pawns & enemy_attacks) != 0 {
-            return true;
-        }
-    }
-    false
-}
-
-fn is_connected_pawn(sq: u8, color: Color, my_pawns: u64) -> bool {
-    let file = (sq % 8) as i16;
-    let rank = (sq / 8) as i16;
-    let mut neighbors = 0u64;
-    for f in (file - 1).max(0)..=(file + 1).min(7) {
-        if f == file {
-            continue;
-        }
-        for r in (rank - 1).max(0)..=(rank + 1).min(7) {
-            neighbors |= 1u64 << (r * 8 + f);
-        }
-    }
-    (my_pawns & neighbors) != 0
-}
@@ -0,0 +1,217 @@
+use crate::types::Move;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+pub const DEFAULT_FILE: &str = "experience.bin";
+
+/// Searches shallower than this aren't worth remembering.
+pub const MIN_DEPTH: u8 = 4;
+
+const RECORD_SIZE: usize = 23;
+
+/// The engine counts a game as lost when its last score is below this.
+const LOSS_SCORE: i32 = -300;
+
+/// Share of the later score taken over when a game is learned backwards, in percent.
+const LEARNING_RATE: i32 = 50;
+
+/// One remembered root move. On disk it is 23 little-endian bytes: key u64,
+/// move u16, score i32, depth u8, losses u32, visits u32.
+#[derive(Debug, Clone, Copy)]
+pub struct ExperienceEntry {
+    pub key: u64,
+    pub m: Move,
+    pub score: i32,
+    pub depth: u8,
+    pub losses: u32, // Games lost after playing the move
+    pub visits: u32, // Times the move was played
+}
+
+impl ExperienceEntry {
+    fn from_bytes(b: &[u8; RECORD_SIZE]) -> Self {
+        ExperienceEntry {
+            key: u64::from_le_bytes(b[0..8].try_into().unwrap()),
+            m: Move::from_u16(u16::from_le_bytes([b[8], b[9]])),
+            score: i32::from_le_bytes(b[10..14].try_into().unwrap()),
+            depth: b[14],
+            losses: u32::from_le_bytes(b[15..19].try_into().unwrap()),
+            visits: u32::from_le_bytes(b[19..23].try_into().unwrap()),
+        }
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.key.to_le_bytes())?;
+        out.write_all(&self.m.to_u16().to_le_bytes())?;
+        out.write_all(&self.score.to_le_bytes())?;
+        out.write_all(&[self.depth])?;
+        out.write_all(&self.losses.to_le_bytes())?;
+        out.write_all(&self.visits.to_le_bytes())
+    }
+
+    /// Lost in more than half of the games it was played in.
+    pub fn is_losing(&self) -> bool {
+        self.losses * 2 > self.visits
+    }
+}
+
+/// Root moves and scores from earlier deep searches, kept in a file across
+/// sessions. Each search is appended as it finishes, and at the end of a game
+/// its scores are learned backwards and the file is rewritten.
+pub struct Experience {
+    pub enabled: bool,
+    path: String,
+    entries: HashMap<u64, Vec<ExperienceEntry>>,
+    game: Vec<(u64, Move)>, // Searches recorded during the current game, in order
+}
+
+impl Default for Experience {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Experience {
+    /// Off until a front end turns it on, so that nothing is written to the
+    /// working directory unasked.
+    pub fn new() -> Self {
+        Experience {
+            enabled: false,
+            path: DEFAULT_FILE.to_string(),
+            entries: HashMap::new(),
+            game: Vec::new(),
+        }
+    }
+
+    pub fn set_path(&mut self, path: &str) {
+        self.path = path.to_string();
+        self.entries.clear();
+        self.game.clear();
+    }
+
+    /// Reads the file, later records of a key and move replacing earlier ones.
+    /// A missing file is just an empty experience.
+    pub fn load(&mut self) -> io::Result<usize> {
+        self.entries.clear();
+        let file = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut reader = BufReader::new(file);
+        let mut buf = [0u8; RECORD_SIZE];
+        let mut count = 0;
+        loop {
+            match reader.read_exact(&mut buf) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+            self.insert(ExperienceEntry::from_bytes(&buf));
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    pub fn probe(&self, key: u64) -> &[ExperienceEntry] {
+        self.entries.get(&key).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn find(&self, key: u64, m: Move) -> Option<&ExperienceEntry> {
+        self.probe(key).iter().find(|e| e.m == m)
+    }
+
+    /// The best scoring move that hasn't mostly lost.
+    pub fn best(&self, key: u64) -> Option<&ExperienceEntry> {
+        self.probe(key)
+            .iter()
+            .filter(|e| !e.is_losing())
+            .max_by_key(|e| (e.score, e.depth))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ExperienceEntry> {
+        self.entries.values().flatten()
+    }
+
+    fn insert(&mut self, entry: ExperienceEntry) {
+        let list = self.entries.entry(entry.key).or_default();
+        match list.iter_mut().find(|e| e.m == entry.m) {
+            Some(e) => *e = entry,
+            None => list.push(entry),
+        }
+    }
+
+    /// Remembers the result of a finished search and appends it to the file.
+    pub fn record(&mut self, key: u64, m: Move, score: i32, depth: u8) -> io::Result<()> {
+        if !self.enabled || depth < MIN_DEPTH {
+            return Ok(());
+        }
+        let entry = match self.find(key, m) {
+            Some(old) => ExperienceEntry {
+                score: if depth >= old.depth { score } else { old.score },
+                depth: depth.max(old.depth),
+                visits: old.visits + 1,
+                ..*old
+            },
+            None => ExperienceEntry {
+                key,
+                m,
+                score,
+                depth,
+                losses: 0,
+                visits: 1,
+            },
+        };
+        self.insert(entry);
+        self.game.push((key, m));
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut out = BufWriter::new(file);
+        entry.write_to(&mut out)?;
+        out.flush()
+    }
+
+    /// Learns the game just played: each score moves towards the one the
+    /// engine saw on its next move, and every move of a lost game counts a
+    /// loss. The whole file is then rewritten without duplicates.
+    pub fn end_game(&mut self) -> io::Result<()> {
+        let game = std::mem::take(&mut self.game);
+        if !self.enabled || game.is_empty() {
+            return Ok(());
+        }
+
+        let &(last_key, last_move) = game.last().unwrap();
+        let lost = self
+            .find(last_key, last_move)
+            .is_some_and(|e| e.score <= LOSS_SCORE);
+
+        let mut next_score = None;
+        for &(key, m) in game.iter().rev() {
+            let Some(list) = self.entries.get_mut(&key) else {
+                continue;
+            };
+            let Some(e) = list.iter_mut().find(|e| e.m == m) else {
+                continue;
+            };
+            if let Some(next) = next_score {
+                e.score = (e.score * (100 - LEARNING_RATE) + next * LEARNING_RATE) / 100;
+            }
+            if lost {
+                e.losses += 1;
+            }
+            next_score = Some(e.score);
+        }
+        self.save()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(&self.path)?);
+        for entry in self.iter() {
+            entry.write_to(&mut out)?;
+        }
+        out.flush()
+    }
+}
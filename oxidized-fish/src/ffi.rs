@@ -0,0 +1,331 @@
+//! C API for embedding the engine. `include/oxidized_fish.h` is generated
+//! from this file with `cbindgen --config cbindgen.toml --output include/oxidized_fish.h`,
+//! and `examples/ffi_test.c` shows the calls in order.
+//!
+//! An engine handle is not thread safe: call it from one thread at a time.
+//! The search itself runs on a thread of its own, which is where the info
+//! callback is called from.
+
+use crate::board::Board;
+use crate::game::GameState;
+use crate::movegen::parse_move;
+use crate::search::{InfoCallback, Search, SearchInfo};
+use crate::types::Move;
+use std::ffi::{c_char, c_void, CStr};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Bumped whenever a signature or struct layout here changes.
+const API_VERSION: u32 = 1;
+
+const MAX_DEPTH: u32 = 64;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OxStatus {
+    Ok = 0,
+    InvalidArgument = 1,
+    InvalidFen = 2,
+    IllegalMove = 3,
+    Busy = 4,
+    NoMove = 5,
+}
+
+/// Search limits, where 0 means no limit. A search without any runs until
+/// `ox_engine_stop`.
+#[repr(C)]
+pub struct OxLimits {
+    pub depth: u32,
+    pub movetime_ms: u64,
+    pub nodes: u64,
+    pub multipv: u32, // Lines to report, 0 is the same as 1
+}
+
+/// Progress of a search, only valid during the callback.
+#[repr(C)]
+pub struct OxInfo {
+    pub depth: u32,
+    pub multipv: u32,  // 1 for the best line
+    pub score_cp: i32, // For the side to move
+    pub mate: i32,     // Moves to mate, negative when getting mated, 0 if none
+    pub nodes: u64,
+    pub time_ms: u64,
+    pub pv: *const c_char, // Space separated UCI moves
+}
+
+pub type OxInfoCallback = Option<unsafe extern "C" fn(info: *const OxInfo, user_data: *mut c_void)>;
+
+/// An engine with its own hash table and position.
+pub struct OxEngine {
+    game: GameState,
+    searcher: Option<Search>, // None while the search thread owns it
+    search_thread: Option<JoinHandle<(Search, Option<Move>)>>,
+    stop_flag: Arc<AtomicBool>,
+    best_move: Option<Move>,
+}
+
+/// The callback's user data, handed to the search thread as is.
+struct UserData(*mut c_void);
+unsafe impl Send for UserData {}
+
+impl OxEngine {
+    fn is_searching(&self) -> bool {
+        self.search_thread.is_some()
+    }
+
+    fn join(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            let (searcher, m) = handle.join().expect("Search thread panicked");
+            self.searcher = Some(searcher);
+            self.best_move = m;
+        }
+    }
+
+    fn stop(&mut self) {
+        if self.search_thread.is_some() {
+            self.stop_flag.store(true, Ordering::Relaxed);
+            self.join();
+        }
+    }
+}
+
+/// The version of this API, for checking against the header the caller was
+/// built with.
+#[no_mangle]
+pub extern "C" fn ox_api_version() -> u32 {
+    API_VERSION
+}
+
+/// The engine version as a NUL-terminated string.
+#[no_mangle]
+pub extern "C" fn ox_version() -> *const c_char {
+    c"Oxidized Fish 0.7-Hardened".as_ptr()
+}
+
+/// A new engine at the start position with a `hash_mb` hash table, or the
+/// default size for 0. Free it with `ox_engine_free`.
+#[no_mangle]
+pub extern "C" fn ox_engine_new(hash_mb: usize) -> *mut OxEngine {
+    let searcher = if hash_mb == 0 {
+        Search::new()
+    } else {
+        Search::with_hash(hash_mb)
+    };
+    Box::into_raw(Box::new(OxEngine {
+        game: GameState::new(Board::new()),
+        stop_flag: searcher.stop_flag.clone(),
+        searcher: Some(searcher),
+        search_thread: None,
+        best_move: None,
+    }))
+}
+
+/// Stops any search and frees the engine.
+///
+/// # Safety
+/// `engine` must come from `ox_engine_new` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ox_engine_free(engine: *mut OxEngine) {
+    if engine.is_null() {
+        return;
+    }
+    let mut engine = Box::from_raw(engine);
+    engine.stop();
+}
+
+/// Sets the position from a FEN, or the start position when `fen` is NULL,
+/// followed by `move_count` moves in UCI notation. The position is left
+/// unchanged on error.
+///
+/// # Safety
+/// `engine` must be live, `fen` NULL or NUL-terminated, and `moves` must
+/// point to `move_count` NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn ox_engine_set_position(
+    engine: *mut OxEngine,
+    fen: *const c_char,
+    moves: *const *const c_char,
+    move_count: usize,
+) -> OxStatus {
+    let Some(engine) = engine.as_mut() else {
+        return OxStatus::InvalidArgument;
+    };
+    if engine.is_searching() {
+        return OxStatus::Busy;
+    }
+    let board = if fen.is_null() {
+        Board::new()
+    } else {
+        let Ok(fen) = CStr::from_ptr(fen).to_str() else {
+            return OxStatus::InvalidFen;
+        };
+        if !Board::is_valid_fen(fen) {
+            return OxStatus::InvalidFen;
+        }
+        Board::from_fen(fen)
+    };
+    if move_count > 0 && moves.is_null() {
+        return OxStatus::InvalidArgument;
+    }
+
+    let mut game = GameState::new(board);
+    for i in 0..move_count {
+        let m_ptr = *moves.add(i);
+        if m_ptr.is_null() {
+            return OxStatus::InvalidArgument;
+        }
+        let m = CStr::from_ptr(m_ptr)
+            .to_str()
+            .ok()
+            .and_then(|m_str| parse_move(&game.board, m_str));
+        match m {
+            Some(m) => game.make_move(m),
+            None => return OxStatus::IllegalMove,
+        }
+    }
+    engine.game = game;
+    OxStatus::Ok
+}
+
+/// Starts searching the current position and returns at once. `callback`
+/// may be NULL; it is called on the search thread with `user_data`.
+///
+/// # Safety
+/// `engine` must be live and `limits` NULL (no limits) or valid.
+#[no_mangle]
+pub unsafe extern "C" fn ox_engine_start_search(
+    engine: *mut OxEngine,
+    limits: *const OxLimits,
+    callback: OxInfoCallback,
+    user_data: *mut c_void,
+) -> OxStatus {
+    let Some(engine) = engine.as_mut() else {
+        return OxStatus::InvalidArgument;
+    };
+    if engine.is_searching() {
+        return OxStatus::Busy;
+    }
+    let Some(mut searcher) = engine.searcher.take() else {
+        return OxStatus::Busy;
+    };
+
+    let (depth, movetime, nodes, multipv) = match limits.as_ref() {
+        Some(l) => (l.depth, l.movetime_ms, l.nodes, l.multipv),
+        None => (0, 0, 0, 0),
+    };
+    let depth = if depth == 0 {
+        MAX_DEPTH
+    } else {
+        depth.min(MAX_DEPTH)
+    };
+    let time_limit = (movetime > 0).then(|| Duration::from_millis(movetime));
+    searcher.node_limit = (nodes > 0).then_some(nodes);
+    searcher.multi_pv = (multipv as usize).clamp(1, 64);
+
+    searcher.on_info = callback.map(|callback| {
+        let user_data = UserData(user_data);
+        Box::new(move |info: &SearchInfo| {
+            // Use the wrapper whole, the closure would capture the bare pointer otherwise
+            let user_data = &user_data;
+            unsafe { report(callback, info, user_data.0) };
+        }) as InfoCallback
+    });
+
+    engine.best_move = None;
+    let game = engine.game.clone();
+    engine.stop_flag.store(false, Ordering::Relaxed);
+    engine.search_thread = Some(thread::spawn(move || {
+        let m = searcher.go(&game, depth as u8, time_limit);
+        searcher.on_info = None;
+        (searcher, m)
+    }));
+    OxStatus::Ok
+}
+
+/// Stops the search and returns once it has ended.
+///
+/// # Safety
+/// `engine` must be live.
+#[no_mangle]
+pub unsafe extern "C" fn ox_engine_stop(engine: *mut OxEngine) {
+    if let Some(engine) = engine.as_mut() {
+        engine.stop();
+    }
+}
+
+/// Returns once the search has ended by its limits.
+///
+/// # Safety
+/// `engine` must be live.
+#[no_mangle]
+pub unsafe extern "C" fn ox_engine_wait(engine: *mut OxEngine) {
+    if let Some(engine) = engine.as_mut() {
+        engine.join();
+    }
+}
+
+/// Whether a search is still running.
+///
+/// # Safety
+/// `engine` must be live.
+#[no_mangle]
+pub unsafe extern "C" fn ox_engine_is_searching(engine: *const OxEngine) -> bool {
+    engine.as_ref().is_some_and(|e| {
+        e.search_thread
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    })
+}
+
+/// Waits for the search and writes its best move in UCI notation into
+/// `buf`, which needs room for 6 bytes.
+///
+/// # Safety
+/// `engine` must be live and `buf` point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ox_engine_best_move(
+    engine: *mut OxEngine,
+    buf: *mut c_char,
+    len: usize,
+) -> OxStatus {
+    let Some(engine) = engine.as_mut() else {
+        return OxStatus::InvalidArgument;
+    };
+    if buf.is_null() {
+        return OxStatus::InvalidArgument;
+    }
+    engine.join();
+    let Some(m) = engine.best_move else {
+        return OxStatus::NoMove;
+    };
+    let text = m.to_string();
+    if text.len() + 1 > len {
+        return OxStatus::InvalidArgument;
+    }
+    ptr::copy_nonoverlapping(text.as_ptr() as *const c_char, buf, text.len());
+    *buf.add(text.len()) = 0;
+    OxStatus::Ok
+}
+
+unsafe fn report(
+    callback: unsafe extern "C" fn(*const OxInfo, *mut c_void),
+    info: &SearchInfo,
+    user_data: *mut c_void,
+) {
+    let pv: Vec<String> = info.pv.iter().map(|m| m.to_string()).collect();
+    let mut pv = pv.join(" ").into_bytes();
+    pv.push(0);
+    let info = OxInfo {
+        depth: info.depth as u32,
+        multipv: info.multipv.unwrap_or(1) as u32,
+        score_cp: info.score,
+        mate: info.mate_in().unwrap_or(0),
+        nodes: info.nodes,
+        time_ms: info.time_ms,
+        pv: pv.as_ptr() as *const c_char,
+    };
+    callback(&info, user_data);
+}
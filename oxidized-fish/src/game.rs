@@ -0,0 +1,206 @@
+use crate::board::Board;
+use crate::types::{Color, Move};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawReason {
+    Stalemate,
+    FiftyMoves,
+    InsufficientMaterial,
+    Repetition,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    Checkmate(Color), // Winner
+    Draw(DrawReason),
+}
+
+#[allow(dead_code)]
+impl GameResult {
+    pub fn to_pgn(self) -> &'static str {
+        match self {
+            GameResult::Checkmate(Color::White) => "1-0",
+            GameResult::Checkmate(Color::Black) => "0-1",
+            GameResult::Draw(_) => "1/2-1/2",
+        }
+    }
+
+    pub fn reason(self) -> &'static str {
+        match self {
+            GameResult::Checkmate(Color::White) => "White mates",
+            GameResult::Checkmate(Color::Black) => "Black mates",
+            GameResult::Draw(DrawReason::Stalemate) => "Stalemate",
+            GameResult::Draw(DrawReason::FiftyMoves) => "Draw by fifty move rule",
+            GameResult::Draw(DrawReason::InsufficientMaterial) => "Insufficient material",
+            GameResult::Draw(DrawReason::Repetition) => "Draw by repetition",
+        }
+    }
+}
+
+/// A game from its starting position: the current board along with every
+/// position and move that led to it.
+#[derive(Clone)]
+pub struct GameState {
+    pub board: Board,
+    boards: Vec<Board>, // Positions before each move, for undo
+    hashes: Vec<u64>,   // Every position of the game, the current one last
+    moves: Vec<Move>,
+}
+
+#[allow(dead_code)]
+impl GameState {
+    pub fn new(board: Board) -> Self {
+        GameState {
+            board,
+            boards: Vec::new(),
+            hashes: vec![board.hash],
+            moves: Vec::new(),
+        }
+    }
+
+    pub fn make_move(&mut self, m: Move) {
+        self.boards.push(self.board);
+        self.board = self.board.make_move(m);
+        self.hashes.push(self.board.hash);
+        self.moves.push(m);
+    }
+
+    /// Takes back the last move, returning it.
+    pub fn undo(&mut self) -> Option<Move> {
+        let m = self.moves.pop()?;
+        self.board = self.boards.pop().expect("Board history out of sync");
+        self.hashes.pop();
+        Some(m)
+    }
+
+    pub fn start_board(&self) -> &Board {
+        self.boards.first().unwrap_or(&self.board)
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    pub fn hashes(&self) -> &[u64] {
+        &self.hashes
+    }
+
+    /// Earlier occurrences of the current position.
+    pub fn repetition_count(&self) -> usize {
+        count_repetitions(&self.hashes, self.board.halfmove_clock)
+    }
+
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.repetition_count() >= 2
+    }
+
+    pub fn is_draw(&self) -> bool {
+        matches!(self.result(), Some(GameResult::Draw(_)))
+    }
+
+    /// The result if the game is over by the rules, checkmate taking priority.
+    pub fn result(&self) -> Option<GameResult> {
+        if !self.board.has_legal_moves() {
+            return Some(if self.board.is_in_check() {
+                GameResult::Checkmate(self.board.side_to_move.opponent())
+            } else {
+                GameResult::Draw(DrawReason::Stalemate)
+            });
+        }
+        if self.board.halfmove_clock >= 100 {
+            return Some(GameResult::Draw(DrawReason::FiftyMoves));
+        }
+        if self.board.is_insufficient_material() {
+            return Some(GameResult::Draw(DrawReason::InsufficientMaterial));
+        }
+        if self.is_threefold_repetition() {
+            return Some(GameResult::Draw(DrawReason::Repetition));
+        }
+        None
+    }
+}
+
+/// Counts earlier occurrences of the last position in `hashes`. Captures and
+/// pawn moves can't be undone, so only the last `halfmove_clock` plies are
+/// searched, and only those with the same side to move.
+pub fn count_repetitions(hashes: &[u64], halfmove_clock: u8) -> usize {
+    let Some(&current) = hashes.last() else {
+        return 0;
+    };
+    let last = hashes.len() - 1;
+    let window = (halfmove_clock as usize).min(last);
+    hashes[last - window..last]
+        .iter()
+        .rev()
+        .skip(1)
+        .step_by(2)
+        .filter(|&&h| h == current)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::parse_move;
+
+    fn play(game: &mut GameState, moves: &str) {
+        for m in moves.split_whitespace() {
+            let m = parse_move(&game.board, m).expect("Illegal test move");
+            game.make_move(m);
+        }
+    }
+
+    #[test]
+    fn counts_repetitions_by_side_to_move() {
+        assert_eq!(count_repetitions(&[], 0), 0);
+        assert_eq!(count_repetitions(&[1, 2, 1, 2, 1], 4), 2);
+        // The same hash with the other side to move doesn't count
+        assert_eq!(count_repetitions(&[1, 1, 2, 1], 3), 1);
+        // Nor does one from before the last capture or pawn move
+        assert_eq!(count_repetitions(&[1, 2, 1, 2, 1], 2), 1);
+    }
+
+    #[test]
+    fn threefold_repetition_through_undo() {
+        let mut game = GameState::new(Board::new());
+        play(&mut game, "g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1");
+        assert_eq!(game.repetition_count(), 1);
+        assert_eq!(game.result(), None);
+
+        play(&mut game, "f6g8");
+        assert_eq!(game.repetition_count(), 2);
+        assert_eq!(
+            game.result(),
+            Some(GameResult::Draw(DrawReason::Repetition))
+        );
+
+        game.undo();
+        assert!(!game.is_threefold_repetition());
+        assert_eq!(game.result(), None);
+        play(&mut game, "f6g8");
+        assert!(game.is_threefold_repetition());
+    }
+
+    #[test]
+    fn results_by_the_rules() {
+        let result = |fen: &str| GameState::new(Board::from_fen(fen)).result();
+        assert_eq!(
+            result("8/8/8/4k3/8/8/8/4K2R w - - 100 80"),
+            Some(GameResult::Draw(DrawReason::FiftyMoves))
+        );
+        assert_eq!(result("8/8/8/4k3/8/8/8/4K2R w - - 99 80"), None);
+        assert_eq!(
+            result("8/8/8/4k3/8/8/8/4KN2 w - - 0 1"),
+            Some(GameResult::Draw(DrawReason::InsufficientMaterial))
+        );
+        assert_eq!(
+            result("7k/5K2/6Q1/8/8/8/8/8 b - - 0 1"),
+            Some(GameResult::Draw(DrawReason::Stalemate))
+        );
+        // Mate on the hundredth ply beats the fifty-move rule
+        assert_eq!(
+            result("7k/6Q1/6K1/8/8/8/8/8 b - - 100 90"),
+            Some(GameResult::Checkmate(Color::White))
+        );
+    }
+}
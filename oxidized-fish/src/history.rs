@@ -0,0 +1,87 @@
+//! What the search learns about moves for ordering and reductions. Every
+//! table is rewarded on a cutoff and penalised for the moves tried before
+//! it, through the same gravity formula: an entry moves less the closer it
+//! already is to `MAX_HISTORY`, so it stays in range and new results
+//! outweigh old ones.
+
+use crate::board::Board;
+use crate::types::{Move, PieceType};
+
+pub const MAX_HISTORY: i32 = 16384;
+
+/// Moves `entry` by `bonus`, a penalty when negative.
+pub fn apply_gravity(entry: &mut i32, bonus: i32) {
+    let bonus = bonus.clamp(-MAX_HISTORY, MAX_HISTORY);
+    *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+}
+
+/// The reward for a cutoff at `depth`, and the penalty for failing to cut.
+pub fn bonus(depth: u8) -> i32 {
+    let depth = depth as i32;
+    (16 * depth * depth + 32 * depth).min(1536)
+}
+
+/// A move as its piece, by color and type, and its target square.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PieceTo {
+    pub piece: usize, // Color * 6 + piece type
+    pub to: usize,
+}
+
+impl PieceTo {
+    pub fn new(board: &Board, m: Move) -> Self {
+        let piece = match board.get_piece_at(m.from()) {
+            Some((piece, color)) => color as usize * 6 + piece as usize,
+            None => 0,
+        };
+        PieceTo {
+            piece,
+            to: m.to() as usize,
+        }
+    }
+}
+
+/// Scores by the piece moved and its target square.
+pub type PieceToHistory = [[i32; 64]; 12];
+
+/// Quiet move scores following each move, for the one played one ply
+/// before and the one two plies before.
+pub struct ContinuationHistory(Vec<PieceToHistory>);
+
+impl Default for ContinuationHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContinuationHistory {
+    pub fn new() -> Self {
+        ContinuationHistory(vec![[[0; 64]; 12]; 12 * 64])
+    }
+
+    pub fn clear(&mut self) {
+        for table in self.0.iter_mut() {
+            *table = [[0; 64]; 12];
+        }
+    }
+
+    pub fn get(&self, prev: PieceTo) -> &PieceToHistory {
+        &self.0[prev.piece * 64 + prev.to]
+    }
+
+    pub fn get_mut(&mut self, prev: PieceTo) -> &mut PieceToHistory {
+        &mut self.0[prev.piece * 64 + prev.to]
+    }
+}
+
+/// Capture scores by the piece moved, its target square and the type of
+/// piece taken.
+pub type CaptureHistory = [[[i32; 6]; 64]; 12];
+
+/// The type of piece `m` takes, a pawn for en passant.
+pub fn captured(board: &Board, m: Move) -> PieceType {
+    board
+        .get_piece_at(m.to())
+        .map(|(p, _)| p)
+        .unwrap_or(PieceType::Pawn)
+}
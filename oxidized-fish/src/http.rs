@@ -0,0 +1,241 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+/// Largest request body accepted.
+const MAX_BODY: usize = 64 * 1024;
+/// Longest request or header line accepted, line ending included.
+const MAX_LINE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Header lookup, ignoring case as HTTP does.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Reads one request. Anything left in the reader's buffer after the body
+/// is only expected for WebSocket upgrades, where the client waits for the
+/// handshake first.
+pub fn read_request(stream: impl Read) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    read_line(&mut reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(invalid("Malformed request line"));
+    };
+    let method = method.to_string();
+    let (path, query) = match target.split_once('?') {
+        Some((p, q)) => (url_decode(p), parse_query(q)),
+        None => (url_decode(target), Vec::new()),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        if read_line(&mut reader, &mut line)? == 0 {
+            break;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(invalid("Too many headers"));
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let length = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, v)| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(invalid("Request body too large"));
+    }
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request {
+        method,
+        path,
+        query,
+        headers,
+        body,
+    })
+}
+
+/// Replaces `line` with the next line, refusing one longer than `MAX_LINE`
+/// rather than buffering whatever a client sends.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    line.clear();
+    let n = reader.take(MAX_LINE as u64 + 1).read_line(line)?;
+    if n > MAX_LINE {
+        return Err(invalid("Request line too long"));
+    }
+    Ok(n)
+}
+
+pub fn respond(
+    stream: &mut TcpStream,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason(status),
+        content_type,
+        body.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+pub fn respond_json(stream: &mut TcpStream, status: u16, body: &str) -> io::Result<()> {
+    respond(stream, status, "application/json", body.as_bytes())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        101 => "Switching Protocols",
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((k, v)) => (url_decode(k), url_decode(v)),
+            None => (url_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+/// Decodes `%XX` escapes and `+` as a space.
+pub fn url_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    Err(_) => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(text: &str) -> io::Result<Request> {
+        read_request(text.as_bytes())
+    }
+
+    #[test]
+    fn reads_a_request() {
+        let request = read(
+            "POST /analyze?fen=8%2F8+w&depth=5&flag HTTP/1.1\r\n\
+             Host: localhost\r\ncontent-length: 4\r\n\r\nbodyextra",
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/analyze");
+        assert_eq!(request.query_param("fen"), Some("8/8 w"));
+        assert_eq!(request.query_param("depth"), Some("5"));
+        assert_eq!(request.query_param("flag"), Some(""));
+        assert_eq!(request.header("Content-Length"), Some("4"));
+        assert_eq!(request.body, b"body");
+    }
+
+    #[test]
+    fn decodes_urls() {
+        assert_eq!(url_decode("a%20b+c%2Fd"), "a b c/d");
+        assert_eq!(url_decode("100%"), "100%");
+        assert_eq!(url_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn rejects_malformed_request_lines() {
+        assert!(read("\r\n\r\n").is_err());
+        assert!(read("GET\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn rejects_long_lines() {
+        let long_target = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE));
+        assert!(read(&long_target).is_err());
+        let long_header = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(MAX_LINE));
+        assert!(read(&long_header).is_err());
+        let fits = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(MAX_LINE - 10));
+        assert!(read(&fits).is_ok());
+    }
+
+    #[test]
+    fn rejects_too_many_headers() {
+        let headers = |n: usize| -> String {
+            let lines: String = (0..n).map(|i| format!("X-{}: 1\r\n", i)).collect();
+            format!("GET / HTTP/1.1\r\n{}\r\n", lines)
+        };
+        assert!(read(&headers(MAX_HEADERS)).is_ok());
+        assert!(read(&headers(MAX_HEADERS + 1)).is_err());
+    }
+
+    #[test]
+    fn rejects_large_or_short_bodies() {
+        let large = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        assert!(read(&large).is_err());
+        assert!(read("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort").is_err());
+    }
+}
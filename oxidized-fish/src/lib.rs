@@ -0,0 +1,30 @@
+//! The engine core: board, move generation, evaluation and search. It builds
+//! for `wasm32-unknown-unknown` as well, where `wasm` exports it to the browser,
+//! and `ffi` exports it to C elsewhere.
+//! The protocol front ends and the server live in the binary.
+
+pub mod bitboard;
+pub mod board;
+pub mod endgame;
+pub mod eval;
+pub mod experience;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ffi;
+pub mod game;
+pub mod history;
+pub mod mate;
+pub mod movegen;
+pub mod params;
+pub mod pawns;
+pub mod pgn;
+pub mod platform;
+pub mod search;
+pub mod skill;
+pub mod stats;
+pub mod tables;
+pub mod trace;
+pub mod types;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+pub mod wdl;
+pub mod zobrist;
@@ -1,13 +1,46 @@
-mod bitboard;
-mod board;
-mod eval;
-mod movegen;
-mod search;
-mod tables;
-mod types;
+mod annotate;
+mod http;
+mod json;
+mod match_runner;
+mod puzzles;
+mod search_tree;
+mod server;
+mod spsa;
 mod uci;
-mod zobrist;
+mod uci_engine;
+mod wdl_fit;
+mod websocket;
+mod xboard;
+
+// The front ends reach the core through `crate::`, as they did when it was
+// part of the binary
+use oxidized_fish::{
+    board, eval, experience, game, mate, movegen, params, pgn, search, skill, tables, types, wdl,
+};
+
+use std::io::{self, BufRead};
 
 fn main() {
-    uci::start_uci();
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("serve") => return server::run(&args[2..]),
+        Some("puzzles") => return puzzles::run(&args[2..]),
+        Some("annotate") => return annotate::run(&args[2..]),
+        Some("match") => return match_runner::run(&args[2..]),
+        Some("tune") => return spsa::run(&args[2..]),
+        Some("tree") => return search_tree::run(&args[2..]),
+        Some("wdl") => return wdl_fit::run(&args[2..]),
+        _ => {}
+    }
+
+    // The first command tells which protocol the GUI speaks
+    let mut first_line = String::new();
+    if io::stdin().lock().read_line(&mut first_line).unwrap_or(0) == 0 {
+        return;
+    }
+    if first_line.trim() == "xboard" {
+        xboard::start_xboard();
+    } else {
+        uci::start_uci(first_line.trim().to_string());
+    }
 }
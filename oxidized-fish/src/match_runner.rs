@@ -0,0 +1,849 @@
+//! `match`: plays two UCI engines against each other, each opening once with
+//! either color, and reports the first engine's Elo against the second.
+//! With `--sprt` the match stops as soon as the sequential probability
+//! ratio test accepts either hypothesis. Engines are started as child
+//! processes, by default this binary, so two configurations of it can be
+//! compared through their UCI options.
+//!
+//! ```text
+//! match --engine name=base --engine name=dev option.Contempt=10 \
+//!       --games 1000 --concurrency 4 --tc 10+0.1 --openings book.epd \
+//!       --pgn games.pgn --sprt elo0=0 elo1=5 alpha=0.05 beta=0.05
+//! ```
+
+use crate::board::Board;
+use crate::game::GameState;
+use crate::movegen::parse_move;
+use crate::pgn::{self, Annotation, PgnGame};
+use crate::uci_engine::UciEngine;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_GAMES: usize = 100;
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Slack on top of the clock before a silent engine loses on time.
+const TIME_MARGIN: Duration = Duration::from_millis(1000);
+
+/// Games that go on this long are drawn.
+const MAX_PLIES: usize = 600;
+
+#[derive(Clone)]
+pub struct EngineConfig {
+    pub name: String,
+    pub cmd: String,
+    pub options: Vec<(String, String)>,
+}
+
+#[derive(Clone, Copy)]
+enum TimeControl {
+    Clock { base: Duration, inc: Duration },
+    MoveTime(Duration),
+}
+
+/// Adjudicates a draw once both engines have scored within `score` of zero
+/// for `count` moves each, from move `from_move` on.
+#[derive(Clone, Copy)]
+struct DrawRule {
+    from_move: usize,
+    count: usize,
+    score: i32,
+}
+
+/// Adjudicates a loss once an engine has scored `score` or worse for
+/// `count` moves and its opponent agrees.
+#[derive(Clone, Copy)]
+struct ResignRule {
+    count: usize,
+    score: i32,
+}
+
+#[derive(Clone, Copy)]
+struct Sprt {
+    elo0: f64,
+    elo1: f64,
+    alpha: f64,
+    beta: f64,
+}
+
+#[derive(Clone)]
+pub struct Config {
+    pub engines: Vec<EngineConfig>,
+    pub games: usize,
+    concurrency: usize,
+    tc: TimeControl,
+    pub openings: Vec<Opening>,
+    pgn: Option<String>,
+    sprt: Option<Sprt>,
+    draw: Option<DrawRule>,
+    resign: Option<ResignRule>,
+}
+
+#[derive(Clone)]
+pub struct Opening {
+    start: Board,
+    moves: Vec<String>, // UCI, played before the engines take over
+}
+
+/// Wins, draws and losses of the first engine.
+#[derive(Default)]
+pub struct Score {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+pub struct Finished {
+    round: usize,
+    pgn: String,
+    first_engine_score: f64, // 1, 0.5 or 0
+}
+
+pub fn run(args: &[String]) {
+    let Some(config) = parse_args(args) else {
+        eprintln!(
+            "Usage: match --engine [cmd=<path>] [name=<name>] [option.<name>=<value> ...] \
+             --engine ... [--games <n>] [--concurrency <n>] [--tc <seconds>+<inc> | \
+             --movetime <ms>] [--openings <file.epd|file.pgn>] [--pgn <file>] \
+             [--sprt elo0=<e> elo1=<e> alpha=<a> beta=<b>] \
+             [--draw movenumber=<n> movecount=<n> score=<cp>] \
+             [--resign movecount=<n> score=<cp>]"
+        );
+        return;
+    };
+    if config.engines.len() != 2 {
+        eprintln!("Error: A match needs exactly two engines");
+        return;
+    }
+    let mut pgn_out = match &config.pgn {
+        Some(path) => match File::create(path) {
+            Ok(file) => Some(BufWriter::new(file)),
+            Err(e) => {
+                eprintln!("Error: Failed to create {}: {}", path, e);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    let score = play_match(&config, |finished, score| {
+        if let Some(out) = pgn_out.as_mut() {
+            if let Err(e) = writeln!(out, "{}", finished.pgn).and_then(|_| out.flush()) {
+                eprintln!("Error: Failed to write games: {}", e);
+            }
+        }
+        println!(
+            "Game {} done. {} vs {}: {}",
+            finished.round + 1,
+            config.engines[0].name,
+            config.engines[1].name,
+            score.report(config.sprt.as_ref())
+        );
+        match config.sprt.as_ref().and_then(|sprt| sprt.decision(score)) {
+            Some(accepted) => {
+                println!("SPRT: {} accepted", accepted);
+                false
+            }
+            None => true,
+        }
+    });
+    println!("Finished: {}", score.report(config.sprt.as_ref()));
+}
+
+/// Plays the games of `config` and returns the first engine's score.
+/// `on_game` is called as each game finishes, with the score so far, and
+/// stops the match by returning false.
+pub fn play_match(config: &Config, mut on_game: impl FnMut(&Finished, &Score) -> bool) -> Score {
+    let config = Arc::new(config.clone());
+    let next_round = Arc::new(AtomicUsize::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    let workers: Vec<_> = (0..config.concurrency.min(config.games))
+        .map(|_| {
+            let (config, next_round, stop, tx) =
+                (config.clone(), next_round.clone(), stop.clone(), tx.clone());
+            thread::spawn(move || worker(&config, &next_round, &stop, &tx))
+        })
+        .collect();
+    drop(tx);
+
+    let mut score = Score::default();
+    for finished in rx {
+        match finished.first_engine_score {
+            s if s > 0.75 => score.wins += 1,
+            s if s < 0.25 => score.losses += 1,
+            _ => score.draws += 1,
+        }
+        if !on_game(&finished, &score) {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+    score
+}
+
+/// Plays rounds with its own pair of engines until there are none left.
+fn worker(
+    config: &Config,
+    next_round: &AtomicUsize,
+    stop: &AtomicBool,
+    tx: &mpsc::Sender<Finished>,
+) {
+    let mut engines = Vec::new();
+    for e in &config.engines {
+        match UciEngine::start(&e.name, &e.cmd, &e.options) {
+            Ok(engine) => engines.push(engine),
+            Err(err) => {
+                eprintln!("Error: Failed to start {}: {}", e.name, err);
+                stop.store(true, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    while !stop.load(Ordering::Relaxed) {
+        let round = next_round.fetch_add(1, Ordering::Relaxed);
+        if round >= config.games {
+            break;
+        }
+        // Each opening is played twice, the first engine taking white first
+        let opening = &config.openings[(round / 2) % config.openings.len()];
+        let first_is_white = round.is_multiple_of(2);
+        let (white, black) = engines.split_at_mut(1);
+        let (white, black) = if first_is_white {
+            (&mut white[0], &mut black[0])
+        } else {
+            (&mut black[0], &mut white[0])
+        };
+        let (game, result, reason) = play_game(config, opening, white, black);
+        let first_engine_score = match (result, first_is_white) {
+            ("1-0", true) | ("0-1", false) => 1.0,
+            ("1/2-1/2", _) => 0.5,
+            _ => 0.0,
+        };
+        let pgn = game_pgn(round, opening, &game, white, black, result, reason, config);
+        let finished = Finished {
+            round,
+            pgn,
+            first_engine_score,
+        };
+        if tx.send(finished).is_err() {
+            break;
+        }
+    }
+}
+
+/// Plays one game, returning it with its result and how it ended.
+fn play_game(
+    config: &Config,
+    opening: &Opening,
+    white: &mut UciEngine,
+    black: &mut UciEngine,
+) -> (GameState, &'static str, String) {
+    let mut game = GameState::new(opening.start);
+    for m in &opening.moves {
+        if let Some(m) = parse_move(&game.board, m) {
+            game.make_move(m);
+        }
+    }
+    if let Err(e) = white.new_game() {
+        return (game, "0-1", e.to_string());
+    }
+    if let Err(e) = black.new_game() {
+        return (game, "1-0", e.to_string());
+    }
+
+    let start_fen = opening.start.to_fen();
+    let mut clocks = match config.tc {
+        TimeControl::Clock { base, .. } => [base, base],
+        TimeControl::MoveTime(_) => [Duration::ZERO; 2],
+    };
+    let mut scores: [Vec<i32>; 2] = [Vec::new(), Vec::new()];
+
+    loop {
+        if let Some(result) = game.result() {
+            return (game, result.to_pgn(), result.reason().to_string());
+        }
+        if game.moves().len() >= MAX_PLIES {
+            return (
+                game,
+                "1/2-1/2",
+                "Draw by adjudication: game too long".to_string(),
+            );
+        }
+
+        let us = game.board.side_to_move as usize;
+        let engine = if us == 0 { &mut *white } else { &mut *black };
+        let (win, lose) = if us == 0 {
+            ("1-0", "0-1")
+        } else {
+            ("0-1", "1-0")
+        };
+
+        let mut position = if start_fen == START_FEN {
+            "position startpos".to_string()
+        } else {
+            format!("position fen {}", start_fen)
+        };
+        if !game.moves().is_empty() {
+            let moves: Vec<String> = game.moves().iter().map(|m| m.to_string()).collect();
+            position.push_str(&format!(" moves {}", moves.join(" ")));
+        }
+        let (go, timeout) = match config.tc {
+            TimeControl::Clock { inc, .. } => (
+                format!(
+                    "go wtime {} btime {} winc {} binc {}",
+                    clocks[0].as_millis(),
+                    clocks[1].as_millis(),
+                    inc.as_millis(),
+                    inc.as_millis()
+                ),
+                clocks[us] + TIME_MARGIN,
+            ),
+            TimeControl::MoveTime(t) => (format!("go movetime {}", t.as_millis()), t + TIME_MARGIN),
+        };
+
+        let reply = match engine.go(&position, &go, timeout) {
+            Ok(reply) => reply,
+            Err(e) => return (game, lose, e.to_string()),
+        };
+        if let TimeControl::Clock { inc, .. } = config.tc {
+            if reply.elapsed > clocks[us] {
+                return (game, lose, format!("{} loses on time", engine.name));
+            }
+            clocks[us] = clocks[us] - reply.elapsed + inc;
+        }
+        let Some(m) = parse_move(&game.board, &reply.best) else {
+            let reason = format!("{} plays illegal move {}", engine.name, reply.best);
+            return (game, lose, reason);
+        };
+        game.make_move(m);
+
+        if let Some(score) = reply.score {
+            scores[us].push(score);
+        }
+        if let Some(rule) = config.resign {
+            let resigns = |s: &[i32]| {
+                s.len() >= rule.count && s[s.len() - rule.count..].iter().all(|&s| s <= -rule.score)
+            };
+            let wins = |s: &[i32]| {
+                s.len() >= rule.count && s[s.len() - rule.count..].iter().all(|&s| s >= rule.score)
+            };
+            if resigns(&scores[us]) && wins(&scores[1 - us]) {
+                return (
+                    game,
+                    lose,
+                    format!("{} resigns by adjudication", engine.name),
+                );
+            }
+            if wins(&scores[us]) && resigns(&scores[1 - us]) {
+                let loser = if us == 0 { &black.name } else { &white.name };
+                return (game, win, format!("{} resigns by adjudication", loser));
+            }
+        }
+        if let Some(rule) = config.draw {
+            let drawn = |s: &[i32]| {
+                s.len() >= rule.count
+                    && s[s.len() - rule.count..]
+                        .iter()
+                        .all(|s| s.abs() <= rule.score)
+            };
+            if game.board.fullmove_number as usize > rule.from_move
+                && drawn(&scores[0])
+                && drawn(&scores[1])
+            {
+                return (game, "1/2-1/2", "Draw by adjudication".to_string());
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn game_pgn(
+    round: usize,
+    opening: &Opening,
+    game: &GameState,
+    white: &UciEngine,
+    black: &UciEngine,
+    result: &str,
+    reason: String,
+    config: &Config,
+) -> String {
+    let mut tags = vec![
+        ("Event".to_string(), "oxidized-fish match".to_string()),
+        ("Site".to_string(), "?".to_string()),
+        ("Date".to_string(), today()),
+        ("Round".to_string(), (round + 1).to_string()),
+        ("White".to_string(), white.name.clone()),
+        ("Black".to_string(), black.name.clone()),
+        ("Result".to_string(), result.to_string()),
+    ];
+    let start_fen = opening.start.to_fen();
+    if start_fen != START_FEN {
+        tags.push(("SetUp".to_string(), "1".to_string()));
+        tags.push(("FEN".to_string(), start_fen));
+    }
+    tags.push((
+        "TimeControl".to_string(),
+        match config.tc {
+            TimeControl::Clock { base, inc } => {
+                format!("{}+{}", base.as_secs_f64(), inc.as_secs_f64())
+            }
+            TimeControl::MoveTime(t) => format!("{}/move", t.as_secs_f64()),
+        },
+    ));
+    tags.push(("Termination".to_string(), reason.clone()));
+
+    let pgn_game = PgnGame {
+        tags,
+        start: opening.start,
+        moves: game.moves().to_vec(),
+    };
+    // The reason is also left as a comment on the last move
+    let mut annotations = vec![Annotation::default(); pgn_game.moves.len()];
+    if let Some(last) = annotations.last_mut() {
+        last.comment = Some(reason);
+    }
+    pgn::write_game(&pgn_game, &annotations)
+}
+
+/// The date for the PGN Date tag, from the system clock.
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / 86400) as i64;
+    // Days since 1970-01-01 to a civil date, after Howard Hinnant
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+impl Score {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    /// The first engine's mean score and its per-game variance.
+    fn mean_and_variance(&self) -> (f64, f64) {
+        let n = self.games().max(1) as f64;
+        let (w, d, l) = (
+            self.wins as f64 / n,
+            self.draws as f64 / n,
+            self.losses as f64 / n,
+        );
+        let mean = w + d / 2.0;
+        let variance = w * (1.0 - mean).powi(2) + d * (0.5 - mean).powi(2) + l * mean.powi(2);
+        (mean, variance)
+    }
+
+    /// Elo difference and its 95% error margin.
+    pub fn elo(&self) -> (f64, f64) {
+        let (mean, variance) = self.mean_and_variance();
+        let margin = 1.959964 * (variance / self.games().max(1) as f64).sqrt();
+        let elo = score_to_elo(mean) + 0.0; // No -0.0 at an even score
+        let upper = score_to_elo(mean + margin);
+        let lower = score_to_elo(mean - margin);
+        (elo, (upper - lower) / 2.0)
+    }
+
+    fn report(&self, sprt: Option<&Sprt>) -> String {
+        let (mean, _) = self.mean_and_variance();
+        let (elo, margin) = self.elo();
+        let mut out = format!(
+            "{} - {} - {} [{:.3}] {} games, Elo {:+.1} +/- {:.1}",
+            self.wins,
+            self.losses,
+            self.draws,
+            mean,
+            self.games(),
+            elo,
+            margin
+        );
+        if let Some(sprt) = sprt {
+            let (lower, upper) = sprt.bounds();
+            out.push_str(&format!(
+                ", LLR {:.2} ({:.2}, {:.2}) [{}, {}]",
+                sprt.llr(self),
+                lower,
+                upper,
+                sprt.elo0,
+                sprt.elo1
+            ));
+        }
+        out
+    }
+}
+
+/// Elo from a mean score, clamped short of the infinities at 0 and 1.
+fn score_to_elo(score: f64) -> f64 {
+    let score = score.clamp(1e-3, 1.0 - 1e-3);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+impl Sprt {
+    /// Log-likelihood ratio of `elo1` against `elo0`, in the normal
+    /// approximation for logistic Elo.
+    fn llr(&self, score: &Score) -> f64 {
+        let (mean, variance) = score.mean_and_variance();
+        if score.games() == 0 || variance <= 0.0 {
+            return 0.0;
+        }
+        let (s0, s1) = (elo_to_score(self.elo0), elo_to_score(self.elo1));
+        (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance) * score.games() as f64
+    }
+
+    fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    /// The hypothesis the test accepts, once it does.
+    fn decision(&self, score: &Score) -> Option<&'static str> {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Some("H1")
+        } else if llr <= lower {
+            Some("H0")
+        } else {
+            None
+        }
+    }
+}
+
+/// This binary as an engine.
+pub fn self_engine(name: String) -> EngineConfig {
+    let cmd = std::env::current_exe()
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| "oxidized-fish".to_string());
+    EngineConfig {
+        name,
+        cmd,
+        options: Vec::new(),
+    }
+}
+
+pub fn parse_args(args: &[String]) -> Option<Config> {
+    let mut config = Config {
+        engines: Vec::new(),
+        games: DEFAULT_GAMES,
+        concurrency: 1,
+        tc: TimeControl::Clock {
+            base: Duration::from_secs(10),
+            inc: Duration::from_millis(100),
+        },
+        openings: Vec::new(),
+        pgn: None,
+        sprt: None,
+        draw: None,
+        resign: None,
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        let flag = args[i].as_str();
+        // A flag's values run up to the next flag
+        let values: Vec<&str> = args[i + 1..]
+            .iter()
+            .map(String::as_str)
+            .take_while(|a| !a.starts_with("--"))
+            .collect();
+        i += 1 + values.len();
+        let pairs: Vec<(&str, &str)> = values.iter().filter_map(|v| v.split_once('=')).collect();
+        let number = values
+            .first()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|&n| n > 0);
+        let ok = match (flag, number) {
+            ("--engine", _) => {
+                let mut engine = self_engine(format!("engine{}", config.engines.len() + 1));
+                if let Some(&(_, cmd)) = pairs.iter().find(|(k, _)| *k == "cmd") {
+                    engine.cmd = cmd.to_string();
+                }
+                for &(key, value) in &pairs {
+                    match key {
+                        "cmd" => {}
+                        "name" => engine.name = value.to_string(),
+                        _ => match key.strip_prefix("option.") {
+                            Some(option) => {
+                                engine.options.push((option.to_string(), value.to_string()))
+                            }
+                            None => eprintln!("Error: Unknown engine setting {}", key),
+                        },
+                    }
+                }
+                config.engines.push(engine);
+                pairs.len() == values.len()
+            }
+            ("--games", Some(n)) => {
+                config.games = n;
+                true
+            }
+            ("--concurrency", Some(n)) => {
+                config.concurrency = n;
+                true
+            }
+            ("--tc", _) => match values.first().and_then(|v| parse_tc(v)) {
+                Some(tc) => {
+                    config.tc = tc;
+                    true
+                }
+                None => false,
+            },
+            ("--movetime", Some(n)) => {
+                config.tc = TimeControl::MoveTime(Duration::from_millis(n as u64));
+                true
+            }
+            ("--openings", _) if values.len() == 1 => match read_openings(values[0]) {
+                Some(openings) => {
+                    config.openings = openings;
+                    true
+                }
+                None => return None,
+            },
+            ("--pgn", _) if values.len() == 1 => {
+                config.pgn = Some(values[0].to_string());
+                true
+            }
+            ("--sprt", _) => {
+                let get = |key: &str, default: f64| {
+                    pairs
+                        .iter()
+                        .find(|(k, _)| *k == key)
+                        .map_or(Some(default), |(_, v)| v.parse().ok())
+                };
+                match (
+                    get("elo0", 0.0),
+                    get("elo1", 5.0),
+                    get("alpha", 0.05),
+                    get("beta", 0.05),
+                ) {
+                    (Some(elo0), Some(elo1), Some(alpha), Some(beta))
+                        if elo1 > elo0
+                            && alpha > 0.0
+                            && alpha < 1.0
+                            && beta > 0.0
+                            && beta < 1.0 =>
+                    {
+                        config.sprt = Some(Sprt {
+                            elo0,
+                            elo1,
+                            alpha,
+                            beta,
+                        });
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            ("--draw", _) => {
+                let get = |key: &str, default: i32| {
+                    pairs
+                        .iter()
+                        .find(|(k, _)| *k == key)
+                        .map_or(Some(default), |(_, v)| {
+                            v.parse::<i32>().ok().filter(|&n| n >= 0)
+                        })
+                };
+                match (get("movenumber", 40), get("movecount", 8), get("score", 10)) {
+                    (Some(from_move), Some(count), Some(score)) => {
+                        config.draw = Some(DrawRule {
+                            from_move: from_move as usize,
+                            count: count.max(1) as usize,
+                            score,
+                        });
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            ("--resign", _) => {
+                let get = |key: &str, default: i32| {
+                    pairs
+                        .iter()
+                        .find(|(k, _)| *k == key)
+                        .map_or(Some(default), |(_, v)| {
+                            v.parse::<i32>().ok().filter(|&n| n > 0)
+                        })
+                };
+                match (get("movecount", 3), get("score", 600)) {
+                    (Some(count), Some(score)) => {
+                        config.resign = Some(ResignRule {
+                            count: count as usize,
+                            score,
+                        });
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        };
+        if !ok {
+            eprintln!("Error: Bad option {} {}", flag, values.join(" "));
+            return None;
+        }
+    }
+
+    if config.openings.is_empty() {
+        config.openings.push(Opening {
+            start: Board::new(),
+            moves: Vec::new(),
+        });
+    }
+    Some(config)
+}
+
+/// `<seconds>+<increment>`, either part fractional.
+fn parse_tc(tc: &str) -> Option<TimeControl> {
+    let (base, inc) = tc.split_once('+').unwrap_or((tc, "0"));
+    let base: f64 = base.parse().ok().filter(|&b: &f64| b > 0.0)?;
+    let inc: f64 = inc.parse().ok().filter(|&i: &f64| i >= 0.0)?;
+    Some(TimeControl::Clock {
+        base: Duration::from_secs_f64(base),
+        inc: Duration::from_secs_f64(inc),
+    })
+}
+
+/// Openings from an EPD file, one position a line, or from the games of a
+/// PGN file.
+fn read_openings(path: &str) -> Option<Vec<Opening>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Error: Failed to read {}: {}", path, e);
+            return None;
+        }
+    };
+    let mut openings = Vec::new();
+    if path.to_ascii_lowercase().ends_with(".pgn") {
+        for (i, game) in pgn::read_games(&text).into_iter().enumerate() {
+            match game {
+                Ok(game) => openings.push(Opening {
+                    start: game.start,
+                    moves: game.moves.iter().map(|m| m.to_string()).collect(),
+                }),
+                Err(e) => eprintln!("Error: Opening {}: {}", i + 1, e),
+            }
+        }
+    } else {
+        for line in text.lines() {
+            // EPD has four FEN fields and then operations instead of the move counters
+            let fields: Vec<&str> = line.split_whitespace().take(4).collect();
+            if fields.len() < 4 {
+                continue;
+            }
+            let fen = format!("{} 0 1", fields.join(" "));
+            if Board::is_valid_fen(&fen) {
+                openings.push(Opening {
+                    start: Board::from_fen(&fen),
+                    moves: Vec::new(),
+                });
+            } else {
+                eprintln!("Error: Bad EPD line {}", line);
+            }
+        }
+    }
+    if openings.is_empty() {
+        eprintln!("Error: No openings in {}", path);
+        return None;
+    }
+    Some(openings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(wins: usize, draws: usize, losses: usize) -> Score {
+        Score {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    fn sprt() -> Sprt {
+        Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    fn assert_near(value: f64, expected: f64) {
+        assert!(
+            (value - expected).abs() < 1e-6,
+            "{} is not {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn mean_and_variance_of_a_score() {
+        let (mean, variance) = score(60, 20, 20).mean_and_variance();
+        assert_near(mean, 0.7);
+        assert_near(variance, 0.16);
+        let (mean, variance) = score(30, 40, 30).mean_and_variance();
+        assert_near(mean, 0.5);
+        assert_near(variance, 0.15);
+    }
+
+    #[test]
+    fn elo_and_margin() {
+        let (elo, margin) = score(60, 20, 20).elo();
+        assert_near(elo, 147.190714);
+        assert_near(margin, 66.013382);
+        let (elo, margin) = score(520, 300, 480).elo();
+        assert_near(elo, 10.693701);
+        assert_near(margin, 16.582634);
+    }
+
+    #[test]
+    fn even_score_is_zero_elo() {
+        let (elo, _) = score(30, 40, 30).elo();
+        assert!(elo == 0.0 && elo.is_sign_positive());
+    }
+
+    #[test]
+    fn llr_and_bounds() {
+        let sprt = sprt();
+        assert_near(sprt.llr(&score(60, 20, 20)), 0.883207);
+        assert_near(sprt.llr(&score(30, 40, 30)), -0.017256);
+        assert_near(sprt.llr(&score(520, 300, 480)), 0.574015);
+        assert_eq!(sprt.llr(&score(0, 0, 0)), 0.0);
+        assert_eq!(sprt.llr(&score(10, 0, 0)), 0.0);
+        let (lower, upper) = sprt.bounds();
+        assert_near(lower, -2.944439);
+        assert_near(upper, 2.944439);
+    }
+
+    #[test]
+    fn decides_once_a_bound_is_crossed() {
+        let sprt = sprt();
+        assert_eq!(sprt.decision(&score(520, 300, 480)), None);
+        assert_eq!(sprt.decision(&score(5200, 3000, 4800)), Some("H1"));
+        assert_eq!(sprt.decision(&score(4800, 3000, 5200)), Some("H0"));
+    }
+}
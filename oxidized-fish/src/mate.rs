@@ -0,0 +1,281 @@
+//! Mate solver behind `go mate N`: a depth-first proof-number search (df-pn)
+//! over the moves of the side to mate and every evasion of the defender.
+//!
+//! A node is keyed by its position and the plies left to mate in, so
+//! transpositions share their proofs. The attacker is the side to move when
+//! an odd number of plies is left, and with one left only checks can mate.
+//! Mates are searched for in 1, 2, ... moves, so the first proof is the
+//! shortest and every failure before it proves there is no shorter one.
+//! Repetitions and the fifty-move rule are not considered.
+
+use crate::board::Board;
+use crate::movegen::MoveGen;
+use crate::platform;
+use crate::types::Move;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Proof and disproof numbers saturate here, meaning proven or disproven.
+const INF: u32 = 1 << 28;
+
+/// Longest mate searched for, in moves, so plies fit the search's ply limit.
+pub const MAX_MATE_MOVES: u32 = 63;
+
+pub enum MateResult {
+    Mate(u32, Vec<Move>), // Mate in so many moves; the defender resists longest
+    NoMate,               // Proven within the moves asked for
+    Unknown,              // Stopped before either was proven
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    pn: u32, // Proof number: how hard it is to show the attacker mates
+    dn: u32, // Disproof number: how hard it is to show the defender escapes
+}
+
+impl Entry {
+    fn is_proven(self) -> bool {
+        self.pn == 0
+    }
+
+    fn is_solved(self) -> bool {
+        self.pn == 0 || self.dn == 0
+    }
+}
+
+pub struct MateSearch {
+    table: HashMap<(u64, u8), Entry>,
+    max_entries: usize, // The table is cut down beyond this
+    pub nodes: u64,
+    pub time_limit: Option<Duration>,
+    pub node_limit: Option<u64>,
+    start_time: Option<Instant>,
+    stop_flag: Arc<AtomicBool>,
+    stopped: bool,
+    ignore_limits: bool, // While following a proof, which is always finished
+}
+
+impl MateSearch {
+    pub fn new(stop_flag: Arc<AtomicBool>, max_entries: usize) -> Self {
+        MateSearch {
+            table: HashMap::new(),
+            max_entries: max_entries.max(1024),
+            nodes: 0,
+            time_limit: None,
+            node_limit: None,
+            start_time: None,
+            stop_flag,
+            stopped: false,
+            ignore_limits: false,
+        }
+    }
+
+    /// The shortest mate for the side to move in at most `max_moves` moves.
+    pub fn solve(&mut self, board: &Board, max_moves: u32) -> MateResult {
+        self.nodes = 0;
+        self.stopped = false;
+        self.start_time = platform::now();
+        if self.start_time.is_none() {
+            // Without a clock a time limit can only be kept by counting nodes
+            if let Some(limit) = self.time_limit {
+                let nodes = limit.as_millis() as u64 * platform::NODES_PER_MS;
+                self.node_limit = Some(self.node_limit.map_or(nodes, |n| n.min(nodes)));
+            }
+        }
+
+        for moves in 1..=max_moves.min(MAX_MATE_MOVES) {
+            let entry = self.prove(board, (2 * moves - 1) as u8);
+            if entry.is_proven() {
+                return MateResult::Mate(moves, self.principal_variation(board, moves));
+            }
+            if !entry.is_solved() {
+                return MateResult::Unknown;
+            }
+        }
+        MateResult::NoMate
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.ignore_limits {
+            return false;
+        }
+        if self.stopped
+            || self.stop_flag.load(Ordering::Relaxed)
+            || self.node_limit.is_some_and(|n| self.nodes >= n)
+        {
+            self.stopped = true;
+        } else if self.nodes.is_multiple_of(1024) {
+            if let (Some(start), Some(limit)) = (self.start_time, self.time_limit) {
+                self.stopped = start.elapsed() >= limit;
+            }
+        }
+        self.stopped
+    }
+
+    /// Searches until `board` is solved with `plies` left or the search stops.
+    fn prove(&mut self, board: &Board, plies: u8) -> Entry {
+        let entry = self.lookup(board, plies);
+        if entry.is_solved() {
+            return entry;
+        }
+        self.mid(board, plies, INF, INF);
+        self.lookup(board, plies)
+    }
+
+    /// The attacker's moves with `plies` left, or the defender's.
+    fn moves(board: &Board, plies: u8) -> Vec<Move> {
+        let mut moves = MoveGen::generate(board);
+        moves.retain(|&m| board.is_legal(m));
+        if plies == 1 {
+            moves.retain(|&m| board.make_move(m).is_in_check());
+        }
+        moves
+    }
+
+    /// The stored numbers of a node, or first estimates for a new one: a
+    /// defender with few replies is easier to mate.
+    fn lookup(&mut self, board: &Board, plies: u8) -> Entry {
+        if let Some(&entry) = self.table.get(&(board.hash, plies)) {
+            return entry;
+        }
+        let attacker = plies % 2 == 1;
+        let entry = if attacker {
+            let count = Self::moves(board, plies).len() as u32;
+            if count == 0 {
+                Entry { pn: INF, dn: 0 }
+            } else {
+                Entry { pn: 1, dn: count }
+            }
+        } else if !board.has_legal_moves() {
+            if board.is_in_check() {
+                Entry { pn: 0, dn: INF }
+            } else {
+                Entry { pn: INF, dn: 0 }
+            }
+        } else if plies == 0 {
+            Entry { pn: INF, dn: 0 }
+        } else {
+            let count = Self::moves(board, plies).len() as u32;
+            Entry { pn: count, dn: 1 }
+        };
+        self.table.insert((board.hash, plies), entry);
+        entry
+    }
+
+    /// Expands an unsolved node until its numbers reach the thresholds. In
+    /// terms of the side to move, phi is its proof number and delta its
+    /// disproof number, which makes both kinds of node the same.
+    fn mid(&mut self, board: &Board, plies: u8, th_phi: u32, th_delta: u32) {
+        self.nodes += 1;
+        // Following a proof needs the proven entries it walks along
+        if self.table.len() > self.max_entries && !self.ignore_limits {
+            self.evict();
+        }
+
+        let attacker = plies % 2 == 1;
+        let children: Vec<Board> = Self::moves(board, plies)
+            .into_iter()
+            .map(|m| board.make_move(m))
+            .collect();
+
+        loop {
+            let mut phi = INF;
+            let mut delta = 0;
+            let mut best = 0;
+            let mut best_phi = 0;
+            let mut second_delta = INF;
+            for (i, child) in children.iter().enumerate() {
+                let (c_phi, c_delta) = phi_delta(self.lookup(child, plies - 1), !attacker);
+                delta = (delta + c_phi).min(INF);
+                if c_delta < phi {
+                    second_delta = phi;
+                    phi = c_delta;
+                    best = i;
+                    best_phi = c_phi;
+                } else if c_delta < second_delta {
+                    second_delta = c_delta;
+                }
+            }
+
+            let entry = if attacker {
+                Entry { pn: phi, dn: delta }
+            } else {
+                Entry { pn: delta, dn: phi }
+            };
+            self.table.insert((board.hash, plies), entry);
+            if phi >= th_phi || delta >= th_delta || self.should_stop() {
+                return;
+            }
+
+            // Go on with the most promising child until it stops being that
+            let child_th_phi = th_delta - delta + best_phi;
+            let child_th_delta = th_phi.min(second_delta + 1);
+            self.mid(&children[best], plies - 1, child_th_phi, child_th_delta);
+        }
+    }
+
+    /// Shrinks the table to half its limit so that it isn't swept again at
+    /// the next node, keeping what is most costly to find again: unsolved
+    /// entries go first, then disproofs, then proofs.
+    fn evict(&mut self) {
+        let low_water = self.max_entries / 2;
+        self.table.retain(|_, e| e.is_solved());
+        if self.table.len() > low_water {
+            self.table.retain(|_, e| e.is_proven());
+        }
+        if self.table.len() > low_water {
+            self.table.clear();
+        }
+    }
+
+    /// The fewest moves, up to `max_moves`, the side to move needs to mate.
+    fn shortest_mate(&mut self, board: &Board, max_moves: u32) -> Option<u32> {
+        (1..=max_moves).find(|&n| self.prove(board, (2 * n - 1) as u8).is_proven())
+    }
+
+    /// Follows a proven mate in `moves`: the attacker plays towards the
+    /// quickest mate and the defender delays it the longest.
+    fn principal_variation(&mut self, board: &Board, mut moves: u32) -> Vec<Move> {
+        self.ignore_limits = true;
+        let mut pv = Vec::new();
+        let mut board = *board;
+        loop {
+            let plies = (2 * moves - 1) as u8;
+            let Some(m) = Self::moves(&board, plies)
+                .into_iter()
+                .find(|&m| self.lookup(&board.make_move(m), plies - 1).is_proven())
+            else {
+                break;
+            };
+            pv.push(m);
+            board = board.make_move(m);
+
+            let mut reply = None;
+            for m in Self::moves(&board, plies - 1) {
+                let n = self.shortest_mate(&board.make_move(m), moves - 1);
+                if reply.is_none_or(|(_, longest)| n > Some(longest)) {
+                    reply = n.map(|n| (m, n));
+                }
+            }
+            let Some((m, n)) = reply else {
+                break;
+            };
+            pv.push(m);
+            board = board.make_move(m);
+            moves = n;
+        }
+        self.ignore_limits = false;
+        pv
+    }
+}
+
+/// A node's numbers from the point of view of its side to move.
+fn phi_delta(entry: Entry, attacker: bool) -> (u32, u32) {
+    if attacker {
+        (entry.pn, entry.dn)
+    } else {
+        (entry.dn, entry.pn)
+    }
+}
@@ -147,3 +147,10 @@ impl MoveGen {
         }
     }
 }
+
+/// The legal move written `m_str` in UCI notation, if there is one.
+pub fn parse_move(board: &Board, m_str: &str) -> Option<Move> {
+    MoveGen::generate(board)
+        .into_iter()
+        .find(|&m| m.to_string() == m_str && board.is_legal(m))
+}
@@ -0,0 +1,216 @@
+//! Search parameters that can be tuned. Each one is a hidden UCI spin
+//! option: `setoption` takes it but `uci` doesn't list it, so GUIs aren't
+//! cluttered. `spsa_config` lists them all for a tuner, and the binary's
+//! `tune` subcommand runs SPSA over them itself.
+
+/// SPSA's learning rate at the last iteration, for every parameter.
+pub const SPSA_R_END: f64 = 0.002;
+
+/// One tunable parameter. `step` is the perturbation SPSA ends with, which
+/// should be worth a few Elo.
+pub struct Tunable {
+    pub name: &'static str,
+    pub default: i32,
+    pub min: i32,
+    pub max: i32,
+    pub step: f64,
+    field: fn(&mut SearchParams) -> &mut i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchParams {
+    pub rfp_margin: i32,              // Per ply of depth
+    pub nmp_base_reduction: i32,      // Null move reduction before depth is added
+    pub nmp_depth_divisor: i32,       // One more ply of reduction per this many
+    pub futility_margin: i32,         // Per ply of depth
+    pub lmp_base: i32,                // Plus depth^2, quiet moves searched before pruning
+    pub aspiration_window: i32,       // Also the smallest it shrinks back to
+    pub aspiration_growth: i32,       // Percent the window grows by on a fail
+    pub qs_delta_margin: i32,         // What a capture may gain beyond its victim
+    pub qs_see_threshold: i32,        // Captures losing more than this are pruned
+    pub see_quiet_margin: i32,        // Times depth^2, the SEE a quiet move needs
+    pub see_capture_margin: i32,      // Times depth, the same for a capture
+    pub probcut_margin: i32,          // Over beta, for a capture to cut the node
+    pub singular_margin: i32,         // Times depth, under the TT score for other moves
+    pub double_extension_margin: i32, // Further under, to extend the TT move twice
+}
+
+pub const TUNABLES: [Tunable; 14] = [
+    Tunable {
+        name: "RfpMargin",
+        default: 120,
+        min: 40,
+        max: 300,
+        step: 10.0,
+        field: |p| &mut p.rfp_margin,
+    },
+    Tunable {
+        name: "NmpBaseReduction",
+        default: 3,
+        min: 1,
+        max: 6,
+        step: 1.0,
+        field: |p| &mut p.nmp_base_reduction,
+    },
+    Tunable {
+        name: "NmpDepthDivisor",
+        default: 4,
+        min: 2,
+        max: 8,
+        step: 1.0,
+        field: |p| &mut p.nmp_depth_divisor,
+    },
+    Tunable {
+        name: "FutilityMargin",
+        default: 250,
+        min: 50,
+        max: 500,
+        step: 20.0,
+        field: |p| &mut p.futility_margin,
+    },
+    Tunable {
+        name: "LmpBase",
+        default: 8,
+        min: 2,
+        max: 20,
+        step: 1.0,
+        field: |p| &mut p.lmp_base,
+    },
+    Tunable {
+        name: "AspirationWindow",
+        default: 35,
+        min: 10,
+        max: 100,
+        step: 4.0,
+        field: |p| &mut p.aspiration_window,
+    },
+    Tunable {
+        name: "AspirationGrowth",
+        default: 160,
+        min: 110,
+        max: 300,
+        step: 10.0,
+        field: |p| &mut p.aspiration_growth,
+    },
+    Tunable {
+        name: "QsDeltaMargin",
+        default: 200,
+        min: 0,
+        max: 600,
+        step: 20.0,
+        field: |p| &mut p.qs_delta_margin,
+    },
+    Tunable {
+        name: "QsSeeThreshold",
+        default: -200,
+        min: -600,
+        max: 0,
+        step: 25.0,
+        field: |p| &mut p.qs_see_threshold,
+    },
+    Tunable {
+        name: "SeeQuietMargin",
+        default: -30,
+        min: -120,
+        max: 0,
+        step: 4.0,
+        field: |p| &mut p.see_quiet_margin,
+    },
+    Tunable {
+        name: "SeeCaptureMargin",
+        default: -90,
+        min: -300,
+        max: 0,
+        step: 10.0,
+        field: |p| &mut p.see_capture_margin,
+    },
+    Tunable {
+        name: "ProbCutMargin",
+        default: 200,
+        min: 50,
+        max: 500,
+        step: 15.0,
+        field: |p| &mut p.probcut_margin,
+    },
+    Tunable {
+        name: "SingularMargin",
+        default: 2,
+        min: 1,
+        max: 8,
+        step: 1.0,
+        field: |p| &mut p.singular_margin,
+    },
+    Tunable {
+        name: "DoubleExtensionMargin",
+        default: 25,
+        min: 0,
+        max: 100,
+        step: 5.0,
+        field: |p| &mut p.double_extension_margin,
+    },
+];
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        let mut params = SearchParams {
+            rfp_margin: 0,
+            nmp_base_reduction: 0,
+            nmp_depth_divisor: 0,
+            futility_margin: 0,
+            lmp_base: 0,
+            aspiration_window: 0,
+            aspiration_growth: 0,
+            qs_delta_margin: 0,
+            qs_see_threshold: 0,
+            see_quiet_margin: 0,
+            see_capture_margin: 0,
+            probcut_margin: 0,
+            singular_margin: 0,
+            double_extension_margin: 0,
+        };
+        for t in &TUNABLES {
+            *(t.field)(&mut params) = t.default;
+        }
+        params
+    }
+}
+
+impl SearchParams {
+    pub fn get(&self, name: &str) -> Option<i32> {
+        let t = TUNABLES.iter().find(|t| t.name == name)?;
+        let mut params = *self;
+        Some(*(t.field)(&mut params))
+    }
+
+    /// Sets the parameter called `name`, clamped to its range. False if
+    /// there is no such parameter or `value` isn't a number.
+    pub fn set(&mut self, name: &str, value: &str) -> bool {
+        let Some(t) = TUNABLES.iter().find(|t| t.name == name) else {
+            return false;
+        };
+        let Ok(value) = value.trim().parse::<i32>() else {
+            return false;
+        };
+        *(t.field)(self) = value.clamp(t.min, t.max);
+        true
+    }
+}
+
+/// The parameters in the SPSA format OpenBench and fishtest read, one a
+/// line: `name, int, value, min, max, c_end, r_end`.
+pub fn spsa_config(params: &SearchParams) -> String {
+    TUNABLES
+        .iter()
+        .map(|t| {
+            format!(
+                "{}, int, {}, {}, {}, {}, {}\n",
+                t.name,
+                params.get(t.name).unwrap_or(t.default),
+                t.min,
+                t.max,
+                t.step,
+                SPSA_R_END
+            )
+        })
+        .collect()
+}
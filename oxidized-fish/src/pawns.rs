@@ -0,0 +1,208 @@
+use crate::board::Board;
+use crate::tables::{ATTACKS, FILE_BB};
+use crate::types::{Color, PieceType};
+
+const PAWN_TABLE_SIZE: usize = 16384;
+
+const MG_PAWN_ISO: i32 = -20;
+const EG_PAWN_ISO: i32 = -32;
+const MG_PAWN_DOUBLED: i32 = -28;
+const EG_PAWN_DOUBLED: i32 = -50;
+const MG_PAWN_BACKWARD: i32 = -18;
+const EG_PAWN_BACKWARD: i32 = -24;
+
+/// Cached pawn structure score and derived bitboards for one pawn key.
+#[derive(Clone, Copy)]
+pub struct PawnEntry {
+    pub key: u64,
+    pub mg: [i32; 2],
+    pub eg: [i32; 2],
+    pub passed: [u64; 2],
+    pub attacks: [u64; 2],
+    pub semi_open_files: [u8; 2], // Files without a pawn of that side
+}
+
+impl PawnEntry {
+    pub fn is_semi_open(&self, c: usize, file: usize) -> bool {
+        (self.semi_open_files[c] & (1u8 << file)) != 0
+    }
+}
+
+pub struct PawnTable {
+    entries: Vec<Option<PawnEntry>>,
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PawnTable {
+    pub fn new() -> Self {
+        PawnTable {
+            entries: vec![None; PAWN_TABLE_SIZE],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = None;
+        }
+    }
+
+    pub fn probe(&mut self, board: &Board) -> PawnEntry {
+        let idx = board.pawn_hash as usize % PAWN_TABLE_SIZE;
+        if let Some(e) = self.entries[idx] {
+            if e.key == board.pawn_hash {
+                return e;
+            }
+        }
+        let e = evaluate_pawns(board);
+        self.entries[idx] = Some(e);
+        e
+    }
+}
+
+fn evaluate_pawns(board: &Board) -> PawnEntry {
+    let mut e = PawnEntry {
+        key: board.pawn_hash,
+        mg: [0; 2],
+        eg: [0; 2],
+        passed: [0; 2],
+        attacks: [0; 2],
+        semi_open_files: [0; 2],
+    };
+
+    let pawns = [
+        board.by_type[PieceType::Pawn as usize] & board.by_color[0],
+        board.by_type[PieceType::Pawn as usize] & board.by_color[1],
+    ];
+
+    for c in 0..2 {
+        let us = if c == 0 { Color::White } else { Color::Black };
+        let them = us.opponent();
+        let my_pawns = pawns[c];
+        let enemy_pawns = pawns[them as usize];
+
+        let mut bb = my_pawns;
+        while bb != 0 {
+            let sq = bb.trailing_zeros() as u8;
+            let file = (sq % 8) as usize;
+            let rank = sq / 8;
+            let r = if c == 0 { rank } else { 7 - rank };
+
+            e.attacks[c] |= ATTACKS.pawn[c][sq as usize];
+
+            if (my_pawns & FILE_BB[file] & !(1u64 << sq)) != 0 {
+                e.mg[c] += MG_PAWN_DOUBLED;
+                e.eg[c] += EG_PAWN_DOUBLED;
+            }
+            let left_file = if file > 0 { FILE_BB[file - 1] } else { 0 };
+            let right_file = if file < 7 { FILE_BB[file + 1] } else { 0 };
+            if (my_pawns & (left_file | right_file)) == 0 {
+                e.mg[c] += MG_PAWN_ISO;
+                e.eg[c] += EG_PAWN_ISO;
+            }
+            if is_passed_pawn(sq, us, enemy_pawns) {
+                e.passed[c] |= 1u64 << sq;
+            }
+            if is_backward_pawn(sq, us, my_pawns, enemy_pawns) {
+                e.mg[c] += MG_PAWN_BACKWARD;
+                e.eg[c] += EG_PAWN_BACKWARD;
+            }
+            if is_connected_pawn(sq, my_pawns) {
+                e.mg[c] += (r as i32) * 6;
+            }
+            bb &= bb - 1;
+        }
+
+        let mut passed = e.passed[c];
+        while passed != 0 {
+            let sq = passed.trailing_zeros() as u8;
+            let r = if c == 0 { sq / 8 } else { 7 - sq / 8 };
+            e.mg[c] += (r as i32 * r as i32) * 10;
+            e.eg[c] += (r as i32 * r as i32) * 10;
+            passed &= passed - 1;
+        }
+
+        for (file, mask) in FILE_BB.iter().enumerate() {
+            if (my_pawns & mask) == 0 {
+                e.semi_open_files[c] |= 1u8 << file;
+            }
+        }
+    }
+    e
+}
+
+pub fn is_passed_pawn(sq: u8, color: Color, enemy_pawns: u64) -> bool {
+    let file = (sq % 8) as i16;
+    let rank = (sq / 8) as i16;
+    let mut mask = 0u64;
+    for f in (file - 1).max(0)..=(file + 1).min(7) {
+        if color == Color::White {
+            for r in (rank + 1)..8 {
+                mask |= 1u64 << (r * 8 + f);
+            }
+        } else {
+            for r in 0..rank {
+                mask |= 1u64 << (r * 8 + f);
+            }
+        }
+    }
+    (mask & enemy_pawns) == 0
+}
+
+fn is_backward_pawn(sq: u8, color: Color, my_pawns: u64, enemy_pawns: u64) -> bool {
+    let file = (sq % 8) as i16;
+    let rank = (sq / 8) as i16;
+    let stop_sq = if color == Color::White {
+        if rank >= 7 {
+            return false;
+        }
+        sq + 8
+    } else {
+        if rank <= 0 {
+            return false;
+        }
+        sq - 8
+    };
+    let mut neighbors = 0u64;
+    for f in (file - 1).max(0)..=(file + 1).min(7) {
+        if f == file {
+            continue;
+        }
+        if color == Color::White {
+            for r in 0..=rank {
+                neighbors |= 1u64 << (r * 8 + f);
+            }
+        } else {
+            for r in rank..8 {
+                neighbors |= 1u64 << (r * 8 + f);
+            }
+        }
+    }
+    if (my_pawns & neighbors) == 0 {
+        let enemy_attacks =
+            crate::tables::ATTACKS.pawn[color.opponent() as usize][stop_sq as usize];
+        if (enemy_pawns & enemy_attacks) != 0 {
+            return true;
+        }
+    }
+    false
+}
+
+fn is_connected_pawn(sq: u8, my_pawns: u64) -> bool {
+    let file = (sq % 8) as i16;
+    let rank = (sq / 8) as i16;
+    let mut neighbors = 0u64;
+    for f in (file - 1).max(0)..=(file + 1).min(7) {
+        if f == file {
+            continue;
+        }
+        for r in (rank - 1).max(0)..=(rank + 1).min(7) {
+            neighbors |= 1u64 << (r * 8 + f);
+        }
+    }
+    (my_pawns & neighbors) != 0
+}
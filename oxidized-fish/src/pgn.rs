@@ -0,0 +1,316 @@
+//! PGN games and SAN moves. Comments, variations and NAGs are skipped when
+//! reading, so a game is its tags and the moves of its main line. Writing
+//! adds them back from an `Annotation` per move.
+
+use crate::board::Board;
+use crate::game::GameState;
+use crate::movegen::MoveGen;
+use crate::types::{Color, Move, PieceType};
+
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub start: Board, // From the FEN tag, or the start position
+    pub moves: Vec<Move>,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The game replayed up to its last move.
+    pub fn game_state(&self) -> GameState {
+        let mut game = GameState::new(self.start);
+        for &m in &self.moves {
+            game.make_move(m);
+        }
+        game
+    }
+}
+
+/// What is written after a move: a NAG, a comment and a variation that
+/// replaces the move.
+#[derive(Clone, Default)]
+pub struct Annotation {
+    pub nag: Option<u8>,
+    pub comment: Option<String>,
+    pub variation: Vec<Move>,
+}
+
+/// Movetext lines are wrapped before this many characters.
+const LINE_WIDTH: usize = 80;
+
+/// `game` in PGN export format, each move followed by its entry in
+/// `annotations` if there is one. The result comes from the Result tag.
+pub fn write_game(game: &PgnGame, annotations: &[Annotation]) -> String {
+    let mut out = String::new();
+    for (name, value) in &game.tags {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        out.push_str(&format!("[{} \"{}\"]\n", name, value));
+    }
+    out.push('\n');
+
+    let mut tokens = Vec::new();
+    let mut board = game.start;
+    let mut show_number = true; // Black's move needs its number after a break
+    for (i, &m) in game.moves.iter().enumerate() {
+        push_move(&mut tokens, &board, m, show_number);
+        show_number = false;
+        if let Some(a) = annotations.get(i) {
+            if let Some(nag) = a.nag {
+                tokens.push(format!("${}", nag));
+            }
+            if let Some(comment) = &a.comment {
+                tokens.push(format!("{{{}}}", comment.replace('}', ")")));
+                show_number = true;
+            }
+            if !a.variation.is_empty() {
+                let mut variation = Vec::new();
+                let mut line_board = board;
+                for (j, &v) in a.variation.iter().enumerate() {
+                    push_move(&mut variation, &line_board, v, j == 0);
+                    line_board = line_board.make_move(v);
+                }
+                tokens.push(format!("({})", variation.join(" ")));
+                show_number = true;
+            }
+        }
+        board = board.make_move(m);
+    }
+    tokens.push(game.tag("Result").unwrap_or("*").to_string());
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+            out.push_str(&line);
+            out.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    out.push_str(&line);
+    out.push('\n');
+    out
+}
+
+/// `m` in SAN with its move number when white plays it, or when black does
+/// and `show_number` asks for one.
+fn push_move(tokens: &mut Vec<String>, board: &Board, m: Move, show_number: bool) {
+    let san = to_san(board, m);
+    if board.side_to_move == Color::White {
+        tokens.push(format!("{}. {}", board.fullmove_number, san));
+    } else if show_number {
+        tokens.push(format!("{}... {}", board.fullmove_number, san));
+    } else {
+        tokens.push(san);
+    }
+}
+
+/// Every game in `text`, or why it couldn't be read. A game stops at its
+/// result, or at the next tag section when that is missing.
+pub fn read_games(text: &str) -> Vec<Result<PgnGame, String>> {
+    let mut games = Vec::new();
+    let mut tags = Vec::new();
+    let mut tokens = Vec::new();
+    let mut in_movetext = false;
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => {
+                if in_movetext {
+                    games.push(build_game(std::mem::take(&mut tags), &tokens));
+                    tokens.clear();
+                    in_movetext = false;
+                }
+                let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if let Some(tag) = parse_tag(&tag) {
+                    tags.push(tag);
+                }
+            }
+            '{' => {
+                chars.by_ref().find(|&c| c == '}');
+            }
+            ';' | '%' => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '(' => {
+                let mut depth = 1;
+                for c in chars.by_ref() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            _ => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[]{}();".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                if matches!(token.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*") {
+                    games.push(build_game(std::mem::take(&mut tags), &tokens));
+                    tokens.clear();
+                    in_movetext = false;
+                } else {
+                    tokens.push(token);
+                    in_movetext = true;
+                }
+            }
+        }
+    }
+    if in_movetext || !tags.is_empty() {
+        games.push(build_game(tags, &tokens));
+    }
+    games
+}
+
+/// `Name "Value"` from between the brackets of a tag.
+fn parse_tag(tag: &str) -> Option<(String, String)> {
+    let (name, rest) = tag.trim().split_once(char::is_whitespace)?;
+    let value = rest.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((
+        name.to_string(),
+        value.replace("\\\"", "\"").replace("\\\\", "\\"),
+    ))
+}
+
+fn build_game(tags: Vec<(String, String)>, tokens: &[String]) -> Result<PgnGame, String> {
+    let start = match tags.iter().find(|(n, _)| n == "FEN") {
+        Some((_, fen)) if !Board::is_valid_fen(fen) => return Err(format!("Bad FEN {}", fen)),
+        Some((_, fen)) => Board::from_fen(fen),
+        None => Board::new(),
+    };
+    let mut board = start;
+    let mut moves = Vec::new();
+    for token in tokens {
+        // Move numbers are either their own token or stuck to the move
+        let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        if san.is_empty() || san.starts_with('$') {
+            continue;
+        }
+        let Some(m) = parse_san(&board, san) else {
+            return Err(format!(
+                "Illegal move {} after {} moves",
+                token,
+                moves.len()
+            ));
+        };
+        board = board.make_move(m);
+        moves.push(m);
+    }
+    Ok(PgnGame { tags, start, moves })
+}
+
+/// The legal move written `san`, checks and annotations allowed but not
+/// needed. Captures and promotions are also recognized without `x` or `=`.
+pub fn parse_san(board: &Board, san: &str) -> Option<Move> {
+    let san = san.trim_end_matches(['+', '#', '!', '?']);
+    let san = san.replace('0', "O");
+    let loose = |s: &str| s.replace(['x', '='], "");
+    legal_moves(board).into_iter().find(|&m| {
+        let written = to_san_without_check(board, m);
+        written == san || loose(&written) == loose(&san)
+    })
+}
+
+/// `m` in SAN, with `+` or `#` when it gives check or mate.
+pub fn to_san(board: &Board, m: Move) -> String {
+    let mut san = to_san_without_check(board, m);
+    let next = board.make_move(m);
+    if next.is_in_check() {
+        san.push(if next.has_legal_moves() { '+' } else { '#' });
+    }
+    san
+}
+
+/// Consecutive moves from `board` in SAN.
+pub fn line_to_san(board: &Board, moves: &[Move]) -> Vec<String> {
+    let mut board = *board;
+    moves
+        .iter()
+        .map(|&m| {
+            let san = to_san(&board, m);
+            board = board.make_move(m);
+            san
+        })
+        .collect()
+}
+
+fn to_san_without_check(board: &Board, m: Move) -> String {
+    match m.flags() {
+        Move::K_CASTLE => return "O-O".to_string(),
+        Move::Q_CASTLE => return "O-O-O".to_string(),
+        _ => {}
+    }
+    let Some((piece, _)) = board.get_piece_at(m.from()) else {
+        return m.to_string();
+    };
+
+    let mut san = String::new();
+    if piece == PieceType::Pawn {
+        if m.is_capture() {
+            san.push(file_char(m.from()));
+        }
+    } else {
+        san.push(b"PNBRQK"[piece as usize] as char);
+        // Name the file, the rank or both of the moving piece if another can go there
+        let rivals: Vec<Move> = legal_moves(board)
+            .into_iter()
+            .filter(|&o| {
+                o.to() == m.to()
+                    && o.from() != m.from()
+                    && board.get_piece_at(o.from()).map(|(p, _)| p) == Some(piece)
+            })
+            .collect();
+        if !rivals.is_empty() {
+            if rivals.iter().all(|o| o.from() % 8 != m.from() % 8) {
+                san.push(file_char(m.from()));
+            } else if rivals.iter().all(|o| o.from() / 8 != m.from() / 8) {
+                san.push(rank_char(m.from()));
+            } else {
+                san.push(file_char(m.from()));
+                san.push(rank_char(m.from()));
+            }
+        }
+    }
+    if m.is_capture() {
+        san.push('x');
+    }
+    san.push(file_char(m.to()));
+    san.push(rank_char(m.to()));
+    if let Some(promoted) = m.promoted_piece() {
+        san.push('=');
+        san.push(b"PNBRQK"[promoted as usize] as char);
+    }
+    san
+}
+
+fn legal_moves(board: &Board) -> Vec<Move> {
+    let mut moves = MoveGen::generate(board);
+    moves.retain(|&m| board.is_legal(m));
+    moves
+}
+
+fn file_char(sq: u8) -> char {
+    (b'a' + sq % 8) as char
+}
+
+fn rank_char(sq: u8) -> char {
+    (b'1' + sq / 8) as char
+}
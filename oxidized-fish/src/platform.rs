@@ -0,0 +1,36 @@
+//! What the core needs from the host. `Instant::now` and `SystemTime::now`
+//! panic on `wasm32-unknown-unknown`, so the core asks here instead of std.
+//! Threads are only used by the front ends, which stay out of the library.
+
+use std::time::Instant;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Search speed assumed when turning a time limit into a node limit.
+/// Browsers run the engine at roughly half its native speed.
+pub const NODES_PER_MS: u64 = 250;
+
+/// The current time, or `None` when the target has no clock.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now() -> Option<Instant> {
+    Some(Instant::now())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn now() -> Option<Instant> {
+    None
+}
+
+/// Nanoseconds since the epoch to seed random choices, or 0 without a clock.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn time_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn time_seed() -> u64 {
+    0
+}
@@ -0,0 +1,394 @@
+//! `puzzles <games.pgn>`: mines tactics from played games. A position
+//! becomes a puzzle when the side to move has exactly one winning move right
+//! after the opponent's mistake, and stays one only while the winning move
+//! is unique at every later move of the solver. Puzzles are written as CSV.
+
+use crate::board::Board;
+use crate::game::GameState;
+use crate::pgn::{self, PgnGame};
+use crate::search::{Search, MAX_PLY};
+use crate::types::{Color, Move, PieceType, MATE_VALUE};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Duration;
+
+const DEFAULT_DEPTH: u8 = 10;
+const DEFAULT_HASH: usize = 64;
+const DEFAULT_MAX_MOVES: usize = 3;
+
+/// The best move has to win at least this much, in centipawns, and the
+/// second best has to stay below `UNCLEAR` for the puzzle to be unique.
+const WINNING: i32 = 200;
+const UNCLEAR: i32 = 80;
+
+/// Longest mate kept as a puzzle, in moves.
+const MAX_MATE: i32 = 5;
+
+const VALUES: [i32; 6] = [100, 320, 330, 500, 900, 20000];
+
+struct Config {
+    input: String,
+    output: Option<String>,
+    depth: u8,
+    movetime: Option<Duration>,
+    hash: usize,
+    max_moves: usize, // Solver moves in a puzzle that doesn't mate
+}
+
+struct Puzzle {
+    board: Board,
+    line: Vec<Move>, // Solver's moves and the opponent's best replies
+    themes: Vec<String>,
+    ply: usize, // In the game
+}
+
+pub fn run(args: &[String]) {
+    let Some(config) = parse_args(args) else {
+        eprintln!(
+            "Usage: puzzles <games.pgn> [--output <file.csv>] [--depth <plies>] \
+             [--movetime <ms>] [--hash <MB>] [--max-moves <n>]"
+        );
+        return;
+    };
+    let text = match std::fs::read_to_string(&config.input) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Error: Failed to read {}: {}", config.input, e);
+            return;
+        }
+    };
+    let mut out: Box<dyn Write> = match &config.output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(e) => {
+                eprintln!("Error: Failed to create {}: {}", path, e);
+                return;
+            }
+        },
+        None => Box::new(io::stdout()),
+    };
+
+    let mut searcher = Search::with_hash(config.hash);
+
+    if let Err(e) = writeln!(out, "FEN,Moves,SAN,Themes,White,Black,Ply") {
+        eprintln!("Error: Failed to write puzzles: {}", e);
+        return;
+    }
+    let mut found = 0;
+    let games = pgn::read_games(&text);
+    for (i, game) in games.iter().enumerate() {
+        let game = match game {
+            Ok(game) => game,
+            Err(e) => {
+                eprintln!("Error: Game {}: {}", i + 1, e);
+                continue;
+            }
+        };
+        searcher.clear_tt();
+        for puzzle in mine_game(&mut searcher, &config, game) {
+            if let Err(e) = write_puzzle(&mut out, &puzzle, game) {
+                eprintln!("Error: Failed to write puzzles: {}", e);
+                return;
+            }
+            found += 1;
+        }
+        eprintln!("Game {}/{}: {} puzzles so far", i + 1, games.len(), found);
+    }
+    if let Err(e) = out.flush() {
+        eprintln!("Error: Failed to write puzzles: {}", e);
+    }
+}
+
+fn parse_args(args: &[String]) -> Option<Config> {
+    let mut config = Config {
+        input: String::new(),
+        output: None,
+        depth: DEFAULT_DEPTH,
+        movetime: None,
+        hash: DEFAULT_HASH,
+        max_moves: DEFAULT_MAX_MOVES,
+    };
+    let mut i = 0;
+    while i < args.len() {
+        if !args[i].starts_with("--") {
+            config.input = args[i].clone();
+            i += 1;
+            continue;
+        }
+        let Some(value) = args.get(i + 1) else {
+            eprintln!("Error: Missing value for {}", args[i]);
+            return None;
+        };
+        let number = value.parse::<usize>().ok().filter(|&n| n > 0);
+        match (args[i].as_str(), number) {
+            ("--output", _) => config.output = Some(value.clone()),
+            ("--depth", Some(n)) if n < MAX_PLY => config.depth = n as u8,
+            ("--movetime", Some(n)) => config.movetime = Some(Duration::from_millis(n as u64)),
+            ("--hash", Some(n)) => config.hash = n,
+            ("--max-moves", Some(n)) => config.max_moves = n,
+            _ => {
+                eprintln!("Error: Bad option {} {}", args[i], value);
+                return None;
+            }
+        }
+        i += 2;
+    }
+    (!config.input.is_empty()).then_some(config)
+}
+
+/// The two best moves with their scores, best first.
+fn analyse(
+    searcher: &mut Search,
+    config: &Config,
+    game: &GameState,
+    lines: usize,
+) -> Vec<(Move, i32)> {
+    searcher.multi_pv = lines;
+    searcher.go(game, config.depth, config.movetime);
+    searcher.root_lines.clone()
+}
+
+fn mate_in(score: i32) -> Option<i32> {
+    (score >= MATE_VALUE - MAX_PLY as i32).then(|| (MATE_VALUE - score + 1) / 2)
+}
+
+/// Whether the first of `lines` is the only move that wins. Against a mate
+/// only a mate as fast counts as another solution.
+fn is_unique(lines: &[(Move, i32)]) -> bool {
+    let Some(&(_, best)) = lines.first() else {
+        return false;
+    };
+    let Some(&(_, second)) = lines.get(1) else {
+        return false; // A forced move is no puzzle
+    };
+    match (mate_in(best), mate_in(second)) {
+        (Some(n), Some(m)) => m > n,
+        (Some(_), None) => true,
+        _ => best >= WINNING && second < UNCLEAR,
+    }
+}
+
+/// A puzzle starts from the opponent's mistake, not from a won position:
+/// their move has to turn what wasn't a win for us into one, or a win into
+/// a mate. `before` is our score before the move.
+fn is_mistake(before: Option<i32>, now: i32) -> bool {
+    match before {
+        Some(before) if before >= WINNING => mate_in(now).is_some() && mate_in(before).is_none(),
+        _ => true,
+    }
+}
+
+fn mine_game(searcher: &mut Search, config: &Config, pgn_game: &PgnGame) -> Vec<Puzzle> {
+    let mut puzzles = Vec::new();
+    let mut game = GameState::new(pgn_game.start);
+    let mut previous_best: Option<i32> = None; // For the opponent, before their move
+    for (ply, &played) in pgn_game.moves.iter().enumerate() {
+        let lines = analyse(searcher, config, &game, 2);
+        let best = lines.first().map(|&(_, score)| score);
+        let mistake = best.is_some_and(|now| is_mistake(previous_best.map(|s| -s), now));
+        if mistake && is_unique(&lines) {
+            if let Some(puzzle) = solve(searcher, config, &game, lines, ply) {
+                puzzles.push(puzzle);
+            }
+        }
+        previous_best = best;
+        game.make_move(played);
+    }
+    puzzles
+}
+
+/// Follows the winning move for as long as it stays the only one, with the
+/// opponent's best defence in between. `lines` are the first position's.
+fn solve(
+    searcher: &mut Search,
+    config: &Config,
+    game: &GameState,
+    mut lines: Vec<(Move, i32)>,
+    ply: usize,
+) -> Option<Puzzle> {
+    let mate = mate_in(lines[0].1);
+    if mate.is_some_and(|n| n > MAX_MATE) {
+        return None;
+    }
+    let max_moves = mate.map_or(config.max_moves, |n| n as usize);
+
+    let solver = game.board.side_to_move;
+    let mut position = game.clone();
+    let mut line = Vec::new();
+    let mut mated = false;
+    for n in 0..max_moves {
+        if n > 0 {
+            lines = analyse(searcher, config, &position, 2);
+            if !is_unique(&lines) {
+                break;
+            }
+        }
+        let Some(&(m, _)) = lines.first() else {
+            break;
+        };
+        line.push(m);
+        position.make_move(m);
+        if !position.board.has_legal_moves() {
+            mated = position.board.is_in_check();
+            break;
+        }
+        if n + 1 == max_moves {
+            break;
+        }
+        let Some(&(reply, _)) = analyse(searcher, config, &position, 1).first() else {
+            break;
+        };
+        line.push(reply);
+        position.make_move(reply);
+    }
+    // The solver always has the last move
+    if position.board.side_to_move == solver {
+        line.pop();
+    }
+    if line.is_empty() || (mate.is_some() && !mated) {
+        return None;
+    }
+
+    let mate = mated.then(|| line.len().div_ceil(2));
+    let themes = themes(&game.board, &line, mate);
+    Some(Puzzle {
+        board: game.board,
+        line,
+        themes,
+        ply,
+    })
+}
+
+fn themes(board: &Board, line: &[Move], mate: Option<usize>) -> Vec<String> {
+    let mut themes = Vec::new();
+    if let Some(n) = mate {
+        themes.push(format!("mateIn{}", n));
+    }
+    // The solver's moves are every other one
+    let mut position = *board;
+    for (i, &m) in line.iter().enumerate() {
+        let next = position.make_move(m);
+        if i % 2 == 0 {
+            for (found, theme) in [
+                (is_fork(&position, &next, m), "fork"),
+                (is_pin(&position, &next), "pin"),
+                (
+                    is_discovered_attack(&position, &next, m),
+                    "discoveredAttack",
+                ),
+            ] {
+                if found && !themes.iter().any(|t| t == theme) {
+                    themes.push(theme.to_string());
+                }
+            }
+        }
+        position = next;
+    }
+    if themes.is_empty() {
+        themes.push("advantage".to_string());
+    }
+    themes
+}
+
+/// Squares attacked by a `piece` of `color` on `sq`.
+fn attacks(board: &Board, piece: PieceType, color: Color, sq: u8, occ: u64) -> u64 {
+    let tables = &crate::tables::ATTACKS;
+    match piece {
+        PieceType::Pawn => tables.pawn[color as usize][sq as usize],
+        PieceType::Knight => tables.knight[sq as usize],
+        PieceType::Bishop => board.get_bishop_attacks(sq, occ),
+        PieceType::Rook => board.get_rook_attacks(sq, occ),
+        PieceType::Queen => board.get_bishop_attacks(sq, occ) | board.get_rook_attacks(sq, occ),
+        PieceType::King => tables.king[sq as usize],
+    }
+}
+
+/// The moved piece attacks two pieces that are worth more than it, or are
+/// the king, or are left undefended. Pawns don't count as targets.
+fn is_fork(before: &Board, after: &Board, m: Move) -> bool {
+    let us = before.side_to_move;
+    let them = us.opponent();
+    let Some((piece, _)) = after.get_piece_at(m.to()) else {
+        return false;
+    };
+    let targets = attacks(after, piece, us, m.to(), after.occupied())
+        & after.by_color[them as usize]
+        & !after.by_type[PieceType::Pawn as usize];
+    let mut bb = targets;
+    let mut count = 0;
+    while bb != 0 {
+        let sq = bb.trailing_zeros() as u8;
+        bb &= bb - 1;
+        let Some((target, _)) = after.get_piece_at(sq) else {
+            continue;
+        };
+        if target == PieceType::King
+            || VALUES[target as usize] > VALUES[piece as usize]
+            || !after.is_square_attacked(sq, them)
+        {
+            count += 1;
+        }
+    }
+    count >= 2
+}
+
+/// The move pins a piece of the opponent to its king.
+fn is_pin(before: &Board, after: &Board) -> bool {
+    // Their pins before the move, as if it were their turn
+    let mut theirs = *before;
+    theirs.side_to_move = before.side_to_move.opponent();
+    after.pins & !theirs.calculate_pins() != 0
+}
+
+/// Moving away uncovers an attack of another piece on the king, a queen or
+/// a rook.
+fn is_discovered_attack(before: &Board, after: &Board, m: Move) -> bool {
+    let us = before.side_to_move;
+    let them = us.opponent();
+    let targets = after.by_color[them as usize]
+        & (after.by_type[PieceType::King as usize]
+            | after.by_type[PieceType::Queen as usize]
+            | after.by_type[PieceType::Rook as usize]);
+    let mut sliders = after.by_color[us as usize]
+        & (after.by_type[PieceType::Bishop as usize]
+            | after.by_type[PieceType::Rook as usize]
+            | after.by_type[PieceType::Queen as usize])
+        & !(1u64 << m.to());
+    while sliders != 0 {
+        let sq = sliders.trailing_zeros() as u8;
+        sliders &= sliders - 1;
+        let Some((piece, _)) = after.get_piece_at(sq) else {
+            continue;
+        };
+        let uncovered = attacks(after, piece, us, sq, after.occupied())
+            & !attacks(before, piece, us, sq, before.occupied());
+        if uncovered & targets != 0 {
+            return true;
+        }
+    }
+    false
+}
+
+fn write_puzzle(out: &mut dyn Write, puzzle: &Puzzle, game: &PgnGame) -> io::Result<()> {
+    let uci: Vec<String> = puzzle.line.iter().map(|m| m.to_string()).collect();
+    let san = pgn::line_to_san(&puzzle.board, &puzzle.line);
+    let fields = [
+        puzzle.board.to_fen(),
+        uci.join(" "),
+        san.join(" "),
+        puzzle.themes.join(" "),
+        game.tag("White").unwrap_or("?").to_string(),
+        game.tag("Black").unwrap_or("?").to_string(),
+        (puzzle.ply + 1).to_string(),
+    ];
+    let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+    writeln!(out, "{}", fields.join(","))
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -1,13 +1,111 @@
 use crate::board::Board;
+use crate::experience::Experience;
+use crate::game::GameState;
+use crate::history::{self, CaptureHistory, ContinuationHistory, PieceTo};
+use crate::mate::{MateResult, MateSearch};
 use crate::movegen::MoveGen;
-use crate::types::{Move, PieceType, INFINITY, MATE_VALUE};
+use crate::params::SearchParams;
+use crate::pawns::PawnTable;
+use crate::platform;
+use crate::skill::{Skill, MIN_CANDIDATES};
+#[cfg(feature = "stats")]
+use crate::stats::SearchStats;
+#[cfg(any(feature = "search-trace", feature = "stats"))]
+use crate::trace::Cut;
+#[cfg(feature = "search-trace")]
+use crate::trace::{NodeKind, SearchTree};
+use crate::types::{Color, Move, PieceType, CONTEMPT, INFINITY, MATE_VALUE};
+use lazy_static::lazy_static;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-const MAX_PLY: usize = 128;
+/// Records into the search tree with the `search-trace` feature, while a
+/// recording is under way. Other builds leave it out.
+macro_rules! trace {
+    ($search:ident, $tree:ident => $body:expr) => {
+        #[cfg(feature = "search-trace")]
+        if let Some($tree) = $search.trace.as_mut() {
+            $body;
+        }
+    };
+}
+
+/// Counts into the search statistics with the `stats` feature. Other builds
+/// leave it out.
+macro_rules! stat {
+    ($search:ident, $stats:ident => $body:expr) => {
+        #[cfg(feature = "stats")]
+        {
+            let $stats = &mut $search.stats;
+            $body;
+        }
+    };
+}
+
+pub const MAX_PLY: usize = 128;
 const DEFAULT_TT_SIZE: usize = 4 * 1024 * 1024; // ~128MB
 
+/// Static evaluation of a position in check, which has none.
+const NO_EVAL: i32 = -INFINITY;
+
+/// A quiet move's reduction shrinks by a ply per this much history, up to two.
+const HISTORY_REDUCTION_DIVISOR: i32 = 8192;
+
+/// Late quiet moves with less history than this per ply of depth are pruned.
+const HISTORY_PRUNING_MARGIN: i32 = -4096;
+
+lazy_static! {
+    /// Late move reductions in plies by depth and by the number of moves
+    /// searched before: `0.75 + ln(depth) * ln(moves) / 2.25`.
+    static ref REDUCTIONS: [[u8; 64]; 64] = {
+        let mut table = [[0; 64]; 64];
+        for (depth, row) in table.iter_mut().enumerate().skip(1) {
+            for (moves, r) in row.iter_mut().enumerate().skip(1) {
+                *r = (0.75 + (depth as f64).ln() * (moves as f64).ln() / 2.25) as u8;
+            }
+        }
+        table
+    };
+}
+
+/// Material values for delta pruning, as SEE counts them.
+const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 20000];
+
+/// Singular extensions are tried from this depth on.
+const SINGULAR_DEPTH: u8 = 6;
+
+/// Keeps double extensions from growing a line without end.
+const MAX_DOUBLE_EXTENSIONS: u8 = 6;
+
+/// Moves are pruned by SEE up to this depth.
+const SEE_PRUNING_DEPTH: u8 = 6;
+
+/// ProbCut searches this much shallower to show a capture beats beta by a margin.
+const PROBCUT_REDUCTION: u8 = 4;
+
+/// Rough guess at the engine's own rating, only used to size contempt
+/// against a rated opponent. Nothing has measured it.
+const OWN_RATING: i32 = 2400;
+
+/// What the search keeps about each ply of the line it is on.
+#[derive(Clone, Copy)]
+struct StackEntry {
+    static_eval: i32,            // NO_EVAL in check
+    current_move: Option<Move>,  // None for a null move
+    piece_to: Option<PieceTo>,   // Of the current move
+    excluded_move: Option<Move>, // Left out to see if the TT move is singular
+    double_extensions: u8,       // Along the line up to here
+}
+
+const EMPTY_STACK_ENTRY: StackEntry = StackEntry {
+    static_eval: NO_EVAL,
+    current_move: None,
+    piece_to: None,
+    excluded_move: None,
+    double_extensions: 0,
+};
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum TTFlag {
     Exact,
@@ -24,45 +122,147 @@ pub struct TTEntry {
     pub flag: TTFlag,
 }
 
+fn tt_entries(mb_size: usize) -> usize {
+    let entry_size = std::mem::size_of::<Option<TTEntry>>();
+    ((mb_size * 1024 * 1024) / entry_size).max(1)
+}
+
+/// One iteration's result, handed to the protocol front end for printing.
+#[derive(Clone)]
+pub struct SearchInfo {
+    pub depth: u8,
+    pub multipv: Option<usize>, // Line number when searching several
+    pub score: i32,             // Side to move's point of view
+    pub wdl: Option<(i32, i32, i32)>,
+    pub nodes: u64,
+    pub time_ms: u64,
+    pub pv: Vec<Move>,
+}
+
+impl SearchInfo {
+    /// Moves to mate, negative when getting mated.
+    pub fn mate_in(&self) -> Option<i32> {
+        if self.score >= MATE_VALUE - MAX_PLY as i32 {
+            Some((MATE_VALUE - self.score + 1) / 2)
+        } else if self.score <= -MATE_VALUE + MAX_PLY as i32 {
+            Some(-(MATE_VALUE + self.score + 1) / 2)
+        } else {
+            None
+        }
+    }
+
+    pub fn nps(&self) -> u64 {
+        if self.time_ms > 0 {
+            (self.nodes as u128 * 1000 / self.time_ms as u128) as u64
+        } else {
+            0
+        }
+    }
+}
+
+pub type InfoCallback = Box<dyn FnMut(&SearchInfo) + Send>;
+
 pub struct Search {
     pub nodes: u64,
     pub killers: [[Option<Move>; 2]; MAX_PLY],
+    stack: [StackEntry; MAX_PLY],
     pub history: [[i32; 64]; 64],
+    pub continuation_history: ContinuationHistory,
+    pub capture_history: CaptureHistory,
     pub countermoves: [[Option<Move>; 64]; 64],
     pub tt: Arc<Mutex<Vec<Option<TTEntry>>>>,
+    pub pawn_table: PawnTable,
     pub start_time: Option<Instant>,
     pub time_limit: Option<Duration>,
     pub stop_search: bool,
+    needs_move: bool, // Stops wait until the first depth has given a root move
     pub tt_size: usize,
-    pub stop_flag: Arc<AtomicBool>,
-    pub game_history: [u64; 1024],
-    pub game_history_count: usize,
+    pub stop_flag: Arc<AtomicBool>, // Raised from another thread, which clears it before a search
+    pub game_history: Vec<u64>,
+    root_index: usize,
+    pub multi_pv: usize,
+    pub root_lines: Vec<(Move, i32)>, // Best lines of the last full iteration, best first
+    pub skill: Skill,
+    pub node_limit: Option<u64>, // Set by the caller
+    max_nodes: Option<u64>,
+    pub experience: Experience,
+    experience_seeded: bool,
+    pub dynamic_contempt: bool,
+    pub opponent_elo: Option<i32>,
+    contempt: i32, // For the root side to move
+    root_color: Color,
+    pub show_wdl: bool,
+    pub normalize_score: bool,
+    pub params: SearchParams,
+    pub on_info: Option<InfoCallback>,
+    #[cfg(feature = "search-trace")]
+    pub trace: Option<SearchTree>, // Records the tree when set
+    #[cfg(feature = "stats")]
+    pub stats: SearchStats, // Of the last `go`
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Search {
     pub fn new() -> Self {
+        Self::with_tt_size(DEFAULT_TT_SIZE)
+    }
+
+    pub fn with_hash(mb_size: usize) -> Self {
+        Self::with_tt_size(tt_entries(mb_size))
+    }
+
+    fn with_tt_size(tt_size: usize) -> Self {
         Search {
             nodes: 0,
             killers: [[None; 2]; MAX_PLY],
+            stack: [EMPTY_STACK_ENTRY; MAX_PLY],
             history: [[0; 64]; 64],
+            continuation_history: ContinuationHistory::new(),
+            capture_history: [[[0; 6]; 64]; 12],
             countermoves: [[None; 64]; 64],
-            tt: Arc::new(Mutex::new(vec![None; DEFAULT_TT_SIZE])),
+            tt: Arc::new(Mutex::new(vec![None; tt_size])),
+            pawn_table: PawnTable::new(),
             start_time: None,
             time_limit: None,
             stop_search: false,
-            tt_size: DEFAULT_TT_SIZE,
+            needs_move: false,
+            tt_size,
             stop_flag: Arc::new(AtomicBool::new(false)),
-            game_history: [0; 1024],
-            game_history_count: 0,
+            game_history: Vec::new(),
+            root_index: 0,
+            multi_pv: 1,
+            root_lines: Vec::new(),
+            skill: Skill::new(),
+            node_limit: None,
+            max_nodes: None,
+            experience: Experience::new(),
+            experience_seeded: false,
+            dynamic_contempt: false,
+            opponent_elo: None,
+            contempt: 0,
+            root_color: Color::White,
+            show_wdl: false,
+            normalize_score: false,
+            params: SearchParams::default(),
+            on_info: None,
+            #[cfg(feature = "search-trace")]
+            trace: None,
+            #[cfg(feature = "stats")]
+            stats: SearchStats::default(),
         }
     }
 
     pub fn resize_tt(&mut self, mb_size: usize) {
-        let entry_size = std::mem::size_of::<Option<TTEntry>>();
-        let new_size = (mb_size * 1024 * 1024) / entry_size;
+        let new_size = tt_entries(mb_size);
         self.tt_size = new_size;
         let mut tt = self.tt.lock().unwrap();
         *tt = vec![None; new_size];
+        self.experience_seeded = false;
     }
 
     pub fn clear_tt(&mut self) {
@@ -72,9 +272,29 @@ impl Search {
                 *entry = None;
             }
         }
+        self.pawn_table.clear();
         self.clear_history();
         self.clear_killers();
         self.countermoves = [[None; 64]; 64];
+        self.experience_seeded = false;
+    }
+
+    /// Puts the best remembered move of every experience position in the TT.
+    /// Only the move: a score from another game and search proves nothing
+    /// here, so it goes in as a bound no search can cut on.
+    fn seed_tt_from_experience(&mut self) {
+        let seeds: Vec<_> = self
+            .experience
+            .iter()
+            .filter(|e| self.experience.best(e.key).is_some_and(|b| b.m == e.m))
+            .map(|e| (e.key, e.m))
+            .collect();
+        for (key, m) in seeds {
+            if self.probe_tt(key).is_none() {
+                self.store_tt(key, Some(m), -INFINITY, 0, TTFlag::LowerBound);
+            }
+        }
+        self.experience_seeded = true;
     }
 
     pub fn clear_history(&mut self) {
@@ -83,6 +303,8 @@ impl Search {
                 self.history[i][j] = 0;
             }
         }
+        self.continuation_history.clear();
+        self.capture_history = [[[0; 6]; 64]; 12];
     }
 
     pub fn clear_killers(&mut self) {
@@ -91,14 +313,28 @@ impl Search {
         }
     }
 
-    fn should_stop(&self) -> bool {
-        if self.stop_search || self.stop_flag.load(Ordering::Relaxed) {
+    /// Whether the search was stopped, by its limits or from outside.
+    fn stopped(&self) -> bool {
+        !self.needs_move && (self.stop_search || self.stop_flag.load(Ordering::Relaxed))
+    }
+
+    /// Limits stop the search through `stop_search`, leaving `stop_flag` to
+    /// the thread that started it.
+    fn should_stop(&mut self) -> bool {
+        if self.needs_move {
+            return false;
+        }
+        if self.stopped() {
+            return true;
+        }
+        if self.max_nodes.is_some_and(|n| self.nodes >= n) {
+            self.stop_search = true;
             return true;
         }
         if self.nodes % 2048 == 0 {
             if let (Some(start), Some(limit)) = (self.start_time, self.time_limit) {
                 if start.elapsed() >= limit {
-                    self.stop_flag.store(true, Ordering::Relaxed);
+                    self.stop_search = true;
                     return true;
                 }
             }
@@ -106,15 +342,52 @@ impl Search {
         false
     }
 
-    fn is_repetition(&self, hash: u64) -> bool {
-        for i in 0..self.game_history_count.saturating_sub(1) {
-            if self.game_history[i] == hash {
-                return true;
+    /// A position repeated inside the search tree is a draw right away, while
+    /// one from before the root has to have occurred twice already.
+    fn is_repetition(&self, board: &Board) -> bool {
+        let current = self.game_history.len() - 1;
+        let window = (board.halfmove_clock as usize).min(current);
+        let mut count = 0;
+        for i in (current - window..current).rev().skip(1).step_by(2) {
+            if self.game_history[i] == board.hash {
+                if i >= self.root_index {
+                    return true;
+                }
+                count += 1;
+                if count >= 2 {
+                    return true;
+                }
             }
         }
         false
     }
 
+    /// A draw is worth `-contempt` to the side to move at the root, so a
+    /// positive contempt makes it play on. The static eval stays symmetric.
+    fn draw_score(&self, board: &Board) -> i32 {
+        if board.side_to_move == self.root_color {
+            -self.contempt
+        } else {
+            self.contempt
+        }
+    }
+
+    /// The Contempt option, plus with dynamic contempt a share that grows
+    /// with the root score and one from the rating gap to the opponent.
+    fn compute_contempt(&self, root_score: i32) -> i32 {
+        let mut contempt = CONTEMPT.load(Ordering::Relaxed);
+        if self.dynamic_contempt {
+            if root_score.abs() < MATE_VALUE - MAX_PLY as i32 {
+                contempt += 50 * root_score / (root_score.abs() + 200);
+            }
+            // A weakened engine has no rating to compare
+            if let Some(opponent) = self.opponent_elo.filter(|_| !self.skill.enabled()) {
+                contempt += ((OWN_RATING - opponent) / 20).clamp(-40, 40);
+            }
+        }
+        contempt
+    }
+
     fn adjust_mate_score_to_tt(&self, score: i32, ply: usize) -> i32 {
         if score >= MATE_VALUE - MAX_PLY as i32 {
             score + ply as i32
@@ -137,95 +410,213 @@ impl Search {
 
     pub fn go(
         &mut self,
-        board: &Board,
-        max_depth: u8,
+        game: &GameState,
+        mut max_depth: u8,
         time_limit: Option<Duration>,
     ) -> Option<Move> {
         self.nodes = 0;
         self.stop_search = false;
-        self.stop_flag.store(false, Ordering::Relaxed);
-        self.start_time = Some(Instant::now());
+        self.start_time = platform::now();
         self.time_limit = time_limit;
+        self.root_lines.clear();
+        stat!(self, stats => *stats = SearchStats::default());
+
+        let board = &game.board;
+        // Keep the positions before the root so the search sees repetitions with them
+        self.game_history.clear();
+        self.game_history.extend_from_slice(game.hashes());
+        self.root_index = self.game_history.len() - 1;
+        self.root_color = board.side_to_move;
+        self.contempt = self.compute_contempt(0);
+
+        // A weakened engine searches less and picks among several candidates,
+        // but only the lines the GUI asked for are reported
+        let shown_pv = self.multi_pv.max(1);
+        let mut multi_pv = shown_pv;
+        self.max_nodes = self.node_limit;
+        if self.start_time.is_none() {
+            // Without a clock a time limit can only be kept by counting nodes
+            if let Some(limit) = time_limit {
+                let nodes = limit.as_millis() as u64 * platform::NODES_PER_MS;
+                self.max_nodes = Some(self.max_nodes.map_or(nodes, |n| n.min(nodes)));
+            }
+        }
+        if self.skill.enabled() {
+            multi_pv = multi_pv.max(MIN_CANDIDATES);
+            max_depth = max_depth.min(self.skill.depth_limit());
+            let nodes = self.skill.node_limit();
+            self.max_nodes = Some(self.max_nodes.map_or(nodes, |n| n.min(nodes)));
+            self.skill.new_search();
+        }
 
-        self.game_history[0] = board.hash;
-        self.game_history_count = 1;
+        if self.experience.enabled && !self.experience_seeded {
+            self.seed_tt_from_experience();
+        }
 
         let mut best_move = None;
         let mut last_best_move = None;
-        let mut aspiration_window = 35;
+        let mut aspiration_window = self.params.aspiration_window;
         let mut last_score = 0;
+        let mut completed_depth = 0;
+        // A stop that comes right away still gets a move to play
+        self.needs_move = true;
 
         for d in 1..=max_depth {
             if self.should_stop() {
                 break;
             }
 
-            let mut alpha = -INFINITY;
-            let mut beta = INFINITY;
-
-            if d > 4 {
-                alpha = last_score - aspiration_window;
-                beta = last_score + aspiration_window;
+            if d > 1 {
+                self.contempt = self.compute_contempt(last_score);
             }
 
-            loop {
-                let (m_opt, score) = self.root_search(board, d, alpha, beta);
-                if self.should_stop() && d > 1 {
-                    break;
+            let mut lines: Vec<(Move, i32)> = Vec::new();
+            for pv_idx in 0..multi_pv {
+                let excluded: Vec<Move> = lines.iter().map(|&(m, _)| m).collect();
+                let mut alpha = -INFINITY;
+                let mut beta = INFINITY;
+
+                if d > 4 && pv_idx == 0 {
+                    alpha = last_score - aspiration_window;
+                    beta = last_score + aspiration_window;
                 }
 
-                if let Some(m) = m_opt {
-                    if score <= alpha {
-                        alpha = (alpha - aspiration_window).max(-INFINITY);
-                        aspiration_window = (aspiration_window as f64 * 1.6) as i32;
-                    } else if score >= beta {
-                        beta = (beta + aspiration_window).min(INFINITY);
-                        aspiration_window = (aspiration_window as f64 * 1.6) as i32;
-                    } else {
-                        best_move = Some(m);
-                        last_best_move = Some(m);
-                        last_score = score;
-
-                        let score_str = if score >= MATE_VALUE - MAX_PLY as i32 {
-                            format!("mate {}", (MATE_VALUE - score + 1) / 2)
-                        } else if score <= -MATE_VALUE + MAX_PLY as i32 {
-                            format!("mate {}", -(-MATE_VALUE - score + 1) / 2)
-                        } else {
-                            format!("cp {}", score)
-                        };
-
-                        let elapsed = self
-                            .start_time
-                            .map(|s| s.elapsed().as_millis() as u64)
-                            .unwrap_or(0);
-                        let nps = if elapsed > 0 {
-                            (self.nodes as u128 * 1000 / elapsed as u128) as u64
+                loop {
+                    let (m_opt, score) = self.root_search(board, d, alpha, beta, &excluded);
+                    if self.should_stop() && d > 1 {
+                        break;
+                    }
+
+                    if let Some(m) = m_opt {
+                        if score <= alpha {
+                            alpha = (alpha - aspiration_window).max(-INFINITY);
+                            aspiration_window =
+                                aspiration_window * self.params.aspiration_growth / 100;
+                        } else if score >= beta {
+                            beta = (beta + aspiration_window).min(INFINITY);
+                            aspiration_window =
+                                aspiration_window * self.params.aspiration_growth / 100;
                         } else {
-                            0
-                        };
-
-                        println!(
-                            "info depth {} score {} nodes {} time {} nps {} pv {}",
-                            d,
-                            score_str,
-                            self.nodes,
-                            elapsed,
-                            nps,
-                            m.to_string()
-                        );
-                        aspiration_window = (aspiration_window as f64 * 0.8).max(35.0) as i32;
+                            if pv_idx == 0 {
+                                best_move = Some(m);
+                                last_best_move = Some(m);
+                                last_score = score;
+                                aspiration_window =
+                                    (aspiration_window * 4 / 5).max(self.params.aspiration_window);
+                            }
+                            lines.push((m, score));
+                            if pv_idx < shown_pv {
+                                self.report(board, d, pv_idx + 1, shown_pv, score, m);
+                            }
+                            break;
+                        }
+                    } else {
                         break;
                     }
-                } else {
-                    break;
+                    if aspiration_window > 2500 {
+                        alpha = -INFINITY;
+                        beta = INFINITY;
+                    }
                 }
-                if aspiration_window > 2500 {
-                    alpha = -INFINITY;
-                    beta = INFINITY;
+
+                if lines.len() <= pv_idx || (self.should_stop() && d > 1) {
+                    break;
                 }
             }
+            if !lines.is_empty() && (lines.len() == multi_pv || !self.should_stop()) {
+                self.root_lines = lines;
+                completed_depth = d;
+            }
+            self.needs_move = false;
+        }
+        self.needs_move = false;
+
+        if self.skill.enabled() {
+            let lines = std::mem::take(&mut self.root_lines);
+            if let Some(m) = self.skill.pick_best(&lines) {
+                last_best_move = Some(m);
+            }
+            self.root_lines = lines;
+        }
+
+        let chosen = last_best_move.or(best_move);
+        if let Some(&(m, score)) = self.root_lines.iter().find(|l| Some(l.0) == chosen) {
+            if let Err(e) = self
+                .experience
+                .record(board.hash, m, score, completed_depth)
+            {
+                eprintln!("Error: Failed to write experience file: {}", e);
+            }
+        }
+        chosen
+    }
+
+    /// `go mate`: the shortest forced mate in at most `moves` moves from the
+    /// df-pn solver, reported with its whole line when found.
+    pub fn solve_mate(
+        &mut self,
+        game: &GameState,
+        moves: u32,
+        time_limit: Option<Duration>,
+    ) -> MateResult {
+        self.start_time = platform::now();
+        let mut solver = MateSearch::new(self.stop_flag.clone(), self.tt_size);
+        solver.time_limit = time_limit;
+        solver.node_limit = self.node_limit;
+        let result = solver.solve(&game.board, moves);
+        self.nodes = solver.nodes;
+
+        if let MateResult::Mate(moves, pv) = &result {
+            let plies = 2 * *moves as i32 - 1;
+            let info = SearchInfo {
+                depth: plies as u8,
+                multipv: None,
+                score: MATE_VALUE - plies,
+                wdl: self.show_wdl.then_some((1000, 0, 0)),
+                nodes: self.nodes,
+                time_ms: self
+                    .start_time
+                    .map(|s| s.elapsed().as_millis() as u64)
+                    .unwrap_or(0),
+                pv: pv.clone(),
+            };
+            if let Some(callback) = self.on_info.as_mut() {
+                callback(&info);
+            }
+        }
+        result
+    }
+
+    fn report(
+        &mut self,
+        board: &Board,
+        depth: u8,
+        pv_idx: usize,
+        multi_pv: usize,
+        score: i32,
+        m: Move,
+    ) {
+        let time_ms = self
+            .start_time
+            .map(|s| s.elapsed().as_millis() as u64)
+            .unwrap_or(0);
+        let is_mate = score.abs() >= MATE_VALUE - MAX_PLY as i32;
+        let info = SearchInfo {
+            depth,
+            multipv: (multi_pv > 1).then_some(pv_idx),
+            score: if self.normalize_score && !is_mate {
+                crate::wdl::normalize(score, board)
+            } else {
+                score
+            },
+            wdl: self.show_wdl.then(|| crate::wdl::wdl(score, board)),
+            nodes: self.nodes,
+            time_ms,
+            pv: vec![m],
+        };
+        if let Some(callback) = self.on_info.as_mut() {
+            callback(&info);
         }
-        last_best_move.or(best_move)
     }
 
     fn root_search(
@@ -234,38 +625,46 @@ impl Search {
         depth: u8,
         mut alpha: i32,
         beta: i32,
+        excluded: &[Move],
     ) -> (Option<Move>, i32) {
         let mut best_move = None;
         let mut best_score = -INFINITY;
+        trace!(self, tree => tree.enter(NodeKind::Root, 0, None, false, depth as i32, alpha, beta));
 
         let mut moves = MoveGen::generate(board);
-        let tt_move = self.probe_tt(board.hash).and_then(|e| e.m);
+        moves.retain(|m| !excluded.contains(m));
+        let use_experience = self.experience.enabled;
+        let mut tt_move = self.probe_tt(board.hash).and_then(|e| e.m);
+        if use_experience && tt_move.is_none() {
+            tt_move = self.experience.best(board.hash).map(|e| e.m);
+        }
         self.order_moves(board, &mut moves, 0, tt_move, None);
+        if use_experience {
+            // Moves that have mostly lost before are tried last
+            moves.sort_by_key(|m| {
+                self.experience
+                    .find(board.hash, *m)
+                    .is_some_and(|e| e.is_losing())
+            });
+        }
 
         for (i, m) in moves.iter().enumerate() {
             if !board.is_legal(*m) {
                 continue;
             }
             let next_board = board.make_move(*m);
+            self.set_current_move(board, Some(*m), 0);
 
+            // Experience only orders the moves, each one is still searched in
+            // the current game's context
             let mut score;
             if i == 0 {
-                self.game_history[self.game_history_count] = next_board.hash;
-                self.game_history_count += 1;
-                score = -self.alpha_beta(
-                    &next_board,
-                    depth - 1,
-                    -beta,
-                    -alpha,
-                    1,
-                    true,
-                    true,
-                    Some(*m),
-                );
-                self.game_history_count -= 1;
+                self.game_history.push(next_board.hash);
+                score =
+                    -self.alpha_beta(&next_board, depth - 1, -beta, -alpha, 1, false, true, true);
+                self.game_history.pop();
             } else {
-                self.game_history[self.game_history_count] = next_board.hash;
-                self.game_history_count += 1;
+                self.game_history.push(next_board.hash);
                 score = -self.alpha_beta(
                     &next_board,
                     depth - 1,
@@ -274,23 +673,22 @@ impl Search {
                     1,
                     true,
                     true,
-                    Some(*m),
+                    true,
                 );
-                self.game_history_count -= 1;
+                self.game_history.pop();
                 if score > alpha && score < beta {
-                    self.game_history[self.game_history_count] = next_board.hash;
-                    self.game_history_count += 1;
+                    self.game_history.push(next_board.hash);
                     score = -self.alpha_beta(
                         &next_board,
                         depth - 1,
                         -beta,
                         -alpha,
                         1,
+                        false,
                         true,
                         true,
-                        Some(*m),
                     );
-                    self.game_history_count -= 1;
+                    self.game_history.pop();
                 }
             }
 
@@ -307,46 +705,103 @@ impl Search {
             }
         }
 
-        if !self.stop_flag.load(Ordering::Relaxed) && best_move.is_some() {
+        // Lines after the first leave out better moves, so only the first is stored
+        if !self.stopped() && best_move.is_some() && excluded.is_empty() {
             self.store_tt(board.hash, best_move, best_score, depth, TTFlag::Exact);
         }
+        trace!(self, tree => tree.leave(Some(best_score)));
         (best_move, best_score)
     }
 
+    /// Searches a node, recorded in the search tree on the way in and out.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(not(feature = "search-trace"), inline(always))]
     fn alpha_beta(
+        &mut self,
+        board: &Board,
+        depth: u8,
+        alpha: i32,
+        beta: i32,
+        ply: usize,
+        cut_node: bool,
+        allow_null: bool,
+        allow_singular: bool,
+    ) -> i32 {
+        trace!(self, tree => tree.enter(
+            NodeKind::Search,
+            ply,
+            ply.checked_sub(1).and_then(|i| self.stack[i].current_move),
+            self.stack[ply.min(MAX_PLY - 1)].excluded_move.is_some(),
+            depth as i32,
+            alpha,
+            beta,
+        ));
+        let score = self.search_node(
+            board,
+            depth,
+            alpha,
+            beta,
+            ply,
+            cut_node,
+            allow_null,
+            allow_singular,
+        );
+        // A stopped search's scores mean nothing
+        trace!(self, tree => tree.leave(
+            (self.needs_move || !self.stop_search && !self.stop_flag.load(Ordering::Relaxed))
+                .then_some(score)
+        ));
+        score
+    }
+
+    fn search_node(
         &mut self,
         board: &Board,
         mut depth: u8,
         mut alpha: i32,
         beta: i32,
         ply: usize,
+        cut_node: bool, // Expected to fail high
         allow_null: bool,
         allow_singular: bool,
-        last_move: Option<Move>,
     ) -> i32 {
         if self.should_stop() {
             return 0;
         }
         self.nodes += 1;
+        stat!(self, stats => stats.nodes += 1);
 
         if ply >= MAX_PLY {
-            return crate::eval::evaluate(board);
+            return crate::eval::evaluate(board, &mut self.pawn_table);
         }
-        if self.is_repetition(board.hash) || board.halfmove_clock >= 100 {
-            return 0;
+        if self.is_repetition(board)
+            || board.is_fifty_move_draw()
+            || board.is_insufficient_material()
+        {
+            return self.draw_score(board);
         }
 
+        // The TT holds the node with every move, so it can't answer without one
+        let excluded_move = self.stack[ply].excluded_move;
         let tt_entry = self.probe_tt(board.hash);
+        stat!(self, stats => {
+            stats.tt_probes += 1;
+            stats.tt_hits += tt_entry.is_some() as u64;
+        });
         let mut tt_move = None;
         if let Some(e) = tt_entry {
             tt_move = e.m;
-            if e.depth >= depth {
+            if e.depth >= depth && excluded_move.is_none() {
                 let adjusted_score = self.adjust_mate_score_from_tt(e.score, ply);
-                match e.flag {
-                    TTFlag::Exact => return adjusted_score,
-                    TTFlag::LowerBound if adjusted_score >= beta => return adjusted_score,
-                    TTFlag::UpperBound if adjusted_score <= alpha => return adjusted_score,
-                    _ => {}
+                let cutoff = match e.flag {
+                    TTFlag::Exact => true,
+                    TTFlag::LowerBound => adjusted_score >= beta,
+                    TTFlag::UpperBound => adjusted_score <= alpha,
+                };
+                if cutoff {
+                    trace!(self, tree => tree.cut(Cut::Tt));
+                    stat!(self, stats => stats.count(Cut::Tt));
+                    return adjusted_score;
                 }
             }
         }
@@ -356,19 +811,42 @@ impl Search {
             depth += 1;
         }
         if depth == 0 {
-            return self.quiescence(board, alpha, beta, ply);
+            return self.quiescence(board, alpha, beta, ply, true);
         }
 
-        let eval = crate::eval::evaluate(board);
+        let pv_node = beta - alpha > 1;
+        let last_move = ply.checked_sub(1).and_then(|i| self.stack[i].current_move);
+        let eval = self.static_eval(board);
+        trace!(self, tree => tree.set_eval((!in_check).then_some(eval)));
+        // Improving on two plies ago, when this side was last to move
+        self.stack[ply].static_eval = if in_check { NO_EVAL } else { eval };
+        let improving = !in_check
+            && (ply < 2
+                || self.stack[ply - 2].static_eval == NO_EVAL
+                || eval > self.stack[ply - 2].static_eval);
+        // Children start without an excluded move of their own
+        if ply + 1 < MAX_PLY {
+            self.stack[ply + 1].excluded_move = None;
+            self.stack[ply + 1].double_extensions = self.stack[ply].double_extensions;
+        }
 
-        // RFP (Static Null Move Pruning)
-        if !in_check && depth <= 3 && ply > 0 && eval - (120 * depth as i32) >= beta {
-            return eval - (120 * depth as i32);
+        // RFP (Static Null Move Pruning), wanting a wider margin while improving
+        let rfp_margin = self.params.rfp_margin * (depth as i32 + improving as i32);
+        if !in_check
+            && depth <= 3
+            && ply > 0
+            && excluded_move.is_none()
+            && eval - rfp_margin >= beta
+        {
+            trace!(self, tree => tree.cut(Cut::Rfp));
+            stat!(self, stats => stats.count(Cut::Rfp));
+            return eval - rfp_margin;
         }
 
         // Null Move Pruning
         if allow_null
             && !in_check
+            && excluded_move.is_none()
             && depth >= 3
             && eval >= beta
             && board.occupied().count_ones() > 4
@@ -380,65 +858,120 @@ impl Search {
                 null_board.hash ^= crate::zobrist::ZOBRIST.ep[sq as usize];
             }
             null_board.ep_square = None;
+            // Repetitions can't span a null move
+            null_board.halfmove_clock = 0;
 
-            let r = 3 + depth / 4;
+            let r = (self.params.nmp_base_reduction + depth as i32 / self.params.nmp_depth_divisor)
+                as u8;
+            self.set_current_move(board, None, ply);
+            stat!(self, stats => stats.null_move_tries += 1);
             let score = -self.alpha_beta(
                 &null_board,
                 depth.saturating_sub(1 + r),
                 -beta,
                 -beta + 1,
                 ply + 1,
+                !cut_node,
                 false,
                 false,
-                None,
             );
             if score >= beta {
+                trace!(self, tree => tree.cut(Cut::Nmp));
+                stat!(self, stats => stats.count(Cut::Nmp));
                 return beta;
             }
         }
 
-        // Singular Extension
-        let mut extension = 0;
+        // ProbCut
+        let probcut_beta = beta + self.params.probcut_margin;
+        if !pv_node
+            && !in_check
+            && depth > PROBCUT_REDUCTION
+            && beta.abs() < MATE_VALUE - MAX_PLY as i32
+            && excluded_move.is_none()
+            // Not when the TT already says a search this deep fails to reach it
+            && !tt_entry.is_some_and(|e| {
+                e.depth + 3 >= depth
+                    && self.adjust_mate_score_from_tt(e.score, ply) < probcut_beta
+            })
+        {
+            if let Some(score) = self.probcut(board, depth, probcut_beta, eval, ply, cut_node) {
+                trace!(self, tree => tree.cut(Cut::ProbCut));
+                stat!(self, stats => stats.count(Cut::ProbCut));
+                return score;
+            }
+        }
 
         // IID (Internal Iterative Deepening)
-        if depth >= 6 && tt_move.is_none() {
+        if depth >= 6 && tt_move.is_none() && excluded_move.is_none() {
             let d = if depth > 8 { depth - 4 } else { depth - 2 };
-            self.alpha_beta(board, d, alpha, beta, ply, false, false, last_move);
+            self.alpha_beta(board, d, alpha, beta, ply, cut_node, false, false);
             if let Some(e) = self.probe_tt(board.hash) {
                 tt_move = e.m;
             }
         }
 
-        if allow_singular && depth >= 6 && tt_move.is_some() {
-            if let Some(e) = tt_entry {
-                if e.depth >= depth - 3 && e.flag != TTFlag::UpperBound {
-                    let tt_score = self.adjust_mate_score_from_tt(e.score, ply);
-                    let margin = 2 * depth as i32;
-                    let singular_beta = tt_score - margin;
-                    let score = self.alpha_beta(
-                        board,
-                        (depth - 1) / 2,
-                        singular_beta - 1,
-                        singular_beta,
-                        ply,
-                        false,
-                        false,
-                        last_move,
-                    );
-                    if score < singular_beta {
-                        extension = 1;
-                    }
+        // Singular Extension: the TT move is extended when every other move
+        // fails low against a margin below its score
+        let mut extension = 0;
+        let singular_entry = tt_entry.filter(|e| {
+            allow_singular
+                && depth >= SINGULAR_DEPTH
+                && excluded_move.is_none()
+                && e.m.is_some()
+                && e.m == tt_move
+                && e.depth + 3 >= depth
+                && e.flag != TTFlag::UpperBound
+                && e.score.abs() < MATE_VALUE - MAX_PLY as i32
+        });
+        if let Some(e) = singular_entry {
+            let tt_score = e.score;
+            let singular_beta = tt_score - self.params.singular_margin * depth as i32;
+            self.stack[ply].excluded_move = e.m;
+            let score = self.alpha_beta(
+                board,
+                (depth - 1) / 2,
+                singular_beta - 1,
+                singular_beta,
+                ply,
+                cut_node,
+                false,
+                false,
+            );
+            self.stack[ply].excluded_move = None;
+
+            if score < singular_beta {
+                extension = 1;
+                // Far below, with double extensions left on this line
+                if !pv_node
+                    && score < singular_beta - self.params.double_extension_margin
+                    && self.stack[ply].double_extensions < MAX_DOUBLE_EXTENSIONS
+                {
+                    extension = 2;
                 }
+            } else if singular_beta >= beta {
+                // Multi-cut: another move beats beta as well, so this node will
+                trace!(self, tree => tree.cut(Cut::MultiCut));
+                stat!(self, stats => stats.count(Cut::MultiCut));
+                return singular_beta;
+            } else if tt_score >= beta {
+                // Not singular but expected to cut anyway, so the TT move is reduced
+                extension = -1;
             }
         }
 
         let mut moves = MoveGen::generate(board);
         self.order_moves(board, &mut moves, ply, tt_move, last_move);
+        // Quiet moves are less likely to be best once a capture was
+        let tt_capture = tt_move.is_some_and(|m| m.is_capture());
 
         let mut best_score = -INFINITY;
         let mut best_move = None;
         let mut moves_searched = 0;
         let mut legal_moves_found = 0;
+        // Moves searched without a cutoff, penalised when a later one cuts
+        let mut quiets_tried = Vec::new();
+        let mut captures_tried = Vec::new();
 
         for m in moves {
             let next_board = board.make_move(m);
@@ -455,17 +988,30 @@ impl Search {
                 continue;
             }
 
+            if Some(m) == excluded_move {
+                continue;
+            }
             legal_moves_found += 1;
             let gives_check = next_board.is_in_check();
+            let is_quiet = !m.is_capture() && !m.is_promotion();
+            let history = if is_quiet {
+                self.quiet_history(board, m, ply)
+            } else {
+                0
+            };
 
-            // Late Move Pruning
+            // Late Move Pruning, keeping more moves while improving
+            let lmp_count =
+                (self.params.lmp_base + (depth * depth) as i32) * if improving { 3 } else { 2 } / 3;
             if !in_check
                 && depth <= 3
                 && !m.is_capture()
                 && !m.is_promotion()
-                && moves_searched >= (8 + depth * depth) as usize
+                && moves_searched >= lmp_count as usize
                 && !gives_check
             {
+                trace!(self, tree => tree.prune(m, Cut::Lmp));
+                stat!(self, stats => stats.count(Cut::Lmp));
                 continue;
             }
 
@@ -475,13 +1021,53 @@ impl Search {
                 && !m.is_capture()
                 && !m.is_promotion()
                 && !gives_check
-                && eval + 250 * depth as i32 <= alpha
+                && eval + self.params.futility_margin * (depth as i32 + improving as i32) <= alpha
+            {
+                trace!(self, tree => tree.prune(m, Cut::Futility));
+                stat!(self, stats => stats.count(Cut::Futility));
+                continue;
+            }
+
+            // SEE Pruning of moves that lose too much material
+            let see_threshold = if is_quiet {
+                self.params.see_quiet_margin * (depth as i32) * (depth as i32)
+            } else {
+                self.params.see_capture_margin * depth as i32
+            };
+            if !pv_node
+                && !in_check
+                && depth <= SEE_PRUNING_DEPTH
+                && moves_searched > 0
+                && best_score > -MATE_VALUE + MAX_PLY as i32
+                && !gives_check
+                && board.see_value(m) < see_threshold
             {
+                trace!(self, tree => tree.prune(m, Cut::See));
+                stat!(self, stats => stats.count(Cut::See));
+                continue;
+            }
+
+            // History Pruning
+            if !in_check
+                && !pv_node
+                && depth <= 3
+                && is_quiet
+                && moves_searched > 0
+                && !gives_check
+                && history < HISTORY_PRUNING_MARGIN * depth as i32
+            {
+                trace!(self, tree => tree.prune(m, Cut::History));
+                stat!(self, stats => stats.count(Cut::History));
                 continue;
             }
 
             let mut score;
-            let d = depth + extension;
+            // Only the TT move is extended, or reduced when it isn't singular
+            let new_depth = if Some(m) == tt_move {
+                (depth as i32 + extension - 1) as u8
+            } else {
+                depth - 1
+            };
 
             // Check for passed pawn push
             let is_pawn = (board.by_type[PieceType::Pawn as usize] & (1u64 << m.from())) != 0;
@@ -489,27 +1075,32 @@ impl Search {
                 let us = board.side_to_move;
                 let enemy_pawns = board.by_type[PieceType::Pawn as usize]
                     & board.by_color[us.opponent() as usize];
-                crate::eval::is_passed_pawn(m.to(), us, enemy_pawns)
+                crate::pawns::is_passed_pawn(m.to(), us, enemy_pawns)
             } else {
                 false
             };
 
+            self.set_current_move(board, Some(m), ply);
+            if ply + 1 < MAX_PLY {
+                // A double extension counts for the rest of the line
+                self.stack[ply + 1].double_extensions =
+                    self.stack[ply].double_extensions + u8::from(new_depth > depth);
+            }
+            self.game_history.push(next_board.hash);
+            trace!(self, tree => tree.set_next_child(0, new_depth as i32 + 1 - depth as i32));
             if moves_searched == 0 {
-                self.game_history[self.game_history_count] = next_board.hash;
-                self.game_history_count += 1;
                 score = -self.alpha_beta(
                     &next_board,
-                    d - 1,
+                    new_depth,
                     -beta,
                     -alpha,
                     ply + 1,
+                    !pv_node && !cut_node,
                     true,
                     true,
-                    Some(m),
                 );
-                self.game_history_count -= 1;
             } else {
-                let reduction = if d >= 3
+                let reduction = if new_depth >= 2
                     && moves_searched >= 3
                     && !m.is_capture()
                     && !m.is_promotion()
@@ -517,63 +1108,77 @@ impl Search {
                     && !gives_check
                     && !is_passed_pawn_push
                 {
-                    let mut r = 1;
-                    if moves_searched >= 8 {
+                    let mut r = REDUCTIONS[(depth as usize).min(63)][moves_searched.min(63)] as i32;
+                    if pv_node {
+                        r -= 1;
+                    }
+                    if !improving {
                         r += 1;
                     }
-                    if d >= 6 {
+                    if tt_capture {
                         r += 1;
                     }
-                    r
+                    if cut_node {
+                        r += 1;
+                    }
+                    r -= (history / HISTORY_REDUCTION_DIVISOR).clamp(-2, 2);
+                    // Never into quiescence, nor deeper than the full search
+                    r.clamp(0, new_depth as i32 - 1) as u8
                 } else {
                     0
                 };
 
-                self.game_history[self.game_history_count] = next_board.hash;
-                self.game_history_count += 1;
+                trace!(self, tree => tree.set_next_child(
+                    reduction as i32,
+                    new_depth as i32 + 1 - depth as i32,
+                ));
+                stat!(self, stats => stats.lmr_searches += (reduction > 0) as u64);
                 score = -self.alpha_beta(
                     &next_board,
-                    d.saturating_sub(1 + reduction),
+                    new_depth - reduction,
                     -alpha - 1,
                     -alpha,
                     ply + 1,
                     true,
                     true,
-                    Some(m),
+                    true,
                 );
-                self.game_history_count -= 1;
-
+                // A reduced move that beats alpha is searched again to its full depth
                 if score > alpha && reduction > 0 {
-                    self.game_history[self.game_history_count] = next_board.hash;
-                    self.game_history_count += 1;
+                    stat!(self, stats => stats.lmr_researches += 1);
+                    trace!(self, tree => tree.set_next_child(
+                        0,
+                        new_depth as i32 + 1 - depth as i32,
+                    ));
                     score = -self.alpha_beta(
                         &next_board,
-                        d - 1,
+                        new_depth,
                         -alpha - 1,
                         -alpha,
                         ply + 1,
+                        !cut_node,
                         true,
                         true,
-                        Some(m),
                     );
-                    self.game_history_count -= 1;
                 }
-                if score > alpha && score < beta {
-                    self.game_history[self.game_history_count] = next_board.hash;
-                    self.game_history_count += 1;
+                if pv_node && score > alpha && score < beta {
+                    trace!(self, tree => tree.set_next_child(
+                        0,
+                        new_depth as i32 + 1 - depth as i32,
+                    ));
                     score = -self.alpha_beta(
                         &next_board,
-                        d - 1,
+                        new_depth,
                         -beta,
                         -alpha,
                         ply + 1,
+                        false,
                         true,
                         true,
-                        Some(m),
                     );
-                    self.game_history_count -= 1;
                 }
             }
+            self.game_history.pop();
 
             moves_searched += 1;
             if self.should_stop() {
@@ -581,20 +1186,35 @@ impl Search {
             }
 
             if score >= beta {
-                if !m.is_capture() {
+                stat!(self, stats => {
+                    stats.beta_cutoffs += 1;
+                    stats.first_move_cutoffs += (moves_searched == 1) as u64;
+                });
+                let bonus = history::bonus(depth);
+                if is_quiet {
                     self.store_killer(m, ply);
-                    self.update_history(m, depth);
+                    self.update_quiet_history(board, m, ply, bonus);
+                    for &q in &quiets_tried {
+                        self.update_quiet_history(board, q, ply, -bonus);
+                    }
                     if let Some(lm) = last_move {
                         self.countermoves[lm.from() as usize][lm.to() as usize] = Some(m);
                     }
+                } else if m.is_capture() {
+                    self.update_capture_history(board, m, bonus);
+                }
+                for &c in &captures_tried {
+                    self.update_capture_history(board, c, -bonus);
+                }
+                if excluded_move.is_none() {
+                    self.store_tt(
+                        board.hash,
+                        Some(m),
+                        self.adjust_mate_score_to_tt(score, ply),
+                        depth,
+                        TTFlag::LowerBound,
+                    );
                 }
-                self.store_tt(
-                    board.hash,
-                    Some(m),
-                    self.adjust_mate_score_to_tt(score, ply),
-                    depth,
-                    TTFlag::LowerBound,
-                );
                 return score;
             }
             if score > best_score {
@@ -604,19 +1224,29 @@ impl Search {
             if score > alpha {
                 alpha = score;
             }
+            if is_quiet {
+                quiets_tried.push(m);
+            } else if m.is_capture() {
+                captures_tried.push(m);
+            }
         }
 
         if legal_moves_found == 0 {
-            return if in_check {
+            return if excluded_move.is_some() {
+                alpha
+            } else if in_check {
                 -MATE_VALUE + ply as i32
             } else {
-                0
+                self.draw_score(board)
             };
         }
 
         if best_score == -INFINITY {
             return alpha;
         }
+        if excluded_move.is_some() {
+            return best_score;
+        }
 
         let flag = if best_score <= alpha {
             TTFlag::UpperBound
@@ -633,52 +1263,240 @@ impl Search {
         best_score
     }
 
-    fn quiescence(&mut self, board: &Board, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+    /// A capture that beats `probcut_beta` in a search `PROBCUT_REDUCTION`
+    /// plies shallower most likely beats beta in the full one, so the node
+    /// is cut with its score.
+    fn probcut(
+        &mut self,
+        board: &Board,
+        depth: u8,
+        probcut_beta: i32,
+        eval: i32,
+        ply: usize,
+        cut_node: bool,
+    ) -> Option<i32> {
+        let mut moves = MoveGen::generate(board);
+        moves.retain(|m| m.is_capture() || m.is_promotion());
+        self.order_moves(board, &mut moves, ply, None, None);
+        for m in moves {
+            // Only captures that win enough on their own are worth trying
+            if !board.is_legal(m) || eval + board.see_value(m) < probcut_beta {
+                continue;
+            }
+            let next_board = board.make_move(m);
+            self.set_current_move(board, Some(m), ply);
+            self.game_history.push(next_board.hash);
+            let mut score = -self.quiescence(
+                &next_board,
+                -probcut_beta,
+                -probcut_beta + 1,
+                ply + 1,
+                false,
+            );
+            if score >= probcut_beta {
+                score = -self.alpha_beta(
+                    &next_board,
+                    depth - PROBCUT_REDUCTION,
+                    -probcut_beta,
+                    -probcut_beta + 1,
+                    ply + 1,
+                    !cut_node,
+                    true,
+                    true,
+                );
+            }
+            self.game_history.pop();
+            if self.should_stop() {
+                return None;
+            }
+            if score >= probcut_beta {
+                self.store_tt(
+                    board.hash,
+                    Some(m),
+                    self.adjust_mate_score_to_tt(score, ply),
+                    depth - (PROBCUT_REDUCTION - 1),
+                    TTFlag::LowerBound,
+                );
+                return Some(score);
+            }
+        }
+        None
+    }
+
+    /// Searches captures and promotions until the position is quiet, every
+    /// evasion when in check, and quiet checks too when `checks` is set, as
+    /// it is at the first ply.
+    fn quiescence(
+        &mut self,
+        board: &Board,
+        alpha: i32,
+        beta: i32,
+        ply: usize,
+        checks: bool,
+    ) -> i32 {
+        trace!(self, tree => tree.enter(
+            NodeKind::Quiescence,
+            ply,
+            ply.checked_sub(1).and_then(|i| self.stack[i].current_move),
+            false,
+            0,
+            alpha,
+            beta,
+        ));
+        let score = self.quiescence_node(board, alpha, beta, ply, checks);
+        // A stopped search's scores mean nothing
+        trace!(self, tree => tree.leave(
+            (self.needs_move || !self.stop_search && !self.stop_flag.load(Ordering::Relaxed))
+                .then_some(score)
+        ));
+        score
+    }
+
+    fn quiescence_node(
+        &mut self,
+        board: &Board,
+        mut alpha: i32,
+        beta: i32,
+        ply: usize,
+        checks: bool,
+    ) -> i32 {
         if self.should_stop() {
             return 0;
         }
         self.nodes += 1;
+        stat!(self, stats => stats.qs_nodes += 1);
         if ply >= MAX_PLY {
-            return crate::eval::evaluate(board);
+            return crate::eval::evaluate(board, &mut self.pawn_table);
+        }
+
+        let tt_entry = self.probe_tt(board.hash);
+        stat!(self, stats => {
+            stats.tt_probes += 1;
+            stats.tt_hits += tt_entry.is_some() as u64;
+        });
+        if let Some(e) = tt_entry {
+            let score = self.adjust_mate_score_from_tt(e.score, ply);
+            let cutoff = match e.flag {
+                TTFlag::Exact => true,
+                TTFlag::LowerBound => score >= beta,
+                TTFlag::UpperBound => score <= alpha,
+            };
+            if cutoff {
+                trace!(self, tree => tree.cut(Cut::Tt));
+                stat!(self, stats => stats.count(Cut::Tt));
+                return score;
+            }
         }
 
-        let stand_pat = crate::eval::evaluate(board);
+        // In check there is no standing pat, every evasion has to be tried
+        let in_check = board.is_in_check();
+        let stand_pat = if in_check {
+            -INFINITY
+        } else {
+            self.static_eval(board)
+        };
+        trace!(self, tree => tree.set_eval((!in_check).then_some(stand_pat)));
         if stand_pat >= beta {
-            return beta;
+            trace!(self, tree => tree.cut(Cut::StandPat));
+            stat!(self, stats => stats.count(Cut::StandPat));
+            return stand_pat;
         }
+        let original_alpha = alpha;
         if stand_pat > alpha {
             alpha = stand_pat;
         }
 
-        let in_check = board.is_in_check();
         let mut moves = MoveGen::generate(board);
-        if !in_check {
+        if !in_check && !checks {
             moves.retain(|m| m.is_capture() || m.is_promotion());
         }
-        self.order_moves(board, &mut moves, ply, None, None);
+        let tt_move = tt_entry.and_then(|e| e.m);
+        self.order_moves(board, &mut moves, ply, tt_move, None);
 
+        let mut best_score = stand_pat;
+        let mut best_move = None;
+        let mut legal_moves_found = 0;
         for m in moves {
-            if !board.is_legal(m) {
-                continue;
-            }
-            if !in_check && stand_pat < alpha - 900 && !m.is_promotion() {
+            let next_board = board.make_move(m);
+            let us = board.side_to_move;
+            let king_bit =
+                next_board.by_type[PieceType::King as usize] & next_board.by_color[us as usize];
+            if king_bit == 0
+                || next_board.is_square_attacked(king_bit.trailing_zeros() as u8, us.opponent())
+            {
                 continue;
             }
-            // Relaxed SEE pruning from 0 to -200 to catch tactical sacrifices
-            if board.see_value(m) < -200 {
-                continue;
+            legal_moves_found += 1;
+
+            if !in_check {
+                let is_quiet = !m.is_capture() && !m.is_promotion();
+                if is_quiet && !next_board.is_in_check() {
+                    continue;
+                }
+                // Delta pruning: even winning the piece taken doesn't reach alpha
+                if m.is_capture() && !m.is_promotion() {
+                    let victim = PIECE_VALUES[history::captured(board, m) as usize];
+                    if stand_pat + victim + self.params.qs_delta_margin <= alpha {
+                        best_score = best_score.max(stand_pat + victim);
+                        trace!(self, tree => tree.prune(m, Cut::Delta));
+                        stat!(self, stats => stats.count(Cut::Delta));
+                        continue;
+                    }
+                }
+                // SEE pruning is relaxed below 0 to catch tactical sacrifices,
+                // but a checking piece may not simply hang
+                let threshold = if is_quiet {
+                    0
+                } else {
+                    self.params.qs_see_threshold
+                };
+                if board.see_value(m) < threshold {
+                    trace!(self, tree => tree.prune(m, Cut::See));
+                    stat!(self, stats => stats.count(Cut::See));
+                    continue;
+                }
             }
 
-            let next_board = board.make_move(m);
-            let score = -self.quiescence(&next_board, -beta, -alpha, ply + 1);
+            self.set_current_move(board, Some(m), ply);
+            let score = -self.quiescence(&next_board, -beta, -alpha, ply + 1, false);
+            if self.should_stop() {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(m);
+            }
             if score >= beta {
-                return beta;
+                break;
             }
             if score > alpha {
                 alpha = score;
             }
         }
-        alpha
+
+        if in_check && legal_moves_found == 0 {
+            return -MATE_VALUE + ply as i32;
+        }
+
+        let flag = if best_score >= beta {
+            TTFlag::LowerBound
+        } else if best_score > original_alpha {
+            TTFlag::Exact
+        } else {
+            TTFlag::UpperBound
+        };
+        self.store_tt(
+            board.hash,
+            best_move,
+            self.adjust_mate_score_to_tt(best_score, ply),
+            0,
+            flag,
+        );
+        best_score
+    }
+
+    fn static_eval(&mut self, board: &Board) -> i32 {
+        crate::eval::evaluate(board, &mut self.pawn_table) + self.skill.noise(board.hash)
     }
 
     fn order_moves(
@@ -707,7 +1525,7 @@ impl Search {
                     .map(|(p, _)| p)
                     .unwrap_or(PieceType::Pawn);
                 score = 4000000 + (victim as i32 * 100) - attacker as i32;
-                score += board.see_value(*m);
+                score += board.see_value(*m) + self.capture_score(board, *m) / 32;
             } else if m.is_promotion() {
                 score = 3500000 + m.promoted_piece().map(|p| p as i32).unwrap_or(0);
             } else {
@@ -722,7 +1540,7 @@ impl Search {
                     score = 2800000;
                 }
                 if score == 0 {
-                    score = self.history[m.from() as usize][m.to() as usize];
+                    score = self.quiet_history(board, *m, ply);
                 }
             }
             std::cmp::Reverse(score)
@@ -736,17 +1554,77 @@ impl Search {
         }
     }
 
-    fn update_history(&mut self, m: Move, depth: u8) {
-        let bonus = (depth as i32) * (depth as i32);
-        let current = self.history[m.from() as usize][m.to() as usize];
-        self.history[m.from() as usize][m.to() as usize] = (current + bonus).min(2000000);
-        if self.history[m.from() as usize][m.to() as usize] >= 2000000 {
-            for i in 0..64 {
-                for j in 0..64 {
-                    self.history[i][j] /= 2;
-                }
+    fn set_current_move(&mut self, board: &Board, m: Option<Move>, ply: usize) {
+        let entry = &mut self.stack[ply];
+        entry.current_move = m;
+        entry.piece_to = m.map(|m| PieceTo::new(board, m));
+    }
+
+    /// The moves that led to `ply`, one and two plies back, when they
+    /// weren't null moves.
+    fn previous_moves(&self, ply: usize) -> [Option<PieceTo>; 2] {
+        let back = |n: usize| {
+            ply.checked_sub(n)
+                .and_then(|i| self.stack.get(i))
+                .and_then(|e| e.piece_to)
+        };
+        [back(1), back(2)]
+    }
+
+    /// A quiet move's butterfly history plus its continuation histories.
+    fn quiet_history(&self, board: &Board, m: Move, ply: usize) -> i32 {
+        let piece_to = PieceTo::new(board, m);
+        let mut score = self.history[m.from() as usize][m.to() as usize];
+        for prev in self.previous_moves(ply).into_iter().flatten() {
+            score += self.continuation_history.get(prev)[piece_to.piece][piece_to.to];
+        }
+        score
+    }
+
+    fn update_quiet_history(&mut self, board: &Board, m: Move, ply: usize, bonus: i32) {
+        let piece_to = PieceTo::new(board, m);
+        history::apply_gravity(&mut self.history[m.from() as usize][m.to() as usize], bonus);
+        for prev in self.previous_moves(ply).into_iter().flatten() {
+            let entry = &mut self.continuation_history.get_mut(prev)[piece_to.piece][piece_to.to];
+            history::apply_gravity(entry, bonus);
+        }
+    }
+
+    fn capture_score(&self, board: &Board, m: Move) -> i32 {
+        let piece_to = PieceTo::new(board, m);
+        self.capture_history[piece_to.piece][piece_to.to][history::captured(board, m) as usize]
+    }
+
+    fn update_capture_history(&mut self, board: &Board, m: Move, bonus: i32) {
+        let piece_to = PieceTo::new(board, m);
+        let captured = history::captured(board, m) as usize;
+        history::apply_gravity(
+            &mut self.capture_history[piece_to.piece][piece_to.to][captured],
+            bonus,
+        );
+    }
+
+    /// `first` and the best moves the TT remembers after it, up to
+    /// `max_len` moves, to show a line once a search is done.
+    pub fn tt_line(&self, board: &Board, first: Move, max_len: usize) -> Vec<Move> {
+        let mut line = vec![first];
+        let mut board = board.make_move(first);
+        let mut seen = vec![board.hash];
+        while line.len() < max_len {
+            let Some(m) = self.probe_tt(board.hash).and_then(|e| e.m) else {
+                break;
+            };
+            if !MoveGen::generate(&board).contains(&m) || !board.is_legal(m) {
+                break;
+            }
+            line.push(m);
+            board = board.make_move(m);
+            if seen.contains(&board.hash) {
+                break;
             }
+            seen.push(board.hash);
         }
+        line
     }
 
     fn probe_tt(&self, hash: u64) -> Option<TTEntry> {
@@ -764,7 +1642,9 @@ impl Search {
         let idx = hash as usize % self.tt_size;
         let mut tt = self.tt.lock().unwrap();
         if let Some(existing) = tt[idx] {
-            if existing.key == hash && existing.depth > depth {
+            // Quiescence results never push out a main search entry, of this
+            // position or of another
+            if existing.depth > depth && (existing.key == hash || depth == 0) {
                 return;
             }
         }
@@ -0,0 +1,297 @@
+//! `tree`: records the search tree of one position and browses recordings.
+//! Recording needs a build with the `search-trace` feature; browsing and
+//! exporting work in any build.
+//!
+//! ```text
+//! tree record --fen "<fen>" --depth 6 --max-ply 4 --output tree.txt
+//! tree show tree.txt --node 120 --levels 2
+//! tree dot tree.txt --node 120 | dot -Tsvg > tree.svg
+//! tree json tree.txt > tree.json
+//! ```
+
+use crate::json::Json;
+use oxidized_fish::trace::{NodeKind, SearchTree, TreeNode};
+use std::fmt::Write as _;
+
+const DEFAULT_SHOW_LEVELS: usize = 2;
+
+pub fn run(args: &[String]) {
+    match args.first().map(String::as_str) {
+        Some("record") => record(&args[1..]),
+        Some(format @ ("show" | "dot" | "json")) => view(format, &args[1..]),
+        _ => usage(),
+    }
+}
+
+fn usage() {
+    eprintln!(
+        "Usage: tree record [--fen <fen>] [--depth <plies>] [--max-ply <n>] \
+         [--max-nodes <n>] [--hash <MB>] [--output <file>]\n       \
+         tree show|dot|json <file> [--node <id>] [--levels <n>]"
+    );
+}
+
+#[cfg(not(feature = "search-trace"))]
+fn record(_args: &[String]) {
+    eprintln!("Error: Recording needs a build with the search-trace feature");
+}
+
+#[cfg(feature = "search-trace")]
+fn record(args: &[String]) {
+    use crate::game::GameState;
+    use crate::search::{Search, MAX_PLY};
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+
+    const DEFAULT_DEPTH: usize = 6;
+    const DEFAULT_MAX_PLY: usize = 4;
+    const DEFAULT_MAX_NODES: usize = 200_000;
+    const DEFAULT_HASH: usize = 16;
+
+    let mut fen = None;
+    let mut output = "tree.txt".to_string();
+    let mut depth = DEFAULT_DEPTH;
+    let mut max_ply = DEFAULT_MAX_PLY;
+    let mut max_nodes = DEFAULT_MAX_NODES;
+    let mut hash = DEFAULT_HASH;
+    let mut i = 0;
+    while i < args.len() {
+        let Some(value) = args.get(i + 1) else {
+            eprintln!("Error: Missing value for {}", args[i]);
+            return usage();
+        };
+        let number = value.parse::<usize>().ok().filter(|&n| n > 0);
+        match (args[i].as_str(), number) {
+            ("--fen", _) => fen = Some(value.clone()),
+            ("--output", _) => output = value.clone(),
+            ("--depth", Some(n)) if n < MAX_PLY => depth = n,
+            ("--max-ply", Some(n)) => max_ply = n,
+            ("--max-nodes", Some(n)) => max_nodes = n,
+            ("--hash", Some(n)) => hash = n,
+            _ => {
+                eprintln!("Error: Bad option {} {}", args[i], value);
+                return usage();
+            }
+        }
+        i += 2;
+    }
+
+    let board = match fen {
+        Some(fen) if !crate::board::Board::is_valid_fen(&fen) => {
+            eprintln!("Error: Bad FEN {}", fen);
+            return;
+        }
+        Some(fen) => crate::board::Board::from_fen(&fen),
+        None => crate::board::Board::new(),
+    };
+    let mut searcher = Search::with_hash(hash);
+    searcher.trace = Some(SearchTree::new(max_ply, max_nodes));
+    let best = searcher.go(&GameState::new(board), depth as u8, None);
+    let Some(tree) = searcher.trace.take() else {
+        return;
+    };
+
+    let written = File::create(&output).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        tree.write(&mut out)?;
+        out.flush()
+    });
+    if let Err(e) = written {
+        eprintln!("Error: Failed to write {}: {}", output, e);
+        return;
+    }
+    println!(
+        "Best move {}, {} nodes searched, {} recorded{} in {}",
+        best.map_or("none".to_string(), |m| m.to_string()),
+        searcher.nodes,
+        tree.nodes.len(),
+        if tree.truncated {
+            " (node limit reached)"
+        } else {
+            ""
+        },
+        output
+    );
+}
+
+fn view(format: &str, args: &[String]) {
+    let mut path = None;
+    let mut node = None;
+    let mut levels = None;
+    let mut i = 0;
+    while i < args.len() {
+        if !args[i].starts_with("--") {
+            path = Some(args[i].clone());
+            i += 1;
+            continue;
+        }
+        let number = args.get(i + 1).and_then(|v| v.parse::<usize>().ok());
+        match (args[i].as_str(), number) {
+            ("--node", Some(n)) => node = Some(n),
+            ("--levels", Some(n)) => levels = Some(n),
+            _ => {
+                eprintln!("Error: Bad option {}", args[i]);
+                return usage();
+            }
+        }
+        i += 2;
+    }
+    let Some(path) = path else {
+        return usage();
+    };
+
+    let tree = match std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| SearchTree::read(&text))
+    {
+        Ok(tree) => tree,
+        Err(e) => {
+            eprintln!("Error: Failed to read {}: {}", path, e);
+            return;
+        }
+    };
+    let children = tree.children();
+    let tops: Vec<usize> = match node {
+        Some(id) if id >= tree.nodes.len() => {
+            eprintln!("Error: No node {}", id);
+            return;
+        }
+        Some(id) => vec![id],
+        None => (0..tree.nodes.len())
+            .filter(|&id| tree.nodes[id].parent.is_none())
+            .collect(),
+    };
+    let view = View {
+        tree: &tree,
+        children: &children,
+    };
+
+    match format {
+        "show" => {
+            let levels = levels.unwrap_or(DEFAULT_SHOW_LEVELS);
+            let mut out = String::new();
+            for &id in &tops {
+                view.show(id, 0, levels, &mut out);
+            }
+            print!("{}", out);
+        }
+        "dot" => {
+            let mut out = String::from("digraph tree {\n  node [shape=box, fontname=monospace];\n");
+            for &id in &tops {
+                view.dot(id, levels.unwrap_or(usize::MAX), &mut out);
+            }
+            out.push_str("}\n");
+            print!("{}", out);
+        }
+        _ => {
+            let levels = levels.unwrap_or(usize::MAX);
+            let nodes = tops.iter().map(|&id| view.json(id, levels)).collect();
+            println!("{}", Json::Array(nodes).to_json_string());
+        }
+    }
+}
+
+struct View<'a> {
+    tree: &'a SearchTree,
+    children: &'a [Vec<usize>],
+}
+
+impl View<'_> {
+    /// One line of `show` for a node, without its id or indentation.
+    fn summary(&self, id: usize) -> String {
+        let n = &self.tree.nodes[id];
+        let mut s = format!("{} d{} [{}, {}]", n.label, n.depth, n.alpha, n.beta);
+        if n.kind == NodeKind::Quiescence {
+            s.push_str(" qs");
+        }
+        if let Some(eval) = n.eval {
+            let _ = write!(s, " eval {}", eval);
+        }
+        if n.reduction != 0 {
+            let _ = write!(s, " r{}", n.reduction);
+        }
+        if n.extension != 0 {
+            let _ = write!(s, " e{:+}", n.extension);
+        }
+        if let Some(cut) = n.cut {
+            let verb = if n.kind == NodeKind::Pruned {
+                "pruned"
+            } else {
+                "cut"
+            };
+            let _ = write!(s, " {} {}", verb, cut.name());
+        }
+        match n.score {
+            Some(score) => {
+                let _ = write!(s, " -> {}", score);
+            }
+            None if n.kind != NodeKind::Pruned => s.push_str(" -> stopped"),
+            None => {}
+        }
+        s
+    }
+
+    /// The node and `levels` of its descendants, indented, with a count of
+    /// those left out.
+    fn show(&self, id: usize, indent: usize, levels: usize, out: &mut String) {
+        let _ = write!(out, "{}#{} {}", " ".repeat(indent), id, self.summary(id));
+        let children = &self.children[id];
+        if levels == 0 && !children.is_empty() {
+            let _ = write!(out, " (+{} children)", children.len());
+        }
+        out.push('\n');
+        if levels > 0 {
+            for &child in children {
+                self.show(child, indent + 2, levels - 1, out);
+            }
+        }
+    }
+
+    fn dot(&self, id: usize, levels: usize, out: &mut String) {
+        let n = &self.tree.nodes[id];
+        let style = if n.kind == NodeKind::Pruned {
+            ", style=dashed, color=gray"
+        } else {
+            ""
+        };
+        let label = format!("#{} {}", id, self.summary(id)).replace('"', "\\\"");
+        let _ = writeln!(out, "  n{} [label=\"{}\"{}];", id, label, style);
+        if levels == 0 {
+            return;
+        }
+        for &child in &self.children[id] {
+            let _ = writeln!(out, "  n{} -> n{};", id, child);
+            self.dot(child, levels - 1, out);
+        }
+    }
+
+    fn json(&self, id: usize, levels: usize) -> Json {
+        let n: &TreeNode = &self.tree.nodes[id];
+        let opt = |v: Option<i32>| v.map_or(Json::Null, |v| Json::from(v as i64));
+        let mut fields = vec![
+            ("id", Json::from(id as i64)),
+            ("kind", Json::from(n.kind.name())),
+            ("move", Json::from(n.label.as_str())),
+            ("ply", Json::from(n.ply as i64)),
+            ("depth", Json::from(n.depth as i64)),
+            ("alpha", Json::from(n.alpha as i64)),
+            ("beta", Json::from(n.beta as i64)),
+            ("eval", opt(n.eval)),
+            ("reduction", Json::from(n.reduction as i64)),
+            ("extension", Json::from(n.extension as i64)),
+            ("cut", n.cut.map_or(Json::Null, |c| Json::from(c.name()))),
+            ("score", opt(n.score)),
+        ];
+        let children = &self.children[id];
+        if levels > 0 {
+            let nodes = children
+                .iter()
+                .map(|&child| self.json(child, levels - 1))
+                .collect();
+            fields.push(("children", Json::Array(nodes)));
+        } else {
+            fields.push(("more_children", Json::from(children.len() as i64)));
+        }
+        Json::object(fields)
+    }
+}
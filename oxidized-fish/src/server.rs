@@ -0,0 +1,594 @@
+use crate::board::Board;
+use crate::game::GameState;
+use crate::http::{self, Request};
+use crate::json::Json;
+use crate::search::{Search, SearchInfo};
+use crate::uci::{
+    format_info, go_mate, parse_go, parse_go_mate, parse_move, parse_position, parse_setoption,
+    set_option,
+};
+use crate::websocket::{self, Message};
+use std::io::{self, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 3001;
+const DEFAULT_ENGINES: usize = 4;
+const DEFAULT_HASH: usize = 16;
+const DEFAULT_MAX_HASH: usize = 256;
+const DEFAULT_MAX_THREADS: usize = 1;
+const MAX_DEPTH: u8 = 64;
+
+/// How long a client may take to send its request, and how long a
+/// WebSocket client may stay silent before its engine goes back to the pool.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const SOCKET_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// The JSON API answers in a single response, so its searches are bounded.
+const DEFAULT_API_MOVETIME: u64 = 1000;
+const MAX_API_MOVETIME: u64 = 10000;
+const MAX_API_MULTIPV: usize = 8;
+
+struct Config {
+    host: String,
+    port: u16,
+    root: PathBuf,
+    engines: usize,
+    hash: usize,     // MB per engine when a client doesn't ask
+    max_hash: usize, // MB
+    max_threads: usize,
+}
+
+struct Server {
+    config: Config,
+    pool: Arc<EnginePool>,
+}
+
+/// Runs `serve [--host ADDR] [--port N] [--root DIR] [--engines N] [--hash MB] [--max-hash MB]
+/// [--max-threads N]` until the process is killed.
+pub fn run(args: &[String]) {
+    let Some(config) = parse_args(args) else {
+        return;
+    };
+    let listener = match TcpListener::bind((config.host.as_str(), config.port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!(
+                "Error: Failed to listen on {}:{}: {}",
+                config.host, config.port, e
+            );
+            return;
+        }
+    };
+    println!(
+        "Serving {} on http://{}:{} with {} engines",
+        config.root.display(),
+        config.host,
+        config.port,
+        config.engines
+    );
+
+    let server = Arc::new(Server {
+        pool: EnginePool::new(config.engines, config.hash),
+        config,
+    });
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        let server = server.clone();
+        thread::spawn(move || {
+            if let Err(e) = server.handle(stream) {
+                if !is_disconnect(&e) {
+                    eprintln!("Error: {}", e);
+                }
+            }
+        });
+    }
+}
+
+fn parse_args(args: &[String]) -> Option<Config> {
+    let mut config = Config {
+        host: DEFAULT_HOST.to_string(),
+        port: DEFAULT_PORT,
+        root: default_root(),
+        engines: DEFAULT_ENGINES,
+        hash: DEFAULT_HASH,
+        max_hash: DEFAULT_MAX_HASH,
+        max_threads: DEFAULT_MAX_THREADS,
+    };
+    let mut i = 0;
+    while i < args.len() {
+        let Some(value) = args.get(i + 1) else {
+            eprintln!("Error: Missing value for {}", args[i]);
+            return None;
+        };
+        let number = value.parse::<usize>().ok().filter(|&n| n > 0);
+        match (args[i].as_str(), number) {
+            ("--host", _) => config.host = value.clone(),
+            ("--root", _) => config.root = PathBuf::from(value),
+            ("--port", Some(n)) if n <= u16::MAX as usize => config.port = n as u16,
+            ("--engines", Some(n)) => config.engines = n,
+            ("--hash", Some(n)) => config.hash = n,
+            ("--max-hash", Some(n)) => config.max_hash = n,
+            ("--max-threads", Some(n)) => config.max_threads = n,
+            _ => {
+                eprintln!("Error: Bad option {} {}", args[i], value);
+                return None;
+            }
+        }
+        i += 2;
+    }
+    config.hash = config.hash.min(config.max_hash);
+    Some(config)
+}
+
+/// `webapp/public` from the repository root or from `oxidized-fish`.
+fn default_root() -> PathBuf {
+    ["webapp/public", "../webapp/public"]
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.is_dir())
+        .unwrap_or_else(|| PathBuf::from("webapp/public"))
+}
+
+fn is_disconnect(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::UnexpectedEof
+            | ErrorKind::ConnectionReset
+            | ErrorKind::BrokenPipe
+            | ErrorKind::WouldBlock // A read timing out
+            | ErrorKind::TimedOut
+    )
+}
+
+/// Idle engines, one per allowed concurrent client. Clients can't turn
+/// experience on, so no two engines write the same file.
+struct EnginePool {
+    idle: Mutex<Vec<Search>>,
+    hash: usize,
+}
+
+/// A claim on one pool slot, returned with a fresh engine when dropped so
+/// that no client's settings or hash contents reach the next one.
+struct Lease {
+    pool: Arc<EnginePool>,
+}
+
+impl EnginePool {
+    fn new(engines: usize, hash: usize) -> Arc<Self> {
+        Arc::new(EnginePool {
+            idle: Mutex::new((0..engines).map(|_| Search::with_hash(hash)).collect()),
+            hash,
+        })
+    }
+
+    fn lease(self: &Arc<Self>) -> Option<(Search, Lease)> {
+        let searcher = self.idle.lock().unwrap().pop()?;
+        Some((searcher, Lease { pool: self.clone() }))
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let searcher = Search::with_hash(self.pool.hash);
+        self.pool.idle.lock().unwrap().push(searcher);
+    }
+}
+
+impl Server {
+    fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let request = http::read_request(&stream)?;
+        let upgrade = request
+            .header("Upgrade")
+            .is_some_and(|u| u.eq_ignore_ascii_case("websocket"));
+
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/ws") if upgrade => self.websocket(stream, &request),
+            ("POST", "/analyze") => {
+                let result = Json::parse(&String::from_utf8_lossy(&request.body))
+                    .map_err(|e| (400, e))
+                    .and_then(|body| self.analyze_json(&body));
+                reply(&mut stream, result)
+            }
+            ("GET", "/bestmove") => {
+                let result = self.bestmove_query(&request);
+                reply(&mut stream, result)
+            }
+            ("GET", _) => self.static_file(&mut stream, &request.path),
+            _ => http::respond_json(&mut stream, 405, &error_json("Method not allowed")),
+        }
+    }
+
+    fn static_file(&self, stream: &mut TcpStream, path: &str) -> io::Result<()> {
+        let relative = Path::new(path.trim_start_matches('/'));
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return http::respond(stream, 403, "text/plain", b"Forbidden");
+        }
+        let mut file = self.config.root.join(relative);
+        if path.ends_with('/') {
+            file.push("index.html");
+        }
+        match std::fs::read(&file) {
+            Ok(body) => http::respond(stream, 200, content_type(&file), &body),
+            Err(_) => http::respond(stream, 404, "text/plain", b"Not found"),
+        }
+    }
+
+    /// `POST /analyze` with `{"fen", "moves", "depth", "movetime", "multipv", "hash"}`,
+    /// all optional.
+    fn analyze_json(&self, body: &Json) -> Result<Json, (u16, String)> {
+        let fen = body.get("fen").and_then(Json::as_str);
+        let moves: Vec<&str> = match body.get("moves") {
+            Some(list) => list
+                .as_array()
+                .ok_or((400, "moves must be an array".to_string()))?
+                .iter()
+                .filter_map(Json::as_str)
+                .collect(),
+            None => Vec::new(),
+        };
+        let game = game_from(fen, &moves)?;
+        let limits = self.api_limits(|key| body.get(key).and_then(Json::as_u64));
+        self.analyze(&game, limits)
+    }
+
+    /// `GET /bestmove?fen=..&moves=e2e4+e7e5&depth=..&movetime=..`
+    fn bestmove_query(&self, request: &Request) -> Result<Json, (u16, String)> {
+        let moves: Vec<&str> = request
+            .query_param("moves")
+            .map(|m| m.split_whitespace().collect())
+            .unwrap_or_default();
+        let game = game_from(request.query_param("fen"), &moves)?;
+        let limits = self.api_limits(|key| request.query_param(key).and_then(|v| v.parse().ok()));
+        self.analyze(&game, limits)
+    }
+
+    fn api_limits(&self, get: impl Fn(&str) -> Option<u64>) -> Limits {
+        let depth = get("depth").map(|d| d.clamp(1, MAX_DEPTH as u64) as u8);
+        let movetime = match (get("movetime"), depth) {
+            (Some(t), _) => t.clamp(1, MAX_API_MOVETIME),
+            (None, Some(_)) => MAX_API_MOVETIME,
+            (None, None) => DEFAULT_API_MOVETIME,
+        };
+        Limits {
+            depth: depth.unwrap_or(MAX_DEPTH),
+            movetime,
+            multipv: get("multipv").unwrap_or(1).clamp(1, MAX_API_MULTIPV as u64) as usize,
+            hash: get("hash")
+                .map(|h| (h as usize).clamp(1, self.config.max_hash))
+                .unwrap_or(self.config.hash),
+        }
+    }
+
+    fn analyze(&self, game: &GameState, limits: Limits) -> Result<Json, (u16, String)> {
+        let (mut searcher, _lease) = self
+            .pool
+            .lease()
+            .ok_or((503, "All engines are busy".to_string()))?;
+        if limits.hash != self.pool.hash {
+            searcher.resize_tt(limits.hash);
+        }
+        searcher.multi_pv = limits.multipv;
+
+        // The latest info of every line, so a cut-off iteration still reports
+        let lines: Arc<Mutex<Vec<SearchInfo>>> = Arc::new(Mutex::new(Vec::new()));
+        let sink = lines.clone();
+        searcher.on_info = Some(Box::new(move |info| {
+            let mut lines = sink.lock().unwrap();
+            let idx = info.multipv.unwrap_or(1) - 1;
+            if idx < lines.len() {
+                lines[idx] = info.clone();
+            } else {
+                lines.push(info.clone());
+            }
+        }));
+
+        let m = searcher.go(
+            game,
+            limits.depth,
+            Some(Duration::from_millis(limits.movetime)),
+        );
+        let lines = lines.lock().unwrap();
+        let (depth, nodes, time) = lines
+            .first()
+            .map_or((0, 0, 0), |i| (i.depth, i.nodes, i.time_ms));
+        Ok(Json::object(vec![
+            (
+                "bestmove",
+                m.map_or(Json::Null, |m| Json::from(m.to_string())),
+            ),
+            ("depth", Json::from(depth as i64)),
+            ("nodes", Json::from(nodes as i64)),
+            ("time", Json::from(time as i64)),
+            ("lines", Json::Array(lines.iter().map(line_json).collect())),
+        ]))
+    }
+
+    fn websocket(&self, stream: TcpStream, request: &Request) -> io::Result<()> {
+        let mut stream = stream;
+        let Some(key) = request.header("Sec-WebSocket-Key") else {
+            return http::respond_json(&mut stream, 400, &error_json("Missing WebSocket key"));
+        };
+        let Some((searcher, _lease)) = self.pool.lease() else {
+            return http::respond_json(&mut stream, 503, &error_json("All engines are busy"));
+        };
+        let handshake = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            websocket::accept_key(key)
+        );
+        io::Write::write_all(&mut stream, handshake.as_bytes())?;
+        stream.set_read_timeout(Some(SOCKET_IDLE_TIMEOUT))?;
+
+        let mut reader = stream.try_clone()?;
+        let mut session = Session {
+            game: GameState::new(Board::new()),
+            stop_flag: searcher.stop_flag.clone(),
+            searcher: Some(searcher),
+            search_thread: None,
+            out: Arc::new(Mutex::new(stream)),
+            max_hash: self.config.max_hash,
+            max_threads: self.config.max_threads,
+        };
+        let result = session.run(&mut reader);
+        session.finish_search();
+        result
+    }
+}
+
+struct Limits {
+    depth: u8,
+    movetime: u64, // ms
+    multipv: usize,
+    hash: usize, // MB
+}
+
+fn reply(stream: &mut TcpStream, result: Result<Json, (u16, String)>) -> io::Result<()> {
+    match result {
+        Ok(json) => http::respond_json(stream, 200, &json.to_json_string()),
+        Err((status, message)) => http::respond_json(stream, status, &error_json(&message)),
+    }
+}
+
+fn error_json(message: &str) -> String {
+    Json::object(vec![("error", Json::from(message))]).to_json_string()
+}
+
+fn line_json(info: &SearchInfo) -> Json {
+    let score = match info.mate_in() {
+        Some(n) => ("mate", Json::from(n as i64)),
+        None => ("cp", Json::from(info.score as i64)),
+    };
+    let mut fields = vec![
+        ("multipv", Json::from(info.multipv.unwrap_or(1) as i64)),
+        ("depth", Json::from(info.depth as i64)),
+        ("score", Json::object(vec![score])),
+    ];
+    if let Some((w, d, l)) = info.wdl {
+        fields.push((
+            "wdl",
+            Json::Array(vec![
+                Json::from(w as i64),
+                Json::from(d as i64),
+                Json::from(l as i64),
+            ]),
+        ));
+    }
+    fields.push((
+        "pv",
+        Json::Array(info.pv.iter().map(|m| Json::from(m.to_string())).collect()),
+    ));
+    Json::object(fields)
+}
+
+/// The position from an optional FEN (start position otherwise) and moves.
+fn game_from(fen: Option<&str>, moves: &[&str]) -> Result<GameState, (u16, String)> {
+    let board = match fen {
+        Some(fen) if !Board::is_valid_fen(fen) => {
+            return Err((400, format!("Invalid FEN: {}", fen)))
+        }
+        Some(fen) => Board::from_fen(fen),
+        None => Board::new(),
+    };
+    let mut game = GameState::new(board);
+    for m_str in moves {
+        match parse_move(&game.board, m_str) {
+            Some(m) => game.make_move(m),
+            None => return Err((400, format!("Illegal move: {}", m_str))),
+        }
+    }
+    Ok(game)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("wasm") => "application/wasm", // instantiateStreaming insists on it
+        _ => "application/octet-stream",
+    }
+}
+
+/// One WebSocket client speaking UCI text, one command per message. Like the
+/// XBoard front end the search runs on its own thread, and `searcher` is
+/// `None` while that thread owns it.
+struct Session {
+    game: GameState,
+    searcher: Option<Search>,
+    search_thread: Option<JoinHandle<Search>>,
+    stop_flag: Arc<AtomicBool>,
+    out: Arc<Mutex<TcpStream>>,
+    max_hash: usize,
+    max_threads: usize,
+}
+
+fn send(out: &Mutex<TcpStream>, text: &str) -> io::Result<()> {
+    websocket::write_text(&mut *out.lock().unwrap(), text)
+}
+
+impl Session {
+    fn run(&mut self, reader: &mut TcpStream) -> io::Result<()> {
+        loop {
+            match websocket::read_message(reader)? {
+                Message::Text(text) => {
+                    for line in text.lines() {
+                        if !self.handle(line)? {
+                            return Ok(());
+                        }
+                    }
+                }
+                Message::Ping(payload) => {
+                    let mut out = self.out.lock().unwrap();
+                    websocket::write_frame(&mut *out, websocket::OP_PONG, &payload)?;
+                }
+                Message::Close => {
+                    let mut out = self.out.lock().unwrap();
+                    return websocket::write_frame(&mut *out, websocket::OP_CLOSE, &[]);
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, line: &str) -> io::Result<bool> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        if args.is_empty() {
+            return Ok(true);
+        }
+
+        match args[0] {
+            "uci" => {
+                send(&self.out, "id name Oxidized Fish 0.7-Hardened")?;
+                send(&self.out, "uciok")?;
+            }
+            "isready" => send(&self.out, "readyok")?,
+            "ucinewgame" => {
+                self.finish_search();
+                self.game = GameState::new(Board::new());
+                if let Some(searcher) = self.searcher.as_mut() {
+                    searcher.clear_tt();
+                }
+            }
+            "setoption" => {
+                self.finish_search();
+                let (name, value) = parse_setoption(&args);
+                self.set_option(&name, &value)?;
+            }
+            "position" => {
+                self.finish_search();
+                if args.get(1) == Some(&"fen") {
+                    let end = args
+                        .iter()
+                        .position(|&a| a == "moves")
+                        .unwrap_or(args.len());
+                    let fen = args[2..end].join(" ");
+                    if !Board::is_valid_fen(&fen) {
+                        return send(&self.out, &format!("info string Invalid FEN: {}", fen))
+                            .map(|_| true);
+                    }
+                }
+                if let Some(game) = parse_position(&args) {
+                    self.game = game;
+                }
+            }
+            "go" => {
+                self.finish_search();
+                self.start_search(&args);
+            }
+            "stop" => self.finish_search(),
+            "eval" => {
+                self.finish_search();
+                if let Some(searcher) = self.searcher.as_mut() {
+                    let table =
+                        crate::eval::trace(&self.game.board, &mut searcher.pawn_table).to_table();
+                    send(&self.out, &table)?;
+                }
+            }
+            "quit" => return Ok(false),
+            _ => send(
+                &self.out,
+                &format!("info string Unknown command: {}", args[0]),
+            )?,
+        }
+        Ok(true)
+    }
+
+    /// Options go through the UCI handler, except the ones that are shared by
+    /// the whole process or touch the server's disk.
+    fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        let Some(searcher) = self.searcher.as_mut() else {
+            return Ok(());
+        };
+        match name {
+            "Hash" => {
+                if let Ok(size) = value.parse::<usize>() {
+                    let size = size.clamp(1, self.max_hash);
+                    searcher.resize_tt(size);
+                    send(&self.out, &format!("info string Hash set to {} MB", size))?;
+                }
+            }
+            // The search is single-threaded, so a value within the limit changes nothing
+            "Threads" => {
+                if value.parse::<usize>().is_ok_and(|t| t > self.max_threads) {
+                    let message = format!("info string Threads limited to {}", self.max_threads);
+                    send(&self.out, &message)?;
+                }
+            }
+            "Contempt" | "Experience Enabled" | "Experience File" => {
+                let message = format!("info string Option {} is fixed by the server", name);
+                send(&self.out, &message)?;
+            }
+            _ => set_option(searcher, name, value),
+        }
+        Ok(())
+    }
+
+    fn start_search(&mut self, args: &[&str]) {
+        let Some(mut searcher) = self.searcher.take() else {
+            return;
+        };
+        let out = self.out.clone();
+        searcher.on_info = Some(Box::new(move |info| {
+            let _ = send(&out, &format_info(info));
+        }));
+
+        let (depth, time_limit) = parse_go(args, self.game.board.side_to_move);
+        let mate = parse_go_mate(args);
+        let game = self.game.clone();
+        let out = self.out.clone();
+        self.stop_flag.store(false, Ordering::Relaxed);
+        self.search_thread = Some(thread::spawn(move || {
+            let m = match mate {
+                Some(moves) => go_mate(&mut searcher, &game, moves, time_limit, |s| {
+                    let _ = send(&out, s);
+                }),
+                None => searcher.go(&game, depth, time_limit),
+            };
+            let best = m.map_or("0000".to_string(), |m| m.to_string());
+            let _ = send(&out, &format!("bestmove {}", best));
+            searcher
+        }));
+    }
+
+    /// Stops a running search, which still sends its bestmove.
+    fn finish_search(&mut self) {
+        let Some(handle) = self.search_thread.take() else {
+            return;
+        };
+        self.stop_flag.store(true, Ordering::Relaxed);
+        self.searcher = Some(handle.join().expect("Search thread panicked"));
+    }
+}
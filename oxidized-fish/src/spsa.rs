@@ -0,0 +1,147 @@
+//! `tune`: tunes the search parameters with SPSA (simultaneous perturbation
+//! stochastic approximation). Each iteration nudges every parameter up or
+//! down at random, plays a short match between the engine with the nudges
+//! and the engine with them reversed, and moves the parameters towards the
+//! winner. Steps shrink over the iterations as fishtest's do, ending at each
+//! parameter's `step` with learning rate `SPSA_R_END`.
+//!
+//! ```text
+//! tune --iterations 500 --games 16 --concurrency 4 --tc 5+0.05 --openings book.epd
+//! ```
+
+use crate::match_runner::{self, EngineConfig};
+use crate::params::{self, SearchParams, SPSA_R_END, TUNABLES};
+use crate::skill::Prng;
+
+const DEFAULT_ITERATIONS: usize = 200;
+const DEFAULT_GAMES: usize = 8; // Per iteration
+
+/// Decay exponents of the learning rate and of the perturbation, from Spall.
+const ALPHA: f64 = 0.602;
+const GAMMA: f64 = 0.101;
+
+pub fn run(args: &[String]) {
+    let mut iterations = DEFAULT_ITERATIONS;
+    let mut match_args = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == "--iterations" {
+            match args
+                .get(i + 1)
+                .and_then(|n| n.parse().ok())
+                .filter(|&n| n > 0)
+            {
+                Some(n) => iterations = n,
+                None => {
+                    eprintln!("Error: Bad option --iterations");
+                    return usage();
+                }
+            }
+            i += 2;
+        } else {
+            match_args.push(args[i].clone());
+            i += 1;
+        }
+    }
+    let Some(mut config) = match_runner::parse_args(&match_args) else {
+        return usage();
+    };
+    if config.engines.len() > 1 {
+        eprintln!("Error: Tuning takes at most one engine");
+        return usage();
+    }
+    let base = config
+        .engines
+        .pop()
+        .unwrap_or_else(|| match_runner::self_engine("engine".to_string()));
+    if !match_args.iter().any(|a| a == "--games") {
+        config.games = DEFAULT_GAMES;
+    }
+    // Whole pairs, so each opening is played with both colors
+    config.games += config.games % 2;
+
+    // Start from the engine's defaults, or from the values given as its options
+    let mut start = SearchParams::default();
+    for (name, value) in &base.options {
+        start.set(name, value);
+    }
+    let mut theta: Vec<f64> = TUNABLES
+        .iter()
+        .map(|t| start.get(t.name).unwrap_or(t.default) as f64)
+        .collect();
+
+    let n = iterations as f64;
+    let big_a = 0.1 * n;
+    let mut rng = Prng::from_time();
+    for k in 1..=iterations {
+        let c: Vec<f64> = TUNABLES
+            .iter()
+            .map(|t| t.step * (n / k as f64).powf(GAMMA))
+            .collect();
+        let flips: Vec<f64> = TUNABLES
+            .iter()
+            .map(|_| if rng.next_u64() & 1 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        let engine = |name: &str, sign: f64| {
+            let mut engine = EngineConfig {
+                name: name.to_string(),
+                ..base.clone()
+            };
+            for (i, t) in TUNABLES.iter().enumerate() {
+                let value = theta[i] + sign * c[i] * flips[i];
+                let value = value.clamp(t.min as f64, t.max as f64).round() as i32;
+                engine.options.push((t.name.to_string(), value.to_string()));
+            }
+            engine
+        };
+        config.engines = vec![engine("plus", 1.0), engine("minus", -1.0)];
+
+        let score = match_runner::play_match(&config, |_, _| true);
+        if score.games() == 0 {
+            eprintln!("Error: No games were played");
+            return;
+        }
+        let result = score.wins as f64 - score.losses as f64;
+        for (i, t) in TUNABLES.iter().enumerate() {
+            let a_end = SPSA_R_END * t.step * t.step;
+            let a = a_end * (big_a + n).powf(ALPHA) / (big_a + k as f64).powf(ALPHA);
+            let r = a / (c[i] * c[i]);
+            theta[i] += r * c[i] * result * flips[i];
+            theta[i] = theta[i].clamp(t.min as f64, t.max as f64);
+        }
+
+        let values: Vec<String> = TUNABLES
+            .iter()
+            .zip(&theta)
+            .map(|(t, v)| format!("{}={:.1}", t.name, v))
+            .collect();
+        println!(
+            "Iteration {}/{}: {} - {} - {}, {}",
+            k,
+            iterations,
+            score.wins,
+            score.losses,
+            score.draws,
+            values.join(" ")
+        );
+        // The next iteration goes on to fresh openings
+        let len = config.openings.len();
+        config.openings.rotate_left(config.games / 2 % len);
+    }
+
+    let mut tuned = SearchParams::default();
+    for (t, v) in TUNABLES.iter().zip(&theta) {
+        tuned.set(t.name, &(v.round() as i32).to_string());
+    }
+    println!("Tuned parameters:");
+    print!("{}", params::spsa_config(&tuned));
+}
+
+fn usage() {
+    eprintln!(
+        "Usage: tune [--iterations <n>] [--engine [cmd=<path>] [option.<name>=<value> ...]] \
+         [--games <per iteration>] [--concurrency <n>] [--tc <seconds>+<inc> | \
+         --movetime <ms>] [--openings <file.epd|file.pgn>] \
+         [--draw movenumber=<n> movecount=<n> score=<cp>] [--resign movecount=<n> score=<cp>]"
+    );
+}
@@ -0,0 +1,77 @@
+//! Counters of what the search does, to judge move ordering and pruning by.
+//! The search only counts with the `stats` feature, so other builds pay
+//! nothing for them; UCI prints them as `info string` lines after `go`.
+
+use crate::trace::{Cut, CUTS};
+
+#[derive(Clone, Default, Debug)]
+pub struct SearchStats {
+    pub nodes: u64,              // Of the main search
+    pub qs_nodes: u64,           // Of quiescence
+    pub tt_probes: u64,          // At every node
+    pub tt_hits: u64,            // Probes that found the position
+    pub beta_cutoffs: u64,       // In the main search
+    pub first_move_cutoffs: u64, // Beta cutoffs by the first move searched
+    pub null_move_tries: u64,    // Null move searches, the cutoffs are under `Cut::Nmp`
+    pub lmr_searches: u64,       // Searches of late moves at a reduced depth
+    pub lmr_researches: u64,     // Those searched again for beating alpha
+    pub cuts: [u64; CUTS.len()], // Times each rule cut a node or pruned a move
+}
+
+impl SearchStats {
+    pub fn count(&mut self, cut: Cut) {
+        self.cuts[cut as usize] += 1;
+    }
+
+    /// The counters as lines for `info string`.
+    pub fn report(&self) -> Vec<String> {
+        let all_nodes = self.nodes + self.qs_nodes;
+        let pruned: Vec<String> = CUTS
+            .iter()
+            .map(|&(cut, name)| format!("{} {}", name, self.cuts[cut as usize]))
+            .collect();
+        vec![
+            format!(
+                "stats nodes {} qsearch {} ({:.1}%)",
+                all_nodes,
+                self.qs_nodes,
+                percent(self.qs_nodes, all_nodes)
+            ),
+            format!(
+                "stats tt probes {} hits {} ({:.1}%) cutoffs {} ({:.1}%)",
+                self.tt_probes,
+                self.tt_hits,
+                percent(self.tt_hits, self.tt_probes),
+                self.cuts[Cut::Tt as usize],
+                percent(self.cuts[Cut::Tt as usize], self.tt_probes)
+            ),
+            format!(
+                "stats beta cutoffs {} first move {} ({:.1}%)",
+                self.beta_cutoffs,
+                self.first_move_cutoffs,
+                percent(self.first_move_cutoffs, self.beta_cutoffs)
+            ),
+            format!(
+                "stats null move tries {} cutoffs {} ({:.1}%)",
+                self.null_move_tries,
+                self.cuts[Cut::Nmp as usize],
+                percent(self.cuts[Cut::Nmp as usize], self.null_move_tries)
+            ),
+            format!(
+                "stats lmr searches {} re-searches {} ({:.1}%)",
+                self.lmr_searches,
+                self.lmr_researches,
+                percent(self.lmr_researches, self.lmr_searches)
+            ),
+            format!("stats pruned {}", pruned.join(" ")),
+        ]
+    }
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
@@ -82,6 +82,28 @@ pub const EG_PST: [[i32; 64]; 6] = [
 pub const MG_VALUE: [i32; 6] = [82, 337, 365, 477, 1025, 0];
 pub const EG_VALUE: [i32; 6] = [94, 281, 297, 512, 968, 0];
 
+pub const FILE_BB: [u64; 8] = [
+    0x0101010101010101,
+    0x0202020202020202,
+    0x0404040404040404,
+    0x0808080808080808,
+    0x1010101010101010,
+    0x2020202020202020,
+    0x4040404040404040,
+    0x8080808080808080,
+];
+
+pub const RANK_BB: [u64; 8] = [
+    0xFF,
+    0xFF00,
+    0xFF0000,
+    0xFF000000,
+    0xFF00000000,
+    0xFF0000000000,
+    0xFF000000000000,
+    0xFF00000000000000,
+];
+
 pub struct AttackTables {
     pub knight: [u64; 64],
     pub king: [u64; 64],
@@ -101,6 +123,12 @@ pub struct Magic {
     pub shift: u8,
 }
 
+impl Default for AttackTables {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AttackTables {
     pub fn new() -> Self {
         let mut knight = [0u64; 64];
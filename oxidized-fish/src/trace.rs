@@ -0,0 +1,338 @@
+//! A recording of the search tree for debugging: a node for every position
+//! searched and a leaf for every move pruned without a search. The search
+//! only records with the `search-trace` feature, so other builds pay nothing
+//! for it, but reading and writing recordings works in any build.
+//!
+//! The file is a header line then a node a line, parents before children,
+//! with `-` for a value that is missing:
+//!
+//! ```text
+//! # oxidized-fish search tree
+//! <id> <parent> <kind> <ply> <move> <depth> <alpha> <beta> <eval> <reduction> <extension> <cut> <score>
+//! ```
+
+use crate::types::Move;
+use std::io::{self, Write};
+
+pub const HEADER: &str = "# oxidized-fish search tree";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeKind {
+    Root,
+    Search,
+    Quiescence,
+    Pruned, // A move skipped without a search
+}
+
+/// What ended a node before its moves were searched, or skipped a move.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cut {
+    Tt,
+    Rfp,
+    Nmp,
+    ProbCut,
+    MultiCut,
+    StandPat,
+    Lmp,
+    Futility,
+    See,
+    History,
+    Delta,
+}
+
+const KINDS: [(NodeKind, &str); 4] = [
+    (NodeKind::Root, "root"),
+    (NodeKind::Search, "search"),
+    (NodeKind::Quiescence, "qsearch"),
+    (NodeKind::Pruned, "pruned"),
+];
+
+/// Every cut with its name in the file.
+pub const CUTS: [(Cut, &str); 11] = [
+    (Cut::Tt, "tt"),
+    (Cut::Rfp, "rfp"),
+    (Cut::Nmp, "nmp"),
+    (Cut::ProbCut, "probcut"),
+    (Cut::MultiCut, "multicut"),
+    (Cut::StandPat, "standpat"),
+    (Cut::Lmp, "lmp"),
+    (Cut::Futility, "futility"),
+    (Cut::See, "see"),
+    (Cut::History, "history"),
+    (Cut::Delta, "delta"),
+];
+
+impl NodeKind {
+    pub fn name(self) -> &'static str {
+        KINDS
+            .iter()
+            .find(|(k, _)| *k == self)
+            .map_or("?", |(_, n)| n)
+    }
+
+    fn from_name(name: &str) -> Option<NodeKind> {
+        KINDS.iter().find(|(_, n)| *n == name).map(|(k, _)| *k)
+    }
+}
+
+impl Cut {
+    pub fn name(self) -> &'static str {
+        CUTS.iter()
+            .find(|(c, _)| *c == self)
+            .map_or("?", |(_, n)| n)
+    }
+
+    fn from_name(name: &str) -> Option<Cut> {
+        CUTS.iter().find(|(_, n)| *n == name).map(|(c, _)| *c)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TreeNode {
+    pub parent: Option<usize>,
+    pub kind: NodeKind,
+    pub ply: usize,
+    pub label: String, // The move into the node, `null`, or what searched the same position again
+    pub depth: i32,    // A pruned move's is its parent's
+    pub alpha: i32,
+    pub beta: i32,
+    pub eval: Option<i32>, // Static, none in check
+    pub reduction: i32,    // Late move reduction of the move into the node
+    pub extension: i32,    // Of the move into the node
+    pub cut: Option<Cut>,
+    pub score: Option<i32>, // None when the search stopped inside the node
+}
+
+pub struct SearchTree {
+    pub nodes: Vec<TreeNode>,
+    pub truncated: bool, // Nodes were left out for the node limit
+    max_ply: usize,
+    max_nodes: usize,
+    open: Vec<Option<usize>>, // Nodes entered and not left, None for those not recorded
+    next_child: (i32, i32),   // Reduction and extension of the next node entered
+}
+
+impl SearchTree {
+    /// An empty recording of nodes up to `max_ply` from the root, and of at
+    /// most `max_nodes` of them.
+    pub fn new(max_ply: usize, max_nodes: usize) -> Self {
+        SearchTree {
+            nodes: Vec::new(),
+            truncated: false,
+            max_ply,
+            max_nodes,
+            open: Vec::new(),
+            next_child: (0, 0),
+        }
+    }
+
+    /// The node searches are in now, if it is recorded.
+    fn current(&mut self) -> Option<&mut TreeNode> {
+        let id = (*self.open.last()?)?;
+        Some(&mut self.nodes[id])
+    }
+
+    /// Whether a child of the current node at `ply` is recorded.
+    fn records(&mut self, ply: usize) -> bool {
+        if self.open.last().is_some_and(|id| id.is_none()) || ply > self.max_ply {
+            return false;
+        }
+        if self.nodes.len() >= self.max_nodes {
+            self.truncated = true;
+            return false;
+        }
+        true
+    }
+
+    /// The reduction and extension the next node entered was given.
+    pub fn set_next_child(&mut self, reduction: i32, extension: i32) {
+        self.next_child = (reduction, extension);
+    }
+
+    /// A node at `ply` is being searched. `m` is the move into it, None for
+    /// a null move, and `excluded` whether a singular search left one out.
+    #[allow(clippy::too_many_arguments)]
+    pub fn enter(
+        &mut self,
+        kind: NodeKind,
+        ply: usize,
+        m: Option<Move>,
+        excluded: bool,
+        depth: i32,
+        alpha: i32,
+        beta: i32,
+    ) {
+        let (reduction, extension) = std::mem::take(&mut self.next_child);
+        if !self.records(ply) {
+            self.open.push(None);
+            return;
+        }
+        let parent = self.open.last().copied().flatten();
+        // The same position again: a verification search or the drop into quiescence
+        let label = if kind == NodeKind::Root {
+            "root".to_string()
+        } else if parent.is_some_and(|p| self.nodes[p].ply == ply) {
+            match kind {
+                _ if excluded => "singular",
+                NodeKind::Quiescence => "qsearch",
+                _ => "iid",
+            }
+            .to_string()
+        } else {
+            m.map_or("null".to_string(), |m| m.to_string())
+        };
+        self.open.push(Some(self.nodes.len()));
+        self.nodes.push(TreeNode {
+            parent,
+            kind,
+            ply,
+            label,
+            depth,
+            alpha,
+            beta,
+            eval: None,
+            reduction,
+            extension,
+            cut: None,
+            score: None,
+        });
+    }
+
+    pub fn set_eval(&mut self, eval: Option<i32>) {
+        if let Some(node) = self.current() {
+            node.eval = eval;
+        }
+    }
+
+    /// The current node returns early because of `cut`.
+    pub fn cut(&mut self, cut: Cut) {
+        if let Some(node) = self.current() {
+            node.cut = Some(cut);
+        }
+    }
+
+    /// The current node skips `m` because of `cut`.
+    pub fn prune(&mut self, m: Move, cut: Cut) {
+        let Some(parent) = self.open.last().copied().flatten() else {
+            return;
+        };
+        let ply = self.nodes[parent].ply + 1;
+        if !self.records(ply) {
+            return;
+        }
+        let p = &self.nodes[parent];
+        let node = TreeNode {
+            parent: Some(parent),
+            kind: NodeKind::Pruned,
+            ply,
+            label: m.to_string(),
+            depth: p.depth,
+            alpha: p.alpha,
+            beta: p.beta,
+            eval: None,
+            reduction: 0,
+            extension: 0,
+            cut: Some(cut),
+            score: None,
+        };
+        self.nodes.push(node);
+    }
+
+    /// The current node returns `score`, or None when the search stopped.
+    pub fn leave(&mut self, score: Option<i32>) {
+        if let Some(node) = self.current() {
+            node.score = score;
+        }
+        self.open.pop();
+    }
+
+    /// Each node's children in the order they were searched, by id.
+    pub fn children(&self) -> Vec<Vec<usize>> {
+        let mut children = vec![Vec::new(); self.nodes.len()];
+        for (id, node) in self.nodes.iter().enumerate() {
+            if let Some(parent) = node.parent {
+                children[parent].push(id);
+            }
+        }
+        children
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let opt = |v: Option<i32>| v.map_or("-".to_string(), |v| v.to_string());
+        writeln!(out, "{}", HEADER)?;
+        for (id, n) in self.nodes.iter().enumerate() {
+            writeln!(
+                out,
+                "{} {} {} {} {} {} {} {} {} {} {} {} {}",
+                id,
+                n.parent.map_or("-".to_string(), |p| p.to_string()),
+                n.kind.name(),
+                n.ply,
+                n.label,
+                n.depth,
+                n.alpha,
+                n.beta,
+                opt(n.eval),
+                n.reduction,
+                n.extension,
+                n.cut.map_or("-", Cut::name),
+                opt(n.score)
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn read(text: &str) -> Result<SearchTree, String> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some(HEADER) {
+            return Err("Not a search tree recording".to_string());
+        }
+        let mut tree = SearchTree::new(usize::MAX, usize::MAX);
+        for (i, line) in lines.enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let node = parse_node(line, tree.nodes.len())
+                .ok_or_else(|| format!("Bad node on line {}", i + 2))?;
+            tree.nodes.push(node);
+        }
+        Ok(tree)
+    }
+}
+
+/// A node line, which must be node `id` and come after its parent.
+fn parse_node(line: &str, id: usize) -> Option<TreeNode> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 13 || fields[0].parse::<usize>().ok()? != id {
+        return None;
+    }
+    let opt = |s: &str| -> Option<Option<i32>> {
+        if s == "-" {
+            Some(None)
+        } else {
+            s.parse().ok().map(Some)
+        }
+    };
+    let parent = match fields[1] {
+        "-" => None,
+        p => Some(p.parse::<usize>().ok().filter(|&p| p < id)?),
+    };
+    let cut = match fields[11] {
+        "-" => None,
+        c => Some(Cut::from_name(c)?),
+    };
+    Some(TreeNode {
+        parent,
+        kind: NodeKind::from_name(fields[2])?,
+        ply: fields[3].parse().ok()?,
+        label: fields[4].to_string(),
+        depth: fields[5].parse().ok()?,
+        alpha: fields[6].parse().ok()?,
+        beta: fields[7].parse().ok()?,
+        eval: opt(fields[8])?,
+        reduction: fields[9].parse().ok()?,
+        extension: fields[10].parse().ok()?,
+        cut,
+        score: opt(fields[12])?,
+    })
+}
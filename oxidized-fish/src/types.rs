@@ -59,6 +59,14 @@ impl Move {
         Move { data }
     }
 
+    pub fn from_u16(data: u16) -> Self {
+        Move { data }
+    }
+
+    pub fn to_u16(self) -> u16 {
+        self.data
+    }
+
     pub fn from(&self) -> u8 {
         (self.data & 0x3F) as u8
     }
@@ -1,23 +1,27 @@
 use crate::board::Board;
-use crate::search::Search;
-use crate::types::{Move, CONTEMPT, THREADS};
+use crate::game::GameState;
+use crate::mate::MateResult;
+pub use crate::movegen::parse_move;
+use crate::search::{Search, SearchInfo};
+use crate::skill::MAX_LEVEL;
+use crate::types::{Color, Move, CONTEMPT, THREADS};
 use std::io::{self, BufRead};
 use std::time::Duration;
 
-pub fn start_uci() {
+/// Runs the UCI loop, starting with `first_line` which was already read to
+/// pick the protocol.
+pub fn start_uci(first_line: String) {
     let stdin = io::stdin();
     let mut searcher = Search::new();
-    let mut board = Board::new();
+    let mut game = GameState::new(Board::new());
 
-    println!("id name Oxidized Fish 0.7-Hardened");
-    println!("id author Gemini");
-    println!("option name Hash type spin default 128 min 1 max 2048");
-    println!("option name Threads type spin default 1 min 1 max 64");
-    println!("option name Contempt type spin default 0 min -100 max 100");
-    println!("uciok");
+    if let Err(e) = searcher.experience.load() {
+        eprintln!("Error: Failed to read experience file: {}", e);
+    }
+    searcher.on_info = Some(Box::new(|info| println!("{}", format_info(info))));
 
-    for line in stdin.lock().lines() {
-        let line = line.unwrap();
+    let lines = std::iter::once(first_line).chain(stdin.lock().lines().map(|l| l.unwrap()));
+    for line in lines {
         let args: Vec<&str> = line.split_whitespace().collect();
 
         if args.is_empty() {
@@ -26,146 +30,298 @@ pub fn start_uci() {
 
         match args[0] {
             "uci" => {
-                println!("id name Oxidized Fish 0.7-Hardened");
-                println!("id author Gemini");
-                println!("option name Hash type spin default 128 min 1 max 2048");
-                println!("option name Threads type spin default 1 min 1 max 64");
-                println!("option name Contempt type spin default 0 min -100 max 100");
-                println!("uciok");
+                print_id();
             }
             "isready" => println!("readyok"),
             "ucinewgame" => {
-                board = Board::new();
+                end_game(&mut searcher);
+                game = GameState::new(Board::new());
                 searcher.clear_tt();
             }
             "setoption" => {
-                if args.len() >= 5 {
-                    match args[2] {
-                        "Hash" => {
-                            if let Ok(size) = args[4].parse::<usize>() {
-                                searcher.resize_tt(size);
-                            }
-                        }
-                        "Threads" => {
-                            if let Ok(threads) = args[4].parse::<usize>() {
-                                THREADS.store(threads, std::sync::atomic::Ordering::Relaxed);
-                            }
-                        }
-                        "Contempt" => {
-                            if let Ok(contempt) = args[4].parse::<i32>() {
-                                CONTEMPT.store(contempt, std::sync::atomic::Ordering::Relaxed);
-                            }
-                        }
-                        _ => {}
-                    }
-                }
+                let (name, value) = parse_setoption(&args);
+                set_option(&mut searcher, &name, &value);
             }
             "position" => {
-                let mut moves_start = 0;
-                searcher.game_history_count = 0;
-                if args.len() > 1 && args[1] == "startpos" {
-                    board = Board::new();
-                    moves_start = 2;
-                } else if args.len() > 1 && args[1] == "fen" {
-                    let mut fen_parts = Vec::new();
-                    let mut i = 2;
-                    while i < args.len() && args[i] != "moves" {
-                        fen_parts.push(args[i]);
-                        i += 1;
-                    }
-                    board = Board::from_fen(&fen_parts.join(" "));
-                    moves_start = i;
-                }
-
-                searcher.game_history[searcher.game_history_count] = board.hash;
-                searcher.game_history_count += 1;
-
-                if moves_start < args.len() && args[moves_start] == "moves" {
-                    for m_str in &args[moves_start + 1..] {
-                        if let Some(mv) = parse_move(&board, m_str) {
-                            board = board.make_move(mv);
-                            searcher.game_history[searcher.game_history_count] = board.hash;
-                            searcher.game_history_count += 1;
-                        } else {
-                            eprintln!("Error: Failed to parse move {}", m_str);
-                        }
-                    }
+                if let Some(g) = parse_position(&args) {
+                    game = g;
                 }
             }
             "go" => {
-                let mut depth = 64;
-                let mut wtime: Option<u64> = None;
-                let mut btime: Option<u64> = None;
-                let mut winc: u64 = 0;
-                let mut binc: u64 = 0;
-                let mut movetime: Option<u64> = None;
-
-                let mut i = 1;
-                while i < args.len() {
-                    match args[i] {
-                        "depth" => {
-                            if i + 1 < args.len() {
-                                depth = args[i + 1].parse().unwrap_or(64);
-                                i += 2;
-                                continue;
-                            }
-                        }
-                        "wtime" => {
-                            if i + 1 < args.len() {
-                                wtime = args[i + 1].parse().ok();
-                                i += 2;
-                                continue;
-                            }
-                        }
-                        "btime" => {
-                            if i + 1 < args.len() {
-                                btime = args[i + 1].parse().ok();
-                                i += 2;
-                                continue;
-                            }
-                        }
-                        "winc" => {
-                            if i + 1 < args.len() {
-                                winc = args[i + 1].parse().unwrap_or(0);
-                                i += 2;
-                                continue;
-                            }
+                let (depth, time_limit) = parse_go(&args, game.board.side_to_move);
+                let m = match parse_go_mate(&args) {
+                    Some(moves) => go_mate(&mut searcher, &game, moves, time_limit, |s| {
+                        println!("{}", s)
+                    }),
+                    None => {
+                        let m = searcher.go(&game, depth, time_limit);
+                        #[cfg(feature = "stats")]
+                        for line in searcher.stats.report() {
+                            println!("info string {}", line);
                         }
-                        "binc" => {
-                            if i + 1 < args.len() {
-                                binc = args[i + 1].parse().unwrap_or(0);
-                                i += 2;
-                                continue;
-                            }
-                        }
-                        "movetime" => {
-                            if i + 1 < args.len() {
-                                movetime = args[i + 1].parse().ok();
-                                i += 2;
-                                continue;
-                            }
-                        }
-                        _ => {}
+                        m
                     }
-                    i += 1;
-                }
-
-                let time_limit =
-                    calculate_time_limit(board.side_to_move, wtime, btime, winc, binc, movetime);
-
-                if let Some(m) = searcher.go(&board, depth, time_limit) {
+                };
+                if let Some(m) = m {
                     println!("bestmove {}", m.to_string());
                 } else {
                     println!("bestmove 0000");
                 }
             }
-            "quit" => break,
+            "spsa" => print!("{}", crate::params::spsa_config(&searcher.params)),
+            "eval" => println!(
+                "{}",
+                crate::eval::trace(&game.board, &mut searcher.pawn_table).to_table()
+            ),
+            "quit" => {
+                end_game(&mut searcher);
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn set_option(searcher: &mut Search, name: &str, value: &str) {
+    match name {
+        "Hash" => {
+            if let Ok(size) = value.parse::<usize>() {
+                searcher.resize_tt(size);
+            }
+        }
+        "Threads" => {
+            if let Ok(threads) = value.parse::<usize>() {
+                THREADS.store(threads, std::sync::atomic::Ordering::Relaxed);
+            }
+        }
+        "Contempt" => {
+            if let Ok(contempt) = value.parse::<i32>() {
+                CONTEMPT.store(contempt, std::sync::atomic::Ordering::Relaxed);
+            }
+        }
+        "Dynamic Contempt" => searcher.dynamic_contempt = value == "true",
+        "UCI_Opponent" => searcher.opponent_elo = parse_opponent_elo(value),
+        "UCI_ShowWDL" => searcher.show_wdl = value == "true",
+        "Normalize Score" => searcher.normalize_score = value == "true",
+        "MultiPV" => {
+            if let Ok(n) = value.parse::<usize>() {
+                searcher.multi_pv = n.clamp(1, 64);
+            }
+        }
+        "Skill Level" => {
+            if let Ok(level) = value.parse::<i32>() {
+                searcher.skill.level = level.clamp(0, MAX_LEVEL);
+            }
+        }
+        "Skill Eval Noise" => searcher.skill.eval_noise = value == "true",
+        "Experience Enabled" => searcher.experience.enabled = value == "true",
+        "Experience File" => {
+            end_game(searcher);
+            searcher.experience.set_path(value);
+            if let Err(e) = searcher.experience.load() {
+                eprintln!("Error: Failed to read experience file: {}", e);
+            }
+            searcher.clear_tt();
+        }
+        // Anything else may be one of the hidden search parameters
+        _ => {
+            searcher.params.set(name, value);
+        }
+    }
+}
+
+/// The game from `position [startpos | fen <fen>] [moves ...]`.
+pub fn parse_position(args: &[&str]) -> Option<GameState> {
+    let mut game;
+    let moves_start;
+    if args.len() > 1 && args[1] == "startpos" {
+        game = GameState::new(Board::new());
+        moves_start = 2;
+    } else if args.len() > 1 && args[1] == "fen" {
+        let mut fen_parts = Vec::new();
+        let mut i = 2;
+        while i < args.len() && args[i] != "moves" {
+            fen_parts.push(args[i]);
+            i += 1;
+        }
+        game = GameState::new(Board::from_fen(&fen_parts.join(" ")));
+        moves_start = i;
+    } else {
+        return None;
+    }
+
+    if moves_start < args.len() && args[moves_start] == "moves" {
+        for m_str in &args[moves_start + 1..] {
+            if let Some(mv) = parse_move(&game.board, m_str) {
+                game.make_move(mv);
+            } else {
+                eprintln!("Error: Failed to parse move {}", m_str);
+            }
+        }
+    }
+    Some(game)
+}
+
+/// Depth and time limits from the arguments of `go`.
+pub fn parse_go(args: &[&str], side_to_move: Color) -> (u8, Option<Duration>) {
+    let mut depth = 64;
+    let mut wtime: Option<u64> = None;
+    let mut btime: Option<u64> = None;
+    let mut winc: u64 = 0;
+    let mut binc: u64 = 0;
+    let mut movetime: Option<u64> = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i] {
+            "depth" => {
+                if i + 1 < args.len() {
+                    depth = args[i + 1].parse().unwrap_or(64);
+                    i += 2;
+                    continue;
+                }
+            }
+            "wtime" => {
+                if i + 1 < args.len() {
+                    wtime = args[i + 1].parse().ok();
+                    i += 2;
+                    continue;
+                }
+            }
+            "btime" => {
+                if i + 1 < args.len() {
+                    btime = args[i + 1].parse().ok();
+                    i += 2;
+                    continue;
+                }
+            }
+            "winc" => {
+                if i + 1 < args.len() {
+                    winc = args[i + 1].parse().unwrap_or(0);
+                    i += 2;
+                    continue;
+                }
+            }
+            "binc" => {
+                if i + 1 < args.len() {
+                    binc = args[i + 1].parse().unwrap_or(0);
+                    i += 2;
+                    continue;
+                }
+            }
+            "movetime" => {
+                if i + 1 < args.len() {
+                    movetime = args[i + 1].parse().ok();
+                    i += 2;
+                    continue;
+                }
+            }
             _ => {}
         }
+        i += 1;
     }
+
+    let time_limit = calculate_time_limit(side_to_move, wtime, btime, winc, binc, movetime);
+
+    (depth, time_limit)
 }
 
-fn calculate_time_limit(
+/// The moves from `go mate <moves>`, if asked for.
+pub fn parse_go_mate(args: &[&str]) -> Option<u32> {
+    let i = args.iter().position(|&a| a == "mate")?;
+    args.get(i + 1)?.parse().ok().filter(|&n| n > 0)
+}
+
+/// Runs the mate solver, falling back to a normal search for a move to
+/// play when it finds no mate. `say` gets the protocol lines to send.
+pub fn go_mate(
+    searcher: &mut Search,
+    game: &GameState,
+    moves: u32,
+    time_limit: Option<Duration>,
+    mut say: impl FnMut(&str),
+) -> Option<Move> {
+    match searcher.solve_mate(game, moves, time_limit) {
+        MateResult::Mate(_, pv) => return pv.first().copied(),
+        MateResult::NoMate => say(&format!("info string No mate in {} moves", moves)),
+        MateResult::Unknown => say("info string Mate search stopped"),
+    }
+    let depth = (2 * moves).min(64) as u8;
+    searcher.go(game, depth, time_limit)
+}
+
+pub fn format_info(info: &SearchInfo) -> String {
+    let mut out = format!("info depth {}", info.depth);
+    if let Some(n) = info.multipv {
+        out.push_str(&format!(" multipv {}", n));
+    }
+    match info.mate_in() {
+        Some(n) => out.push_str(&format!(" score mate {}", n)),
+        None => out.push_str(&format!(" score cp {}", info.score)),
+    }
+    if let Some((w, d, l)) = info.wdl {
+        out.push_str(&format!(" wdl {} {} {}", w, d, l));
+    }
+    out.push_str(&format!(
+        " nodes {} time {} nps {} pv",
+        info.nodes,
+        info.time_ms,
+        info.nps()
+    ));
+    for m in &info.pv {
+        out.push(' ');
+        out.push_str(&m.to_string());
+    }
+    out
+}
+
+fn print_id() {
+    println!("id name Oxidized Fish 0.7-Hardened");
+    println!("id author Gemini");
+    println!("option name Hash type spin default 128 min 1 max 2048");
+    println!("option name Threads type spin default 1 min 1 max 64");
+    println!("option name Contempt type spin default 0 min -100 max 100");
+    println!("option name Dynamic Contempt type check default false");
+    println!("option name UCI_Opponent type string default none none computer none");
+    println!("option name UCI_ShowWDL type check default false");
+    println!("option name Normalize Score type check default false");
+    println!("option name MultiPV type spin default 1 min 1 max 64");
+    println!(
+        "option name Skill Level type spin default {0} min 0 max {0}",
+        MAX_LEVEL
+    );
+    println!("option name Skill Eval Noise type check default true");
+    println!("option name Experience Enabled type check default false");
+    println!(
+        "option name Experience File type string default {}",
+        crate::experience::DEFAULT_FILE
+    );
+    println!("uciok");
+}
+
+fn end_game(searcher: &mut Search) {
+    if let Err(e) = searcher.experience.end_game() {
+        eprintln!("Error: Failed to write experience file: {}", e);
+    }
+}
+
+/// Splits `setoption name <name> value <value>`, either part may hold spaces.
+pub fn parse_setoption(args: &[&str]) -> (String, String) {
+    let value_pos = args
+        .iter()
+        .position(|&a| a == "value")
+        .unwrap_or(args.len());
+    let name = args.get(2..value_pos).unwrap_or(&[]).join(" ");
+    let value = args.get(value_pos + 1..).unwrap_or(&[]).join(" ");
+    (name, value)
+}
+
+/// The rating from `<title> <elo> <computer|human> <name>`, if the GUI knows it.
+fn parse_opponent_elo(value: &str) -> Option<i32> {
+    value.split_whitespace().nth(1)?.parse().ok()
+}
+
+pub fn calculate_time_limit(
     side_to_move: crate::types::Color,
     wtime: Option<u64>,
     btime: Option<u64>,
@@ -207,15 +363,3 @@ fn calculate_time_limit(
 
     None
 }
-
-fn parse_move(board: &Board, m_str: &str) -> Option<Move> {
-    let legal_moves = crate::movegen::MoveGen::generate(board);
-    for m in legal_moves {
-        if m.to_string() == m_str {
-            if board.is_legal(m) {
-                return Some(m);
-            }
-        }
-    }
-    None
-}
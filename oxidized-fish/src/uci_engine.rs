@@ -0,0 +1,165 @@
+//! A UCI engine run as a child process, for the match runner. Its output is
+//! read on a thread of its own so that waiting for a move can time out.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long an engine may take to start up or to answer `isready`.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct UciEngine {
+    pub name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+/// An engine's answer to `go`.
+pub struct EngineMove {
+    pub best: String,
+    pub score: Option<i32>, // Centipawns for the side to move, mates as large scores
+    pub elapsed: Duration,
+}
+
+impl UciEngine {
+    /// Starts `cmd` and sets `options` once it has answered `uci`.
+    pub fn start(name: &str, cmd: &str, options: &[(String, String)]) -> io::Result<Self> {
+        let mut child = Command::new(cmd)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("Engine stdin is piped");
+        let stdout = child.stdout.take().expect("Engine stdout is piped");
+        let (tx, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = UciEngine {
+            name: name.to_string(),
+            child,
+            stdin,
+            lines,
+        };
+        engine.send("uci")?;
+        engine.wait_for("uciok", STARTUP_TIMEOUT)?;
+        for (option, value) in options {
+            engine.send(&format!("setoption name {} value {}", option, value))?;
+        }
+        engine.sync()?;
+        Ok(engine)
+    }
+
+    pub fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    /// Waits until the engine has handled everything sent so far.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.send("isready")?;
+        self.wait_for("readyok", STARTUP_TIMEOUT)
+    }
+
+    pub fn new_game(&mut self) -> io::Result<()> {
+        self.send("ucinewgame")?;
+        self.sync()
+    }
+
+    /// Sends `position` and `go`, then waits up to `timeout` for the move.
+    pub fn go(&mut self, position: &str, go: &str, timeout: Duration) -> io::Result<EngineMove> {
+        self.send(position)?;
+        self.send(go)?;
+        let start = Instant::now();
+        let mut score = None;
+        loop {
+            let left = timeout.saturating_sub(start.elapsed());
+            let line = match self.next_line(left) {
+                Ok(line) => line,
+                Err(e) => {
+                    // Keep a late move from answering the next `go`
+                    if e.kind() == io::ErrorKind::TimedOut {
+                        let _ = self.send("stop");
+                        let _ = self.wait_for("bestmove", STARTUP_TIMEOUT);
+                    }
+                    return Err(e);
+                }
+            };
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("info") => score = parse_score(&line).or(score),
+                Some("bestmove") => {
+                    return Ok(EngineMove {
+                        best: words.next().unwrap_or("0000").to_string(),
+                        score,
+                        elapsed: start.elapsed(),
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads up to a line starting with `token`.
+    fn wait_for(&mut self, token: &str, timeout: Duration) -> io::Result<()> {
+        let start = Instant::now();
+        loop {
+            let line = self.next_line(timeout.saturating_sub(start.elapsed()))?;
+            if line.split_whitespace().next() == Some(token) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn next_line(&mut self, timeout: Duration) -> io::Result<String> {
+        match self.lines.recv_timeout(timeout) {
+            Ok(line) => Ok(line),
+            Err(RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{} did not answer in time", self.name),
+            )),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} exited", self.name),
+            )),
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        // Give it a moment to leave on its own before it is killed
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The score of an `info` line: centipawns, or mates as 30000 less the
+/// plies to mate.
+fn parse_score(line: &str) -> Option<i32> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let i = words.iter().position(|&w| w == "score")?;
+    let value: i32 = words.get(i + 2)?.parse().ok()?;
+    match *words.get(i + 1)? {
+        "cp" => Some(value),
+        "mate" if value > 0 => Some(30000 - 2 * value + 1),
+        "mate" => Some(-30000 - 2 * value),
+        _ => None,
+    }
+}
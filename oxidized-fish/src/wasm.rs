@@ -0,0 +1,203 @@
+//! Exports for the browser, built with
+//! `cargo build --release --lib --target wasm32-unknown-unknown` and copied
+//! to `webapp/public/oxidized_fish.wasm`.
+//!
+//! Strings go in as a pointer and length into memory from `alloc`. Results
+//! are left in a buffer that JS reads through `output_ptr` and the returned
+//! length. Progress is passed to the `env.on_info` import as UCI info lines.
+
+use crate::board::Board;
+use crate::game::GameState;
+use crate::search::{Search, SearchInfo};
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Hash size in MB. Browser memory is scarcer than a server's.
+const HASH_MB: usize = 16;
+const MAX_DEPTH: u32 = 64;
+
+extern "C" {
+    fn on_info(ptr: *const u8, len: usize);
+}
+
+thread_local! {
+    static ENGINE: RefCell<Option<Search>> = const { RefCell::new(None) };
+    static OUTPUT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+#[no_mangle]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// Frees memory from `alloc`.
+///
+/// # Safety
+/// `ptr` and `len` must come from a single `alloc` call.
+#[no_mangle]
+pub unsafe extern "C" fn dealloc(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+#[no_mangle]
+pub extern "C" fn output_ptr() -> *const u8 {
+    OUTPUT.with(|out| out.borrow().as_ptr())
+}
+
+/// Forgets the hash and history between games.
+#[no_mangle]
+pub extern "C" fn new_game() {
+    with_engine(|searcher| searcher.clear_tt());
+}
+
+/// Searches `fen` and leaves a JSON object with the best move and the lines
+/// in the output buffer, returning its length. A limit of 0 is no limit;
+/// `movetime` is turned into nodes since there is no clock.
+///
+/// # Safety
+/// `fen_ptr` must point to `fen_len` bytes of memory from `alloc`.
+#[no_mangle]
+pub unsafe extern "C" fn analyze(
+    fen_ptr: *const u8,
+    fen_len: usize,
+    depth: u32,
+    movetime: u32,
+    nodes: u32,
+    multipv: u32,
+) -> usize {
+    let fen = read_str(fen_ptr, fen_len);
+    let json = match search(&fen, depth, movetime, nodes, multipv) {
+        Some((m, lines)) => {
+            let lines: Vec<String> = lines.iter().map(line_json).collect();
+            format!(
+                "{{\"bestmove\":{},\"lines\":[{}]}}",
+                m.map_or("null".to_string(), |m| format!("\"{}\"", m)),
+                lines.join(",")
+            )
+        }
+        None => "{\"error\":\"Invalid FEN\"}".to_string(),
+    };
+    write_output(json)
+}
+
+/// Like `analyze` but leaves only the move, in UCI notation, or `0000` when
+/// there is none.
+///
+/// # Safety
+/// `fen_ptr` must point to `fen_len` bytes of memory from `alloc`.
+#[no_mangle]
+pub unsafe extern "C" fn bestmove(
+    fen_ptr: *const u8,
+    fen_len: usize,
+    depth: u32,
+    movetime: u32,
+    nodes: u32,
+) -> usize {
+    let fen = read_str(fen_ptr, fen_len);
+    let m = search(&fen, depth, movetime, nodes, 1).and_then(|(m, _)| m);
+    write_output(m.unwrap_or_else(|| "0000".to_string()))
+}
+
+fn search(
+    fen: &str,
+    depth: u32,
+    movetime: u32,
+    nodes: u32,
+    multipv: u32,
+) -> Option<(Option<String>, Vec<SearchInfo>)> {
+    if !Board::is_valid_fen(fen) {
+        return None;
+    }
+    let game = GameState::new(Board::from_fen(fen));
+    let depth = if depth == 0 {
+        MAX_DEPTH
+    } else {
+        depth.min(MAX_DEPTH)
+    };
+    let time_limit = (movetime > 0).then(|| Duration::from_millis(movetime as u64));
+
+    with_engine(|searcher| {
+        searcher.multi_pv = multipv.clamp(1, 64) as usize;
+        searcher.node_limit = (nodes > 0).then_some(nodes as u64);
+
+        // The latest info of every line, as in the server's JSON API
+        let lines: Arc<Mutex<Vec<SearchInfo>>> = Arc::new(Mutex::new(Vec::new()));
+        let sink = lines.clone();
+        searcher.on_info = Some(Box::new(move |info| {
+            let text = info_line(info);
+            unsafe { on_info(text.as_ptr(), text.len()) };
+            let mut lines = sink.lock().unwrap();
+            let idx = info.multipv.unwrap_or(1) - 1;
+            if idx < lines.len() {
+                lines[idx] = info.clone();
+            } else {
+                lines.push(info.clone());
+            }
+        }));
+
+        let m = searcher.go(&game, depth as u8, time_limit);
+        searcher.on_info = None;
+        let lines = lines.lock().unwrap().clone();
+        Some((m.map(|m| m.to_string()), lines))
+    })
+}
+
+fn with_engine<T>(f: impl FnOnce(&mut Search) -> T) -> T {
+    ENGINE.with(|engine| {
+        let mut engine = engine.borrow_mut();
+        let searcher = engine.get_or_insert_with(|| Search::with_hash(HASH_MB));
+        f(searcher)
+    })
+}
+
+unsafe fn read_str(ptr: *const u8, len: usize) -> String {
+    String::from_utf8_lossy(std::slice::from_raw_parts(ptr, len)).into_owned()
+}
+
+fn write_output(text: String) -> usize {
+    let len = text.len();
+    OUTPUT.with(|out| *out.borrow_mut() = text.into_bytes());
+    len
+}
+
+fn pv_text(info: &SearchInfo) -> Vec<String> {
+    info.pv.iter().map(|m| m.to_string()).collect()
+}
+
+fn info_line(info: &SearchInfo) -> String {
+    let mut out = format!("info depth {}", info.depth);
+    if let Some(n) = info.multipv {
+        out.push_str(&format!(" multipv {}", n));
+    }
+    match info.mate_in() {
+        Some(n) => out.push_str(&format!(" score mate {}", n)),
+        None => out.push_str(&format!(" score cp {}", info.score)),
+    }
+    out.push_str(&format!(
+        " nodes {} pv {}",
+        info.nodes,
+        pv_text(info).join(" ")
+    ));
+    out
+}
+
+fn line_json(info: &SearchInfo) -> String {
+    let (kind, value) = match info.mate_in() {
+        Some(n) => ("mate", n),
+        None => ("cp", info.score),
+    };
+    let pv: Vec<String> = pv_text(info).iter().map(|m| format!("\"{}\"", m)).collect();
+    format!(
+        "{{\"multipv\":{},\"depth\":{},\"score\":{{\"{}\":{}}},\"nodes\":{},\"pv\":[{}]}}",
+        info.multipv.unwrap_or(1),
+        info.depth,
+        kind,
+        value,
+        info.nodes,
+        pv.join(",")
+    )
+}
@@ -0,0 +1,370 @@
+//! `wdl`: fits the win-draw-loss model in `wdl.rs` to self-play. `wdl play`
+//! plays games at a fixed depth, each from a few random plies, and writes
+//! every position's material and score with the result of its game. `wdl
+//! fit` fits the model's win rate to those by maximum likelihood for each
+//! material count, fits the cubics in `m / 58` to the per-material values
+//! by least squares weighted with the positions behind them, and prints the
+//! coefficients.
+//!
+//! ```text
+//! wdl play --games 2000 --depth 5 --seed 1 --output wdl.txt
+//! wdl fit wdl.txt
+//! ```
+
+use crate::game::{GameResult, GameState};
+use crate::movegen::MoveGen;
+use crate::search::{Search, MAX_PLY};
+use crate::skill::Prng;
+use crate::types::MATE_VALUE;
+use crate::wdl::{self, MATERIAL_ANCHOR, MATERIAL_MAX, MATERIAL_MIN};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+const HEADER: &str = "# oxidized-fish wdl data";
+
+const DEFAULT_GAMES: usize = 100;
+const DEFAULT_DEPTH: u8 = 5;
+const DEFAULT_RANDOM_PLIES: usize = 8;
+const DEFAULT_HASH: usize = 16;
+
+/// Games still going after this many plies are scored as draws.
+const MAX_GAME_PLIES: usize = 400;
+
+/// Fewest positions a material count needs to be fitted on its own.
+const MIN_POSITIONS: usize = 500;
+
+pub fn run(args: &[String]) {
+    match args.first().map(String::as_str) {
+        Some("play") => play(&args[1..]),
+        Some("fit") => fit(&args[1..]),
+        _ => usage(),
+    }
+}
+
+fn usage() {
+    eprintln!(
+        "Usage: wdl play [--games <n>] [--depth <plies>] [--random-plies <n>] \
+         [--seed <n>] [--hash <MB>] [--output <file>]\n       \
+         wdl fit <file>..."
+    );
+}
+
+/// A position seen in a game: material, score for the side to move, and
+/// the result for that side, 1 for a win, 0 for a draw and -1 for a loss.
+struct Sample {
+    material: i32,
+    score: i32,
+    result: i32,
+}
+
+fn play(args: &[String]) {
+    let mut games = DEFAULT_GAMES;
+    let mut depth = DEFAULT_DEPTH;
+    let mut random_plies = DEFAULT_RANDOM_PLIES;
+    let mut seed = None;
+    let mut hash = DEFAULT_HASH;
+    let mut output = "wdl.txt".to_string();
+    let mut i = 0;
+    while i < args.len() {
+        let Some(value) = args.get(i + 1) else {
+            eprintln!("Error: Missing value for {}", args[i]);
+            return usage();
+        };
+        let number = value.parse::<usize>().ok();
+        match (args[i].as_str(), number) {
+            ("--output", _) => output = value.clone(),
+            ("--games", Some(n)) if n > 0 => games = n,
+            ("--depth", Some(n)) if n > 0 && n < MAX_PLY => depth = n as u8,
+            ("--random-plies", Some(n)) => random_plies = n,
+            ("--seed", Some(n)) => seed = Some(n as u64),
+            ("--hash", Some(n)) if n > 0 => hash = n,
+            _ => {
+                eprintln!("Error: Bad option {} {}", args[i], value);
+                return usage();
+            }
+        }
+        i += 2;
+    }
+
+    let mut out = match File::create(&output) {
+        Ok(file) => BufWriter::new(file),
+        Err(e) => {
+            eprintln!("Error: Failed to create {}: {}", output, e);
+            return;
+        }
+    };
+    let mut rng = seed.map_or_else(Prng::from_time, Prng::new);
+    let mut positions = 0;
+    let written = writeln!(out, "{}", HEADER).and_then(|_| {
+        for game in 1..=games {
+            let samples = play_game(&mut rng, depth, random_plies, hash);
+            for s in &samples {
+                writeln!(out, "{} {} {}", s.material, s.score, s.result)?;
+            }
+            positions += samples.len();
+            eprint!("\rGame {} of {}, {} positions", game, games, positions);
+        }
+        eprintln!();
+        out.flush()
+    });
+    if let Err(e) = written {
+        eprintln!("Error: Failed to write {}: {}", output, e);
+    }
+}
+
+/// Plays one game at `depth` after `random_plies` random moves and returns
+/// its positions with their scores and the result.
+fn play_game(rng: &mut Prng, depth: u8, random_plies: usize, hash: usize) -> Vec<Sample> {
+    // Random plies that end the game start it over
+    let mut game = GameState::new(crate::board::Board::new());
+    while game.moves().len() < random_plies {
+        let moves: Vec<_> = MoveGen::generate(&game.board)
+            .into_iter()
+            .filter(|&m| game.board.is_legal(m))
+            .collect();
+        if moves.is_empty() || game.result().is_some() {
+            game = GameState::new(crate::board::Board::new());
+            continue;
+        }
+        game.make_move(moves[(rng.next_u64() % moves.len() as u64) as usize]);
+    }
+
+    let mut searcher = Search::with_hash(hash);
+    let mut scored = Vec::new(); // Material, score and side to move
+    let result = loop {
+        if let Some(result) = game.result() {
+            break Some(result);
+        }
+        if game.moves().len() >= MAX_GAME_PLIES {
+            break None;
+        }
+        let Some(m) = searcher.go(&game, depth, None) else {
+            break None;
+        };
+        if let Some(&(_, score)) = searcher.root_lines.first() {
+            // Mate scores are outside the model
+            if score.abs() < MATE_VALUE - MAX_PLY as i32 {
+                scored.push((wdl::material(&game.board), score, game.board.side_to_move));
+            }
+        }
+        game.make_move(m);
+    };
+
+    scored
+        .into_iter()
+        .map(|(material, score, side)| Sample {
+            material,
+            score,
+            result: match result {
+                Some(GameResult::Checkmate(winner)) if winner == side => 1,
+                Some(GameResult::Checkmate(_)) => -1,
+                _ => 0,
+            },
+        })
+        .collect()
+}
+
+fn fit(paths: &[String]) {
+    if paths.is_empty() {
+        return usage();
+    }
+    let mut samples = Vec::new();
+    for path in paths {
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| parse_samples(&text));
+        match parsed {
+            Ok(mut s) => samples.append(&mut s),
+            Err(e) => {
+                eprintln!("Error: Failed to read {}: {}", path, e);
+                return;
+            }
+        }
+    }
+
+    let mut by_material = vec![Vec::new(); (MATERIAL_MAX + 1) as usize];
+    for s in &samples {
+        let m = s.material.clamp(MATERIAL_MIN, MATERIAL_MAX) as usize;
+        by_material[m].push((s.score, s.result));
+    }
+    // Points for the cubics: material, a, b and the positions behind them
+    let mut points = Vec::new();
+    println!("material positions a b");
+    for (m, positions) in by_material.iter().enumerate() {
+        if positions.len() < MIN_POSITIONS {
+            continue;
+        }
+        let Some((a, b)) = fit_logistic(positions) else {
+            continue;
+        };
+        println!("{} {} {:.1} {:.1}", m, positions.len(), a, b);
+        points.push((m as f64 / MATERIAL_ANCHOR, a, b, positions.len() as f64));
+    }
+
+    let a_points: Vec<_> = points.iter().map(|&(x, a, _, w)| (x, a, w)).collect();
+    let b_points: Vec<_> = points.iter().map(|&(x, _, b, w)| (x, b, w)).collect();
+    match (fit_cubic(&a_points), fit_cubic(&b_points)) {
+        (Some(a), Some(b)) => {
+            println!("{} positions", samples.len());
+            println!("const AS: [f64; 4] = {};", format_coefficients(&a));
+            println!("const BS: [f64; 4] = {};", format_coefficients(&b));
+        }
+        _ => eprintln!("Error: Too few material counts with enough positions to fit"),
+    }
+}
+
+fn parse_samples(text: &str) -> Result<Vec<Sample>, String> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some(HEADER) {
+        return Err("Not a wdl data file".to_string());
+    }
+    let mut samples = Vec::new();
+    for (i, line) in lines.enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<i32> = line
+            .split_whitespace()
+            .map(|f| f.parse().ok())
+            .collect::<Option<_>>()
+            .filter(|f: &Vec<i32>| f.len() == 3 && (-1..=1).contains(&f[2]))
+            .ok_or_else(|| format!("Bad position on line {}", i + 2))?;
+        samples.push(Sample {
+            material: fields[0],
+            score: fields[1],
+            result: fields[2],
+        });
+    }
+    Ok(samples)
+}
+
+/// The `a` and `b` of the win rate `1 / (1 + exp((a - v) / b))` most likely
+/// to give the wins seen at each score. A position counts once as a win or
+/// not for its score `v`, and once more the same way for the loss at `-v`,
+/// as the model gives both chances with the one curve.
+fn fit_logistic(positions: &[(i32, i32)]) -> Option<(f64, f64)> {
+    // Logistic regression on t = v / 100, with p = 1 / (1 + exp(-(w t + c))),
+    // from p = 1/2 everywhere, where Newton's method can't overshoot
+    let mut w = 0.0;
+    let mut c = 0.0;
+    for _ in 0..50 {
+        let (mut g0, mut g1, mut h00, mut h01, mut h11) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for &(score, result) in positions {
+            for (v, won) in [(score, result == 1), (-score, result == -1)] {
+                let t = v as f64 / 100.0;
+                let p = 1.0 / (1.0 + (-(w * t + c)).exp());
+                let y = if won { 1.0 } else { 0.0 };
+                g0 += (y - p) * t;
+                g1 += y - p;
+                let s = p * (1.0 - p);
+                h00 += s * t * t;
+                h01 += s * t;
+                h11 += s;
+            }
+        }
+        let det = h00 * h11 - h01 * h01;
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let dw = (h11 * g0 - h01 * g1) / det;
+        let dc = (h00 * g1 - h01 * g0) / det;
+        w += dw;
+        c += dc;
+        if dw.abs() < 1e-9 && dc.abs() < 1e-9 {
+            break;
+        }
+    }
+    (w > 0.0 && w.is_finite() && c.is_finite()).then(|| (-c / w * 100.0, 100.0 / w))
+}
+
+/// Coefficients of the cubic `((c0 x + c1) x + c2) x + c3` closest to the
+/// `(x, y, weight)` points by weighted least squares.
+fn fit_cubic(points: &[(f64, f64, f64)]) -> Option<[f64; 4]> {
+    if points.len() < 4 {
+        return None;
+    }
+    // Normal equations, powers from x^3 down to x^0 as the coefficients are
+    let mut m = [[0.0; 5]; 4];
+    for &(x, y, weight) in points {
+        let powers = [x * x * x, x * x, x, 1.0];
+        for i in 0..4 {
+            for j in 0..4 {
+                m[i][j] += weight * powers[i] * powers[j];
+            }
+            m[i][4] += weight * powers[i] * y;
+        }
+    }
+    // Gaussian elimination with partial pivoting
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&i, &j| m[i][col].abs().total_cmp(&m[j][col].abs()))?;
+        m.swap(col, pivot);
+        if m[col][col].abs() < 1e-12 {
+            return None;
+        }
+        let pivot_row = m[col];
+        for (row, r) in m.iter_mut().enumerate() {
+            if row != col {
+                let f = r[col] / pivot_row[col];
+                for (v, p) in r.iter_mut().zip(pivot_row).skip(col) {
+                    *v -= f * p;
+                }
+            }
+        }
+    }
+    Some([0, 1, 2, 3].map(|i| m[i][4] / m[i][i]))
+}
+
+fn format_coefficients(c: &[f64; 4]) -> String {
+    format!("[{:.3}, {:.3}, {:.3}, {:.3}]", c[0], c[1], c[2], c[3])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_a_known_cubic() {
+        let cubic = |x: f64| ((2.0 * x - 3.0) * x + 0.5) * x + 7.0;
+        let points: Vec<_> = (0..10)
+            .map(|i| {
+                let x = 0.3 + i as f64 * 0.1;
+                (x, cubic(x), 1.0 + i as f64)
+            })
+            .collect();
+        let c = fit_cubic(&points).unwrap();
+        for (got, want) in c.iter().zip([2.0, -3.0, 0.5, 7.0]) {
+            assert!((got - want).abs() < 1e-6, "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn fits_the_curve_the_positions_follow() {
+        // Wins, draws and losses in the proportions the model gives for
+        // a = 100 and b = 150, counted out of 1000 at each score
+        let (a, b) = (100.0, 150.0);
+        let rate = |v: f64| 1.0 / (1.0 + ((a - v) / b).exp());
+        let mut positions = Vec::new();
+        for score in (-600..=600).step_by(50) {
+            let wins = (rate(score as f64) * 1000.0).round() as usize;
+            let losses = (rate(-score as f64) * 1000.0).round() as usize;
+            positions.extend(std::iter::repeat_n((score, 1), wins));
+            positions.extend(std::iter::repeat_n((score, -1), losses));
+            positions.extend(std::iter::repeat_n((score, 0), 1000 - wins - losses));
+        }
+        let (fit_a, fit_b) = fit_logistic(&positions).unwrap();
+        assert!((fit_a - a).abs() < 2.0, "a = {}", fit_a);
+        assert!((fit_b - b).abs() < 2.0, "b = {}", fit_b);
+    }
+
+    #[test]
+    fn reads_what_play_writes() {
+        let samples = parse_samples(&format!("{}\n58 35 1\n30 -120 -1\n\n", HEADER)).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(
+            (samples[1].material, samples[1].score, samples[1].result),
+            (30, -120, -1)
+        );
+        assert!(parse_samples("58 35 1\n").is_err());
+        assert!(parse_samples(&format!("{}\n58 35 2\n", HEADER)).is_err());
+    }
+}
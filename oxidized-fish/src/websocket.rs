@@ -0,0 +1,315 @@
+use std::io::{self, Read, Write};
+
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest message accepted from a client, far more than any UCI command.
+const MAX_MESSAGE: usize = 64 * 1024;
+/// Largest ping or close payload the protocol allows.
+const MAX_CONTROL: u64 = 125;
+
+pub const OP_CONTINUATION: u8 = 0x0;
+pub const OP_TEXT: u8 = 0x1;
+pub const OP_BINARY: u8 = 0x2;
+pub const OP_CLOSE: u8 = 0x8;
+pub const OP_PING: u8 = 0x9;
+pub const OP_PONG: u8 = 0xA;
+
+pub enum Message {
+    Text(String),
+    Ping(Vec<u8>), // To be answered with a pong carrying the same payload
+    Close,
+}
+
+/// The `Sec-WebSocket-Accept` reply to a client's `Sec-WebSocket-Key`.
+pub fn accept_key(client_key: &str) -> String {
+    let mut input = client_key.trim().as_bytes().to_vec();
+    input.extend_from_slice(HANDSHAKE_GUID.as_bytes());
+    base64_encode(&sha1(&input))
+}
+
+/// Reads frames until a whole text message, a ping or a close arrives.
+pub fn read_message(stream: &mut impl Read) -> io::Result<Message> {
+    let mut message = Vec::new();
+    loop {
+        let (fin, opcode, payload) = read_frame(stream)?;
+        match opcode {
+            OP_CLOSE => return Ok(Message::Close),
+            OP_PING => return Ok(Message::Ping(payload)),
+            OP_PONG => {}
+            OP_TEXT | OP_BINARY | OP_CONTINUATION => {
+                message.extend_from_slice(&payload);
+                if message.len() > MAX_MESSAGE {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "WebSocket message too large",
+                    ));
+                }
+                if fin {
+                    return Ok(Message::Text(
+                        String::from_utf8_lossy(&message).into_owned(),
+                    ));
+                }
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown WebSocket opcode",
+                ))
+            }
+        }
+    }
+}
+
+fn read_frame(stream: &mut impl Read) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header)?;
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+    let mut len = (header[1] & 0x7F) as u64;
+    if len == 126 {
+        let mut ext = [0u8; 2];
+        stream.read_exact(&mut ext)?;
+        len = u16::from_be_bytes(ext) as u64;
+    } else if len == 127 {
+        let mut ext = [0u8; 8];
+        stream.read_exact(&mut ext)?;
+        len = u64::from_be_bytes(ext);
+    }
+    if len > MAX_MESSAGE as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "WebSocket frame too large",
+        ));
+    }
+    // Control frames can't be fragmented and are short
+    if opcode & 0x8 != 0 && (len > MAX_CONTROL || !fin) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Bad WebSocket control frame",
+        ));
+    }
+
+    let mut mask = [0u8; 4];
+    if masked {
+        stream.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    stream.read_exact(&mut payload)?;
+    if masked {
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+    }
+    Ok((fin, opcode, payload))
+}
+
+/// Writes one unmasked frame, as servers do.
+pub fn write_frame(stream: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    if payload.len() < 126 {
+        frame.push(payload.len() as u8);
+    } else if payload.len() <= u16::MAX as usize {
+        frame.push(126);
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+pub fn write_text(stream: &mut impl Write, text: &str) -> io::Result<()> {
+    write_frame(stream, OP_TEXT, text.as_bytes())
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut msg = data.to_vec();
+    let bit_len = (data.len() as u64).wrapping_mul(8);
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&bit_len.to_be_bytes());
+
+    for chunk in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (hi, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *hi = hi.wrapping_add(v);
+        }
+    }
+
+    let mut out = [0u8; 20];
+    for (i, v) in h.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&v.to_be_bytes());
+    }
+    out
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        out.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        out.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        out.push(if chunk.len() > 1 {
+            ALPHABET[(n >> 6) as usize & 63] as char
+        } else {
+            '='
+        });
+        out.push(if chunk.len() > 2 {
+            ALPHABET[n as usize & 63] as char
+        } else {
+            '='
+        });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame as a client sends it, masked.
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xFA, 0x21, 0x3D];
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        if payload.len() < 126 {
+            frame.push(0x80 | payload.len() as u8);
+        } else {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    fn text(result: io::Result<Message>) -> String {
+        match result {
+            Ok(Message::Text(text)) => text,
+            _ => panic!("expected a text message"),
+        }
+    }
+
+    #[test]
+    fn accept_key_matches_the_rfc_example() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn unmasks_client_frames() {
+        let frame = client_frame(true, OP_TEXT, b"go depth 10");
+        assert_eq!(text(read_message(&mut &frame[..])), "go depth 10");
+    }
+
+    #[test]
+    fn reads_unmasked_frames() {
+        let mut frame = Vec::new();
+        write_text(&mut frame, "uci").unwrap();
+        assert_eq!(text(read_message(&mut &frame[..])), "uci");
+    }
+
+    #[test]
+    fn joins_fragmented_messages() {
+        let mut frames = client_frame(false, OP_TEXT, b"position ");
+        frames.extend(client_frame(false, OP_CONTINUATION, b"startpos "));
+        frames.extend(client_frame(true, OP_CONTINUATION, b"moves e2e4"));
+        assert_eq!(
+            text(read_message(&mut &frames[..])),
+            "position startpos moves e2e4"
+        );
+    }
+
+    #[test]
+    fn reads_extended_lengths() {
+        let long = "x".repeat(300);
+        let frame = client_frame(true, OP_TEXT, long.as_bytes());
+        assert_eq!(text(read_message(&mut &frame[..])), long);
+
+        let mut frame = Vec::new();
+        write_text(&mut frame, &long).unwrap();
+        assert_eq!(&frame[..4], &[0x81, 126, 1, 44]);
+    }
+
+    #[test]
+    fn reads_control_frames() {
+        let frame = client_frame(true, OP_PING, b"abc");
+        assert!(matches!(read_message(&mut &frame[..]), Ok(Message::Ping(p)) if p == b"abc"));
+        let frame = client_frame(true, OP_CLOSE, &[]);
+        assert!(matches!(read_message(&mut &frame[..]), Ok(Message::Close)));
+    }
+
+    #[test]
+    fn rejects_oversized_frames() {
+        let mut frame = vec![0x81, 0x80 | 127];
+        frame.extend_from_slice(&(MAX_MESSAGE as u64 + 1).to_be_bytes());
+        frame.extend_from_slice(&[0; 4]);
+        assert!(read_message(&mut &frame[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_messages() {
+        let chunk = vec![b'x'; MAX_MESSAGE / 2 + 1];
+        let mut frames = client_frame(false, OP_TEXT, &chunk);
+        frames.extend(client_frame(true, OP_CONTINUATION, &chunk));
+        assert!(read_message(&mut &frames[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_control_frames() {
+        let frame = client_frame(false, OP_PING, b"abc");
+        assert!(read_message(&mut &frame[..]).is_err());
+        let frame = client_frame(true, OP_PING, &[0; 126]);
+        assert!(read_message(&mut &frame[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_opcodes_and_truncated_frames() {
+        let frame = client_frame(true, 0x3, b"abc");
+        assert!(read_message(&mut &frame[..]).is_err());
+        let frame = client_frame(true, OP_TEXT, b"abcdef");
+        assert!(read_message(&mut &frame[..frame.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,371 @@
+use crate::board::Board;
+use crate::game::GameState;
+use crate::search::{Search, SearchInfo};
+use crate::types::{Color, Move};
+use crate::uci::{calculate_time_limit, parse_move};
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const MAX_DEPTH: u8 = 64;
+
+/// Thinking time when the GUI never sent a clock.
+const DEFAULT_MOVE_TIME: u64 = 5000;
+
+enum Event {
+    Input(String),
+    SearchDone(u64), // The id of the search
+}
+
+/// CECP state. The search runs on its own thread so that `?`, `exit` and
+/// friends can interrupt it; `searcher` is `None` while that thread owns it.
+struct XBoard {
+    game: GameState,
+    searcher: Option<Search>,
+    search_thread: Option<JoinHandle<(Search, Option<Move>)>>,
+    search_id: u64, // Of the latest search, so an aborted one's event is ignored
+    stop_flag: Arc<AtomicBool>,
+    events: Sender<Event>,
+    force: bool,
+    engine_color: Color,
+    analyzing: bool,
+    post: bool,
+    max_depth: u8,
+    move_time: Option<u64>, // st, in ms
+    moves_per_session: u32, // level, 0 for the whole game
+    increment: u64,         // ms
+    time_left: Option<u64>, // ms
+}
+
+pub fn start_xboard() {
+    let (tx, rx) = mpsc::channel();
+    let input = tx.clone();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if input.send(Event::Input(line)).is_err() {
+                break;
+            }
+        }
+        let _ = input.send(Event::Input("quit".to_string()));
+    });
+
+    let mut searcher = Search::new();
+    if let Err(e) = searcher.experience.load() {
+        eprintln!("Error: Failed to read experience file: {}", e);
+    }
+    xboard_loop(searcher, tx, rx);
+}
+
+fn xboard_loop(searcher: Search, tx: Sender<Event>, rx: mpsc::Receiver<Event>) {
+    let mut xb = XBoard {
+        game: GameState::new(Board::new()),
+        stop_flag: searcher.stop_flag.clone(),
+        searcher: Some(searcher),
+        search_thread: None,
+        search_id: 0,
+        events: tx,
+        force: false,
+        engine_color: Color::Black,
+        analyzing: false,
+        post: false,
+        max_depth: MAX_DEPTH,
+        move_time: None,
+        moves_per_session: 0,
+        increment: 0,
+        time_left: None,
+    };
+
+    while let Ok(event) = rx.recv() {
+        match event {
+            Event::Input(line) => {
+                if !xb.handle(&line) {
+                    break;
+                }
+            }
+            Event::SearchDone(id) => xb.search_done(id),
+        }
+    }
+}
+
+impl XBoard {
+    fn handle(&mut self, line: &str) -> bool {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        if args.is_empty() {
+            return true;
+        }
+
+        match args[0] {
+            "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer"
+            | "name" | "rating" | "draw" | "." => {}
+            "protover" => {
+                println!(
+                    "feature myname=\"Oxidized Fish 0.7-Hardened\" setboard=1 usermove=1 \
+                     analyze=1 ping=1 playother=1 colors=0 sigint=0 sigterm=0 reuse=1"
+                );
+                println!("feature option=\"Experience -check 0\" done=1");
+            }
+            "ping" => println!("pong {}", args.get(1).unwrap_or(&"")),
+            "option" => {
+                self.abort_search();
+                let option = args[1..].join(" ");
+                if let (Some(("Experience", value)), Some(searcher)) =
+                    (option.split_once('='), self.searcher.as_mut())
+                {
+                    searcher.experience.enabled = value == "1";
+                }
+            }
+            "new" => {
+                self.abort_search();
+                self.end_game();
+                self.game = GameState::new(Board::new());
+                self.force = false;
+                self.engine_color = Color::Black;
+                self.max_depth = MAX_DEPTH;
+                self.move_time = None;
+                if let Some(searcher) = self.searcher.as_mut() {
+                    searcher.clear_tt();
+                }
+                self.restart_analysis();
+            }
+            "force" => {
+                self.abort_search();
+                self.force = true;
+            }
+            "go" => {
+                self.abort_search();
+                self.force = false;
+                self.engine_color = self.game.board.side_to_move;
+                self.start_search();
+            }
+            "playother" => {
+                self.abort_search();
+                self.force = false;
+                self.engine_color = self.game.board.side_to_move.opponent();
+            }
+            "usermove" => {
+                if let Some(m_str) = args.get(1) {
+                    self.user_move(m_str);
+                }
+            }
+            "?" => self.stop_flag.store(true, Ordering::Relaxed),
+            "level" => {
+                if args.len() >= 4 {
+                    self.moves_per_session = args[1].parse().unwrap_or(0);
+                    self.increment = (args[3].parse::<f64>().unwrap_or(0.0) * 1000.0) as u64;
+                    self.move_time = None;
+                }
+            }
+            "st" => {
+                if let Some(Ok(secs)) = args.get(1).map(|s| s.parse::<f64>()) {
+                    self.move_time = Some((secs * 1000.0) as u64);
+                }
+            }
+            "sd" => {
+                if let Some(Ok(depth)) = args.get(1).map(|s| s.parse::<u8>()) {
+                    self.max_depth = depth.clamp(1, MAX_DEPTH);
+                }
+            }
+            "time" => {
+                // Centiseconds
+                if let Some(Ok(cs)) = args.get(1).map(|s| s.parse::<u64>()) {
+                    self.time_left = Some(cs * 10);
+                }
+            }
+            "otim" => {}
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "analyze" => {
+                self.abort_search();
+                self.analyzing = true;
+                self.start_search();
+            }
+            "exit" => {
+                self.abort_search();
+                self.analyzing = false;
+            }
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            "setboard" => {
+                self.abort_search();
+                let fen = args[1..].join(" ");
+                self.game = GameState::new(Board::from_fen(&fen));
+                self.restart_analysis();
+            }
+            "result" => {
+                self.abort_search();
+                self.end_game();
+                self.force = true;
+            }
+            "quit" => {
+                self.abort_search();
+                self.end_game();
+                return false;
+            }
+            _ => {
+                // Without the usermove prefix a move is sent bare
+                if parse_move(&self.game.board, args[0]).is_some() {
+                    self.user_move(args[0]);
+                } else {
+                    println!("Error (unknown command): {}", args[0]);
+                }
+            }
+        }
+        true
+    }
+
+    fn user_move(&mut self, m_str: &str) {
+        self.abort_search();
+        let Some(m) = parse_move(&self.game.board, m_str) else {
+            println!("Illegal move: {}", m_str);
+            self.restart_analysis();
+            return;
+        };
+        self.game.make_move(m);
+        if self.report_result() {
+            return;
+        }
+        let engine_to_move = !self.force && self.game.board.side_to_move == self.engine_color;
+        if self.analyzing || engine_to_move {
+            self.start_search();
+        }
+    }
+
+    fn take_back(&mut self, plies: usize) {
+        self.abort_search();
+        for _ in 0..plies {
+            self.game.undo();
+        }
+        self.restart_analysis();
+    }
+
+    fn restart_analysis(&mut self) {
+        if self.analyzing {
+            self.start_search();
+        }
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        if let Some(st) = self.move_time {
+            return Some(Duration::from_millis(st));
+        }
+        let Some(time) = self.time_left else {
+            return Some(Duration::from_millis(DEFAULT_MOVE_TIME));
+        };
+        if self.moves_per_session > 0 {
+            let played = (self.game.moves().len() / 2) as u32;
+            let to_go = (self.moves_per_session - played % self.moves_per_session) as u64;
+            let allocated = (time / to_go + self.increment * 3 / 4)
+                .min(time / 2)
+                .max(10);
+            return Some(Duration::from_millis(allocated));
+        }
+        calculate_time_limit(
+            self.game.board.side_to_move,
+            Some(time),
+            Some(time),
+            self.increment,
+            self.increment,
+            None,
+        )
+    }
+
+    fn start_search(&mut self) {
+        let Some(mut searcher) = self.searcher.take() else {
+            return;
+        };
+        let post = self.post || self.analyzing;
+        searcher.on_info = Some(Box::new(move |info| {
+            if post {
+                println!("{}", format_thinking(info));
+            }
+        }));
+
+        let game = self.game.clone();
+        let (depth, time_limit) = if self.analyzing {
+            (MAX_DEPTH, None)
+        } else {
+            (self.max_depth, self.time_limit())
+        };
+        let events = self.events.clone();
+        self.search_id += 1;
+        let id = self.search_id;
+        self.stop_flag.store(false, Ordering::Relaxed);
+        self.search_thread = Some(thread::spawn(move || {
+            let m = searcher.go(&game, depth, time_limit);
+            let _ = events.send(Event::SearchDone(id));
+            (searcher, m)
+        }));
+    }
+
+    fn join_search(&mut self) -> Option<Move> {
+        let handle = self.search_thread.take()?;
+        let (searcher, m) = handle.join().expect("Search thread panicked");
+        self.searcher = Some(searcher);
+        m
+    }
+
+    /// Stops a running search and throws its move away.
+    fn abort_search(&mut self) {
+        if self.search_thread.is_some() {
+            self.stop_flag.store(true, Ordering::Relaxed);
+            self.join_search();
+        }
+    }
+
+    fn search_done(&mut self, id: u64) {
+        if id != self.search_id || self.search_thread.is_none() {
+            return; // From a search that was already aborted
+        }
+        let m = self.join_search();
+        if self.analyzing {
+            return;
+        }
+        if let Some(m) = m {
+            self.game.make_move(m);
+            println!("move {}", m.to_string());
+        }
+        self.report_result();
+    }
+
+    /// Prints the result if the game is over by the rules.
+    fn report_result(&mut self) -> bool {
+        match self.game.result() {
+            Some(result) => {
+                println!("{} {{{}}}", result.to_pgn(), result.reason());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn end_game(&mut self) {
+        if let Some(searcher) = self.searcher.as_mut() {
+            if let Err(e) = searcher.experience.end_game() {
+                eprintln!("Error: Failed to write experience file: {}", e);
+            }
+        }
+    }
+}
+
+/// Thinking output: ply, score, time in centiseconds, nodes and the PV.
+/// Mates are 100000 plus the number of moves.
+fn format_thinking(info: &SearchInfo) -> String {
+    let score = match info.mate_in() {
+        Some(n) if n > 0 => 100000 + n,
+        Some(n) => -100000 + n,
+        None => info.score,
+    };
+    let pv: Vec<String> = info.pv.iter().map(|m| m.to_string()).collect();
+    format!(
+        "{} {} {} {} {}",
+        info.depth,
+        score,
+        info.time_ms / 10,
+        info.nodes,
+        pv.join(" ")
+    )
+}
@@ -1,7 +1,66 @@
 import { Chess } from 'https://cdn.jsdelivr.net/npm/chess.js@1.4.0/dist/esm/chess.js';
 
+// ===== Engine Connection =====
+// The engine's `serve` mode speaks UCI text over a plain WebSocket at /ws.
+// With `?engine=wasm` the WebAssembly build runs in a worker instead, so
+// the page works from any static host. Both keep the small event API the
+// rest of the app was written against.
+const ENGINE_IN_BROWSER = new URLSearchParams(location.search).get('engine') === 'wasm';
+
+function createEngineSocket() {
+    const handlers = {};
+    const queue = [];
+    let ws = null;
+    let worker = null;
+
+    const fire = (event, data) => (handlers[event] || []).forEach(fn => fn(data));
+    const receive = (text) => {
+        text.split('\n').forEach(line => {
+            const trimmed = line.trim();
+            if (trimmed) fire('engine-response', trimmed);
+        });
+    };
+
+    function connect() {
+        const protocol = location.protocol === 'https:' ? 'wss:' : 'ws:';
+        ws = new WebSocket(`${protocol}//${location.host}/ws`);
+        ws.onopen = () => {
+            fire('connect');
+            while (queue.length) ws.send(queue.shift());
+        };
+        ws.onmessage = (event) => receive(event.data);
+        ws.onerror = (err) => fire('error', err);
+        ws.onclose = () => {
+            fire('disconnect');
+            setTimeout(connect, 2000);
+        };
+    }
+
+    if (ENGINE_IN_BROWSER) {
+        worker = new Worker('engine-worker.js', { type: 'module' });
+        worker.onmessage = (event) => receive(event.data);
+        worker.onerror = (err) => fire('error', err);
+        // Handlers are registered after this returns
+        setTimeout(() => fire('connect'), 0);
+    } else {
+        connect();
+    }
+
+    return {
+        on(event, fn) {
+            (handlers[event] = handlers[event] || []).push(fn);
+        },
+        emit(event, data) {
+            if (event !== 'engine-command') return;
+            if (worker) worker.postMessage(data);
+            else if (ws.readyState === WebSocket.OPEN) ws.send(data);
+            else queue.push(data);
+        }
+    };
+}
+
 // ===== Chess Application State =====
-const socket = io();
+const socket = createEngineSocket();
 
 // Game State
 let game = new Chess();
@@ -77,7 +136,6 @@ function init() {
     
     socket.on('connect', () => {
         updateEngineStatus('active', 'Engine Ready');
-        socket.emit('start-engine');
     });
 
     socket.on('disconnect', () => {
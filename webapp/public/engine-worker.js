@@ -0,0 +1,89 @@
+// Runs the WebAssembly build of the engine off the main thread, answering
+// the same UCI lines as the server's /ws endpoint. Build it with
+//   cargo build --release --lib --target wasm32-unknown-unknown
+// and copy target/wasm32-unknown-unknown/release/oxidized_fish.wasm here.
+//
+// A search can't be interrupted while it runs, so `stop` does nothing and
+// every `go` needs a depth, movetime or nodes limit (movetime is counted in
+// nodes, since the browser build has no clock).
+
+const START_FEN = 'rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1';
+const DEFAULT_MOVETIME = 1000;
+
+const encoder = new TextEncoder();
+const decoder = new TextDecoder();
+let engine = null;
+let fen = START_FEN;
+
+const ready = WebAssembly.instantiateStreaming(fetch('oxidized_fish.wasm'), {
+    env: {
+        on_info: (ptr, len) => postMessage(readString(ptr, len)),
+    },
+}).then(({ instance }) => {
+    engine = instance.exports;
+}).catch((err) => {
+    postMessage(`info string Failed to load oxidized_fish.wasm: ${err}`);
+});
+
+function readString(ptr, len) {
+    return decoder.decode(new Uint8Array(engine.memory.buffer, ptr, len));
+}
+
+function bestmove(position, depth, movetime, nodes) {
+    const bytes = encoder.encode(position);
+    const ptr = engine.alloc(bytes.length);
+    new Uint8Array(engine.memory.buffer, ptr, bytes.length).set(bytes);
+    const len = engine.bestmove(ptr, bytes.length, depth, movetime, nodes);
+    engine.dealloc(ptr, bytes.length);
+    return readString(engine.output_ptr(), len);
+}
+
+function go(args) {
+    const limit = (name) => {
+        const i = args.indexOf(name);
+        return i >= 0 ? parseInt(args[i + 1]) || 0 : 0;
+    };
+    const depth = limit('depth');
+    const nodes = limit('nodes');
+    let movetime = limit('movetime');
+    if (!depth && !nodes && !movetime) movetime = DEFAULT_MOVETIME;
+    postMessage(`bestmove ${bestmove(fen, depth, movetime, nodes)}`);
+}
+
+function handle(line) {
+    const args = line.trim().split(/\s+/);
+    switch (args[0]) {
+        case 'uci':
+            postMessage('id name Oxidized Fish 0.7-Hardened\nuciok');
+            break;
+        case 'isready':
+            postMessage('readyok');
+            break;
+        case 'ucinewgame':
+            fen = START_FEN;
+            engine.new_game();
+            break;
+        case 'position': {
+            const movesAt = args.indexOf('moves');
+            if (movesAt >= 0) {
+                postMessage('info string position moves are not supported, send a FEN');
+            }
+            const end = movesAt >= 0 ? movesAt : args.length;
+            fen = args[1] === 'fen' ? args.slice(2, end).join(' ') : START_FEN;
+            break;
+        }
+        case 'go':
+            go(args);
+            break;
+        case 'stop':
+            break;
+        default:
+            if (args[0]) postMessage(`info string Unknown command: ${args[0]}`);
+    }
+}
+
+onmessage = async (event) => {
+    await ready;
+    if (!engine) return;
+    event.data.split('\n').forEach(handle);
+};